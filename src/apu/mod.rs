@@ -0,0 +1,319 @@
+mod dmc;
+mod envelope;
+mod noise;
+mod pulse;
+mod triangle;
+
+use dmc::Dmc;
+use noise::Noise;
+use pulse::Pulse;
+use triangle::Triangle;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, //
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// NTSC frame counter steps in CPU cycles
+const QUARTER_FRAME_STEPS: [usize; 3] = [7457, 14913, 22371];
+const FOUR_STEP_END: usize = 29829;
+const FIVE_STEP_END: usize = 37281;
+
+// 2A03 audio processing unit: two pulse channels, triangle, noise, DMC and
+// the frame counter, clocked once per CPU cycle.
+pub struct APU {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    frame_cycle: usize,
+    odd_cycle: bool,
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl APU {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_cycle: 0,
+            odd_cycle: false,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr & 0x03, data),
+            0x4004..=0x4007 => self.pulse2.write(addr & 0x03, data),
+            0x4008..=0x400B => self.triangle.write(addr & 0x03, data),
+            0x400C..=0x400F => self.noise.write(addr & 0x03, data),
+            0x4010..=0x4013 => self.dmc.write(addr & 0x03, data),
+            0x4015 => {
+                self.pulse1.set_enabled(data & 0x01 != 0);
+                self.pulse2.set_enabled(data & 0x02 != 0);
+                self.triangle.set_enabled(data & 0x04 != 0);
+                self.noise.set_enabled(data & 0x08 != 0);
+                self.dmc.set_enabled(data & 0x10 != 0);
+            }
+            0x4017 => {
+                self.five_step = data & 0x80 != 0;
+                self.irq_inhibit = data & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // $4015 reads report the length counters and both IRQ sources, and
+    // acknowledge the frame IRQ.
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        status |= (self.pulse1.length > 0) as u8;
+        status |= ((self.pulse2.length > 0) as u8) << 1;
+        status |= ((self.triangle.length > 0) as u8) << 2;
+        status |= ((self.noise.length > 0) as u8) << 3;
+        status |= ((self.dmc.bytes_remaining > 0) as u8) << 4;
+        status |= (self.frame_irq as u8) << 6;
+        status |= (self.dmc.irq as u8) << 7;
+
+        self.frame_irq = false;
+        status
+    }
+
+    pub fn tick(&mut self) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+
+        self.frame_cycle += 1;
+        self.clock_frame_counter();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        let end = if self.five_step {
+            FIVE_STEP_END
+        } else {
+            FOUR_STEP_END
+        };
+
+        if QUARTER_FRAME_STEPS.contains(&self.frame_cycle) {
+            self.clock_quarter_frame();
+            if self.frame_cycle == QUARTER_FRAME_STEPS[1] {
+                self.clock_half_frame();
+            }
+        } else if self.frame_cycle == end {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+            if !self.five_step && !self.irq_inhibit {
+                self.frame_irq = true;
+            }
+            self.frame_cycle = 0;
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_length();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_length();
+        self.pulse2.clock_sweep();
+        self.triangle.clock_length();
+        self.noise.clock_length();
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    // Address of the next DMC sample byte, if the channel is waiting for one
+    pub fn dmc_fetch_addr(&self) -> Option<u16> {
+        self.dmc.fetch_addr()
+    }
+
+    pub fn dmc_fill(&mut self, data: u8) {
+        self.dmc.fill(data);
+    }
+
+    // Non-linear mix of the five channels, in 0.0..=1.0
+    pub fn output(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.output() as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_out + tnd_out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // CPU cycles per DMC output bit at the slowest rate, which $4010
+    // selects with a zero rate index
+    const DMC_BIT_CYCLES: usize = 428;
+
+    fn run(apu: &mut APU, cycles: usize) {
+        for _ in 0..cycles {
+            apu.tick();
+        }
+    }
+
+    #[test]
+    fn test_length_counters() {
+        let mut apu = APU::new();
+
+        // Loads are ignored while a channel is disabled
+        apu.write_register(0x4003, 0x08);
+        assert_eq!(apu.read_status() & 0x0F, 0x00);
+
+        apu.write_register(0x4015, 0x0F);
+        // Lengths of 2 for the first pulse, 254 for the others
+        apu.write_register(0x4003, 0x18);
+        apu.write_register(0x4007, 0x08);
+        apu.write_register(0x400B, 0x08);
+        apu.write_register(0x400F, 0x08);
+        assert_eq!(apu.read_status() & 0x0F, 0x0F);
+
+        // Clocked on the two half frames of the four-step sequence
+        run(&mut apu, QUARTER_FRAME_STEPS[1] - 1);
+        assert_eq!(apu.pulse1.length, 2);
+        run(&mut apu, 1);
+        assert_eq!(apu.pulse1.length, 1);
+        run(&mut apu, FOUR_STEP_END - QUARTER_FRAME_STEPS[1]);
+        assert_eq!(apu.pulse1.length, 0);
+        assert_eq!(apu.read_status() & 0x0F, 0x0E);
+
+        // Halted counters hold
+        apu.write_register(0x4004, 0x20);
+        run(&mut apu, FOUR_STEP_END);
+        assert_eq!(apu.pulse2.length, 252);
+        assert_eq!(apu.triangle.length, 250);
+
+        // Disabling a channel clears its counter
+        apu.write_register(0x4015, 0x02);
+        assert_eq!(apu.read_status() & 0x0F, 0x02);
+    }
+
+    #[test]
+    fn test_frame_irq() {
+        let mut apu = APU::new();
+        run(&mut apu, FOUR_STEP_END - 1);
+        assert!(!apu.irq_pending());
+        run(&mut apu, 1);
+        assert!(apu.irq_pending());
+
+        // Reading $4015 reports and acknowledges it
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.irq_pending());
+        assert_eq!(apu.read_status() & 0x40, 0x00);
+
+        // Raised again a full sequence later
+        run(&mut apu, FOUR_STEP_END);
+        assert!(apu.irq_pending());
+
+        // Setting the inhibit flag clears it and stops new ones
+        apu.write_register(0x4017, 0x40);
+        assert!(!apu.irq_pending());
+        run(&mut apu, FOUR_STEP_END * 2);
+        assert!(!apu.irq_pending());
+
+        // The five-step sequence never raises it
+        apu.write_register(0x4017, 0x80);
+        run(&mut apu, FIVE_STEP_END * 2);
+        assert!(!apu.irq_pending());
+    }
+
+    #[test]
+    fn test_dmc_fetch_and_irq() {
+        let mut apu = APU::new();
+        // No frame IRQs to confuse with the DMC's
+        apu.write_register(0x4017, 0x40);
+        // IRQ on, sample at $C040, 17 bytes long
+        apu.write_register(0x4010, 0x80);
+        apu.write_register(0x4012, 0x01);
+        apu.write_register(0x4013, 0x01);
+        assert_eq!(apu.dmc_fetch_addr(), None);
+
+        apu.write_register(0x4015, 0x10);
+        assert_eq!(apu.read_status() & 0x10, 0x10);
+        for offset in 0..17 {
+            assert_eq!(apu.dmc_fetch_addr(), Some(0xC040 + offset));
+            apu.dmc_fill(0x00);
+            assert_eq!(apu.dmc_fetch_addr(), None);
+            assert_eq!(apu.irq_pending(), offset == 16);
+            // The buffer empties once the output unit takes the byte
+            run(&mut apu, DMC_BIT_CYCLES * 8);
+        }
+
+        assert_eq!(apu.dmc_fetch_addr(), None);
+        assert_eq!(apu.read_status() & 0x90, 0x80);
+        // Writing $4015 acknowledges it
+        apu.write_register(0x4015, 0x00);
+        assert!(!apu.irq_pending());
+
+        // A looping sample restarts instead, wrapping from $FFFF to $8000
+        apu.write_register(0x4010, 0x40);
+        // 65 bytes from $FFC0
+        apu.write_register(0x4012, 0xFF);
+        apu.write_register(0x4013, 0x04);
+        apu.write_register(0x4015, 0x10);
+        for _ in 0..0x40 {
+            apu.dmc_fill(0x00);
+            run(&mut apu, DMC_BIT_CYCLES * 8);
+        }
+        assert_eq!(apu.dmc_fetch_addr(), Some(0x8000));
+        apu.dmc_fill(0x00);
+        assert_eq!(apu.dmc_fetch_addr(), None);
+        run(&mut apu, DMC_BIT_CYCLES * 8);
+        assert_eq!(apu.dmc_fetch_addr(), Some(0xFFC0));
+        assert!(!apu.irq_pending());
+    }
+}
@@ -0,0 +1,103 @@
+use nes_emulator::testrom::{Manifest, Method, MODES};
+use std::{env, process, thread};
+
+const DEFAULT_MANIFEST: &str = "roms/accuracy.txt";
+const MODE_NAMES: [&str; MODES.len()] = ["instr", "cycle"];
+
+// Runs every test ROM in an accuracy manifest, in parallel and in each CPU
+// mode, and prints a pass/fail matrix followed by the failures.
+//
+//   accuracy [manifest] [jobs]
+//
+// The manifest defaults to roms/accuracy.txt and jobs to the number of
+// CPUs. ROMs that are not on disk are listed and skipped. Exits with 0
+// when every test that ran gave its expected result.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let path = args.get(1).map_or(DEFAULT_MANIFEST, String::as_str);
+    let mut manifest = match Manifest::load(path) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            process::exit(2);
+        }
+    };
+    let jobs = match args.get(2) {
+        Some(jobs) => jobs.parse().expect("jobs must be a number"),
+        None => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+    };
+
+    // Suites that are fetched separately may not be there
+    let (present, missing): (Vec<_>, Vec<_>) = manifest
+        .entries
+        .into_iter()
+        .partition(|entry| entry.rom.exists());
+    manifest.entries = present;
+
+    let results = manifest.run(jobs);
+
+    let width = manifest
+        .entries
+        .iter()
+        .map(|entry| entry.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(3);
+    let mut header = format!("{:width$}  method", "rom");
+    for mode in MODE_NAMES {
+        header += &format!("  {mode:5}");
+    }
+    println!("{}", header.trim_end());
+
+    let mut passed = [0; MODES.len()];
+    let mut failures = vec![];
+    for (entry, reports) in manifest.entries.iter().zip(&results) {
+        let method = match entry.method {
+            Method::Status(_) => "status",
+            Method::Log(_) => "log",
+            Method::ScreenHash(_) => "hash",
+        };
+        let mut row = format!("{:width$}  {method:6}", entry.name);
+
+        for (mode, report) in reports.iter().enumerate() {
+            let cell = if entry.expects(report.outcome) {
+                passed[mode] += 1;
+                "pass"
+            } else {
+                failures.push((entry, mode, report));
+                "FAIL"
+            };
+            row += &format!("  {cell:5}");
+        }
+        println!("{}", row.trim_end());
+    }
+
+    for (entry, mode, report) in &failures {
+        println!();
+        println!("{} ({}): {}", entry.name, MODE_NAMES[*mode], report.outcome);
+        for line in report.message.lines() {
+            println!("    {line}");
+        }
+    }
+
+    if !missing.is_empty() {
+        println!();
+        println!("skipped {} missing ROMs:", missing.len());
+        for entry in &missing {
+            println!("    {}", entry.name);
+        }
+    }
+
+    println!();
+    for (mode, name) in MODE_NAMES.iter().enumerate() {
+        let total = manifest.entries.len();
+        let percent = match total {
+            0 => 100.0,
+            total => passed[mode] as f64 * 100.0 / total as f64,
+        };
+        println!("{name}: {} of {total} passed ({percent:.1}%)", passed[mode]);
+    }
+
+    process::exit(if failures.is_empty() { 0 } else { 1 });
+}
@@ -0,0 +1,294 @@
+use nes_emulator::{
+    bus::watch::{Space, Watchpoint},
+    cpu::{
+        cpu::{trace_with_timing, Mem},
+        CPU,
+    },
+    debugger::{parse_number, Debugger, Expr, Stop},
+};
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    process,
+};
+
+const HELP: &str = "\
+s, step [n]            run one instruction, or n, entering subroutines
+n, next                run one instruction, a whole subroutine for JSR
+o, out                 run until the current subroutine returns
+c, continue            run until a breakpoint or a BRK
+u, until <addr>        run until the instruction at addr is next
+f, frame [n]           run until n frames (1 by default) have been drawn
+l, scanline <line>     run until the PPU starts the scanline
+b, break <addr> [if <expr>]
+                       stop at addr, when expr is non-zero if given
+d, delete <id>         remove a breakpoint
+e, enable <id>         turn a breakpoint back on
+disable <id>           turn a breakpoint off without removing it
+bl, breaks             list breakpoints
+w, watch [ppu] <addr>[-<end>] [r|w|rw] [log]
+                       report reads and/or writes (both by default) of
+                       CPU or PPU memory, mirrors included, and stop after
+                       the instruction unless log is given
+dw <id>                remove a watchpoint
+wl, watches            list watchpoints
+bt, backtrace          list the subroutines entered and not returned from
+r, regs                show the next instruction and the registers
+x <addr> [len]         dump memory, 16 bytes by default
+set <reg> <value>      change A, X, Y, P, SP or PC
+p, print <expr>        evaluate an expression
+reset                  press reset
+q, quit                leave
+
+Addresses and expressions take $C000, 0xC000 or decimal. Expressions use
+A X Y P SP PC, the flags C Z I D V N, [addr] for a byte of memory, and C
+operators. An empty line repeats the last command.";
+
+// An interactive debugger for a ROM, in the terminal.
+//
+//   debug <rom.nes>
+//
+// Type `help` at the prompt for the commands.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <rom.nes>", args[0]);
+        process::exit(2);
+    }
+
+    let mut cpu = match CPU::load_rom_file(&args[1]) {
+        Ok(cpu) => cpu,
+        Err(err) => {
+            eprintln!("{}: {err}", args[1]);
+            process::exit(2);
+        }
+    };
+    cpu.reset();
+    let mut debugger = Debugger::new(cpu);
+
+    println!("{}", trace_with_timing(&mut debugger.cpu));
+    let mut last = String::new();
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        let line = match line.trim() {
+            "" => last.clone(),
+            line => line.to_string(),
+        };
+        last.clone_from(&line);
+
+        match command(&mut debugger, &line) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(err) => println!("{err}"),
+        }
+    }
+}
+
+// Runs one command line; false means quit
+fn command(debugger: &mut Debugger, line: &str) -> Result<bool, String> {
+    let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let rest = rest.trim();
+
+    let stop = match name {
+        "s" | "step" => {
+            let count = match rest {
+                "" => 1,
+                count => number(count)?,
+            };
+            let mut stop = Stop::Done;
+            for _ in 0..count {
+                stop = debugger.step_into();
+                if stop != Stop::Done {
+                    break;
+                }
+            }
+            stop
+        }
+        "n" | "next" => debugger.step_over(),
+        "o" | "out" => debugger.step_out(),
+        "c" | "continue" => debugger.resume(),
+        "u" | "until" => debugger.run_to(address(rest)?),
+        "f" | "frame" => debugger.run_frames(match rest {
+            "" => 1,
+            frames => number(frames)?,
+        }),
+        "l" | "scanline" => debugger.run_to_scanline(number(rest)? as u16),
+        "b" | "break" => {
+            let (addr, condition) = match rest.split_once(" if ") {
+                Some((addr, condition)) => (addr, Some(condition.trim())),
+                None => (rest, None),
+            };
+            let id = debugger.add_breakpoint(address(addr)?, condition)?;
+            println!("breakpoint {id} at ${:04X}", address(addr)?);
+            return Ok(true);
+        }
+        "d" | "delete" => {
+            if !debugger.remove_breakpoint(number(rest)?) {
+                return Err(format!("no breakpoint {rest}"));
+            }
+            return Ok(true);
+        }
+        "e" | "enable" | "disable" => {
+            let breakpoint = debugger
+                .breakpoint_mut(number(rest)?)
+                .ok_or_else(|| format!("no breakpoint {rest}"))?;
+            breakpoint.enabled = name != "disable";
+            return Ok(true);
+        }
+        "bl" | "breaks" => {
+            for (id, breakpoint) in debugger.breakpoints() {
+                let mut line = format!("{id:3}  ${:04X}", breakpoint.addr);
+                if !breakpoint.source.is_empty() {
+                    line += &format!(" if {}", breakpoint.source);
+                }
+                if !breakpoint.enabled {
+                    line += "  (disabled)";
+                }
+                println!("{line}  hit {} times", breakpoint.hits);
+            }
+            return Ok(true);
+        }
+        "w" | "watch" => {
+            let mut words: Vec<&str> = rest.split_whitespace().collect();
+            let mut flag = |name: &str| match words.iter().position(|&word| word == name) {
+                Some(idx) => {
+                    words.remove(idx);
+                    true
+                }
+                None => false,
+            };
+            let space = match flag("ppu") {
+                true => Space::Ppu,
+                false => Space::Cpu,
+            };
+            let log = flag("log");
+            let (read, write) = match (flag("r"), flag("w"), flag("rw")) {
+                (true, false, false) => (true, false),
+                (false, true, false) => (false, true),
+                _ => (true, true),
+            };
+            let [range] = words[..] else {
+                return Err("usage: watch [ppu] <addr>[-<end>] [r|w|rw] [log]".to_string());
+            };
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+
+            let mut watchpoint =
+                Watchpoint::new(space, address(start)?..=address(end)?, read, write);
+            watchpoint.break_on_hit = !log;
+            let id = debugger.cpu.bus.watchpoints().add(watchpoint);
+            println!("watchpoint {id} on {range}");
+            return Ok(true);
+        }
+        "dw" => {
+            if !debugger.cpu.bus.watchpoints().remove(number(rest)?) {
+                return Err(format!("no watchpoint {rest}"));
+            }
+            return Ok(true);
+        }
+        "wl" | "watches" => {
+            for (id, watchpoint) in debugger.cpu.bus.watchpoints().iter() {
+                let space = match watchpoint.space {
+                    Space::Cpu => "",
+                    Space::Ppu => "PPU ",
+                };
+                let access = match (watchpoint.read, watchpoint.write) {
+                    (true, true) => "rw",
+                    (true, false) => "r",
+                    _ => "w",
+                };
+                let action = match watchpoint.break_on_hit {
+                    true => "break",
+                    false => "log",
+                };
+                println!(
+                    "{id:3}  {space}${:04X}-${:04X} {access} {action}  hit {} times",
+                    watchpoint.range.start(),
+                    watchpoint.range.end(),
+                    watchpoint.hits
+                );
+            }
+            return Ok(true);
+        }
+        "bt" | "backtrace" => {
+            for call in debugger.calls().iter().rev() {
+                println!("${:04X} called from ${:04X}", call.to, call.from);
+            }
+            return Ok(true);
+        }
+        "r" | "regs" => Stop::Done,
+        "x" => {
+            let (addr, len) = rest.split_once(' ').unwrap_or((rest, "16"));
+            let (addr, len) = (address(addr)?, number(len.trim())?);
+            for row in (0..len).step_by(16) {
+                let mut line = format!("{:04X} ", addr.wrapping_add(row as u16));
+                for offset in row..(row + 16).min(len) {
+                    let byte = debugger.cpu.bus.peek(addr.wrapping_add(offset as u16));
+                    line += &format!(" {byte:02X}");
+                }
+                println!("{line}");
+            }
+            return Ok(true);
+        }
+        "set" => {
+            let (register, value) = rest.split_once(' ').ok_or("usage: set <reg> <value>")?;
+            let value = number(value.trim())?;
+            let cpu = &mut debugger.cpu;
+            match register.to_ascii_uppercase().as_str() {
+                "A" => cpu.register_a = value as u8,
+                "X" => cpu.register_x = value as u8,
+                "Y" => cpu.register_y = value as u8,
+                "P" => cpu.flags = value as u8,
+                "SP" => cpu.stack_pointer = value as u8,
+                "PC" => cpu.program_counter = value as u16,
+                _ => return Err(format!("unknown register {register}")),
+            }
+            Stop::Done
+        }
+        "p" | "print" => {
+            let value = Expr::parse(rest)?.eval(&mut debugger.cpu);
+            println!("{value} (${value:X})");
+            return Ok(true);
+        }
+        "reset" => {
+            debugger.reset();
+            Stop::Done
+        }
+        "q" | "quit" => return Ok(false),
+        "h" | "help" => {
+            println!("{HELP}");
+            return Ok(true);
+        }
+        _ => return Err(format!("unknown command {name}, try help")),
+    };
+
+    for hit in debugger.cpu.bus.watchpoints().take_hits() {
+        println!("{hit}");
+    }
+    match stop {
+        // Printed with the other hits
+        Stop::Done | Stop::Watchpoint(_) => (),
+        Stop::Breakpoint(id) => println!("breakpoint {id}"),
+        Stop::Halted(addr) => println!("halted at ${addr:04X}"),
+    }
+    println!("{}", trace_with_timing(&mut debugger.cpu));
+    Ok(true)
+}
+
+fn number(text: &str) -> Result<usize, String> {
+    parse_number(text)
+        .and_then(|value| usize::try_from(value).ok())
+        .ok_or_else(|| format!("bad number {text}"))
+}
+
+fn address(text: &str) -> Result<u16, String> {
+    parse_number(text)
+        .and_then(|value| u16::try_from(value).ok())
+        .ok_or_else(|| format!("bad address {text}"))
+}
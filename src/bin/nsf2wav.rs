@@ -0,0 +1,77 @@
+use nes_emulator::{
+    cartridge::read_image,
+    nsf::{write_wav, ExpansionChips, Nsf, NsfPlayer, MAX_WAV_SAMPLES},
+};
+use std::{
+    env,
+    fs::File,
+    io::{self, BufWriter},
+    path::PathBuf,
+    process,
+};
+
+const SAMPLE_RATE: u32 = 44100;
+const DEFAULT_SECONDS: u32 = 60;
+const MAX_SECONDS: u32 = (MAX_WAV_SAMPLES / SAMPLE_RATE as usize) as u32;
+
+// Renders one track of an NSF/NSFe rip to a WAV file without opening a window.
+//
+//   nsf2wav <file.nsf> [track] [seconds] [out.wav]
+//
+// Tracks are numbered from 1; the default is the rip's starting song.
+// `seconds` goes up to what a WAV file can hold, a little over 13 hours.
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <file.nsf> [track] [seconds] [out.wav]", args[0]);
+        process::exit(2);
+    }
+
+    let path = PathBuf::from(&args[1]);
+    let nsf = match read_image(&path).and_then(|raw| Nsf::new(&raw)) {
+        Ok(nsf) => nsf,
+        Err(err) => {
+            eprintln!("{}: {err}", path.display());
+            process::exit(1);
+        }
+    };
+
+    let track = match args.get(2).map(|track| track.parse::<u8>()) {
+        Some(Ok(track)) if (1..=nsf.total_songs).contains(&track) => track - 1,
+        Some(_) => {
+            eprintln!("track must be a number from 1 to {}", nsf.total_songs);
+            process::exit(2);
+        }
+        None => nsf.starting_song,
+    };
+    let seconds = match args.get(3).map(|seconds| seconds.parse()) {
+        Some(Ok(seconds)) if seconds <= MAX_SECONDS => seconds,
+        Some(_) => {
+            eprintln!("seconds must be a number up to {MAX_SECONDS}");
+            process::exit(2);
+        }
+        None => DEFAULT_SECONDS,
+    };
+    let out = match args.get(4) {
+        Some(out) => PathBuf::from(out),
+        None => path.with_extension("wav"),
+    };
+
+    println!("{} - {} ({})", nsf.artist, nsf.title, nsf.copyright);
+    println!("Track {} of {}", track + 1, nsf.total_songs);
+    let unsupported = nsf.chips - ExpansionChips::supported();
+    if !unsupported.is_empty() {
+        println!("Expansion audio not emulated: {unsupported:?}");
+    }
+
+    let mut player = NsfPlayer::new(nsf, track, SAMPLE_RATE);
+    let samples = player.render(SAMPLE_RATE as usize * seconds as usize);
+
+    write_wav(
+        &mut BufWriter::new(File::create(&out)?),
+        SAMPLE_RATE,
+        &samples,
+    )?;
+    println!("Wrote {}", out.display());
+    Ok(())
+}
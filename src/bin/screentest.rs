@@ -0,0 +1,70 @@
+use nes_emulator::{
+    cpu::CPU,
+    testrom::{check_golden, frame_hash, run_frames, InputScript, Outcome},
+};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process,
+};
+
+const OUT_DIR: &str = "target/screentest";
+
+// Runs a ROM for a number of frames with scripted input and compares the
+// last frame with a golden PNG, without opening a window.
+//
+//   screentest [--update] <rom.nes> <frames> <golden.png> [input script]
+//
+// On a mismatch actual.png, expected.png and diff.png are written to
+// target/screentest/<golden name>/. --update writes the golden instead.
+// Exits with 0 when the frames match, 1 when they differ, 2 on errors.
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let update = match args.iter().position(|arg| arg == "--update") {
+        Some(idx) => {
+            args.remove(idx);
+            true
+        }
+        None => false,
+    };
+    if args.len() < 4 {
+        eprintln!(
+            "usage: {} [--update] <rom.nes> <frames> <golden.png> [input script]",
+            args[0]
+        );
+        process::exit(2);
+    }
+
+    let rom = PathBuf::from(&args[1]);
+    let frames: usize = args[2].parse().expect("frames must be a number");
+    let golden = PathBuf::from(&args[3]);
+    let input = match args.get(4).map(InputScript::load).transpose() {
+        Ok(input) => input.unwrap_or_default(),
+        Err(err) => fail(&err),
+    };
+
+    let mut cpu = match CPU::load_rom_file_without_save(&rom) {
+        Ok(cpu) => cpu,
+        Err(err) => fail(&format!("{}: {err}", rom.display())),
+    };
+    cpu.reset();
+
+    let frame = run_frames(&mut cpu, frames, &input);
+    println!("frame {frames} hash: {:08X}", frame_hash(&frame));
+
+    let name = golden.file_stem().unwrap_or_default();
+    let out_dir = Path::new(OUT_DIR).join(name);
+    match check_golden(&frame, &golden, &out_dir, update) {
+        Ok(Outcome::Passed) => println!("{}: matches {}", rom.display(), golden.display()),
+        Ok(outcome) => {
+            println!("{}: {outcome}, see {}", rom.display(), out_dir.display());
+            process::exit(1);
+        }
+        Err(err) => fail(&format!("{}: {err}", golden.display())),
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(2);
+}
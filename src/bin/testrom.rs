@@ -0,0 +1,43 @@
+use nes_emulator::testrom::{run_status_rom_file, Outcome};
+use std::{env, path::PathBuf, process, time::Duration};
+
+const DEFAULT_SECONDS: u64 = 60;
+
+// Runs a test ROM that reports through $6000 (blargg's instr_test, apu_test,
+// ppu_vbl_nmi, mmc3_test...) without opening a window.
+//
+//   testrom <rom.nes> [seconds]
+//
+// `seconds` is emulated time. Exits with 0 when the ROM passed, 1 when it
+// failed or never finished, 2 when it could not be loaded.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <rom.nes> [seconds]", args[0]);
+        process::exit(2);
+    }
+
+    let path = PathBuf::from(&args[1]);
+    let seconds = match args.get(2) {
+        Some(seconds) => seconds.parse().expect("seconds must be a number"),
+        None => DEFAULT_SECONDS,
+    };
+
+    let report = match run_status_rom_file(&path, Duration::from_secs(seconds)) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("{}: {err}", path.display());
+            process::exit(2);
+        }
+    };
+
+    if !report.message.is_empty() {
+        println!("{}", report.message);
+    }
+    match report.outcome {
+        Outcome::TimedOut => println!("{}: timed out after {seconds}s", path.display()),
+        outcome => println!("{}: {outcome}", path.display()),
+    }
+
+    process::exit(if report.outcome.passed() { 0 } else { 1 });
+}
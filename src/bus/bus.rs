@@ -1,83 +1,306 @@
-use crate::{cartridge::Rom, cpu::cpu::Mem, ppu::PPU};
+use std::{io, path::Path};
+
+use crate::{
+    apu::APU,
+    cartridge::{
+        mapper::{self, MapperRef},
+        save::BatterySave,
+        Rom, RomError,
+    },
+    cpu::cpu::Mem,
+    joypad::Joypad,
+    ppu::PPU,
+};
+
+use super::watch::{Space, WatchHit, Watchpoints};
 
 const RAM: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
 const PPU_REGISTERS_MIRROR_END: u16 = 0x3FFF;
+const CARTRIDGE_SPACE: u16 = 0x4020;
+const PRG_RAM: u16 = 0x6000;
+// The trainer lives at $7000 in the $6000-$7FFF PRG-RAM window
+const TRAINER_OFFSET: usize = 0x1000;
+
+// Expansion chips report roughly -1.0..=1.0; this brings them to about the
+// loudness of a full-volume pulse channel in the APU mix
+const EXPANSION_AUDIO_GAIN: f32 = 0.15;
+
+// CPU cycles per second: the master clock divided by 12 on NTSC consoles
+// and by 16 on PAL ones
+pub const NTSC_CPU_CLOCK: usize = 1_789_773;
+pub const PAL_CPU_CLOCK: usize = 1_662_607;
+
+// Roughly five seconds of NTSC CPU time between periodic battery flushes
+const SAVE_FLUSH_INTERVAL: usize = NTSC_CPU_CLOCK * 5;
+
+// The CPU is halted this long while OAMDMA copies a page
+const OAM_DMA_CYCLES: usize = 513;
 
 pub struct Bus {
     cpu_vram: [u8; 2048],
-    prg_rom: Vec<u8>,
+    mapper: MapperRef,
     ppu: PPU,
+    apu: APU,
+    joypads: [Joypad; 2],
+    // Last value on the data bus, what reads of write-only registers and
+    // unmapped addresses see
+    open_bus: u8,
+    watchpoints: Watchpoints,
     cycles: usize,
+    battery_save: Option<BatterySave>,
+    last_save_flush: usize,
 }
 
 impl Bus {
-    pub fn new(rom: Rom) -> Self {
-        let ppu = PPU::new(rom.chr_rom, rom.screen_mirroring);
-        
+    pub fn new(mut rom: Rom) -> Result<Self, RomError> {
+        let trainer = rom.trainer.take();
+        let mapper = mapper::new(rom)?;
+
+        if let Some(trainer) = trainer {
+            if let Some(ram) = mapper.borrow_mut().prg_ram() {
+                if ram.len() >= TRAINER_OFFSET + trainer.len() {
+                    ram[TRAINER_OFFSET..TRAINER_OFFSET + trainer.len()].copy_from_slice(&trainer);
+                }
+            }
+        }
+
+        Ok(Self::with_mapper(mapper))
+    }
+
+    // Builds a bus around an already constructed board, e.g. the NSF player's.
+    pub fn with_mapper(mapper: MapperRef) -> Self {
+        let ppu = PPU::new(mapper.clone());
+
         Self {
             cpu_vram: [0; 2048],
-            prg_rom: rom.prg_rom,
+            mapper,
             ppu,
+            apu: APU::new(),
+            joypads: [Joypad::new(), Joypad::new()],
+            open_bus: 0,
+            watchpoints: Watchpoints::new(),
             cycles: 0,
+            battery_save: None,
+            last_save_flush: 0,
         }
     }
 
-    fn read_prg_rom(&self, mut addr: u16) -> u8 {
-        addr -= 0x8000;
-        if self.prg_rom.len() == 0x4000 && addr >= 0x4000 {
-            addr %= 0x4000;
+    // Loads battery-backed RAM from the `.sav` file next to `rom_path` and
+    // keeps it in sync from then on. Does nothing for boards without battery.
+    pub fn attach_battery_save<P: AsRef<Path>>(&mut self, rom_path: P) -> io::Result<()> {
+        let mut save = BatterySave::for_rom(rom_path);
+
+        let mut mapper = self.mapper.borrow_mut();
+        let Some(ram) = mapper.battery_ram() else {
+            return Ok(());
+        };
+        save.load(ram)?;
+        drop(mapper);
+
+        self.battery_save = Some(save);
+        self.last_save_flush = self.cycles;
+        Ok(())
+    }
+
+    pub fn flush_battery_save(&mut self) -> io::Result<()> {
+        let Some(save) = &mut self.battery_save else {
+            return Ok(());
+        };
+
+        self.last_save_flush = self.cycles;
+        match self.mapper.borrow_mut().battery_ram() {
+            Some(ram) => save.flush(ram),
+            None => Ok(()),
         }
+    }
 
-        self.prg_rom[addr as usize]
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    // Controller in port 0 ($4016) or 1 ($4017)
+    pub fn joypad(&mut self, port: usize) -> &mut Joypad {
+        &mut self.joypads[port]
+    }
+
+    pub fn watchpoints(&mut self) -> &mut Watchpoints {
+        &mut self.watchpoints
+    }
+
+    pub fn disk_side_count(&self) -> usize {
+        self.mapper.borrow().disk_side_count()
+    }
+
+    // Flips or swaps the FDS disk; `None` ejects it.
+    pub fn insert_disk(&mut self, side: Option<usize>) {
+        self.mapper.borrow_mut().insert_disk(side);
+    }
+
+    pub fn expansion_audio(&self) -> f32 {
+        self.mapper.borrow().audio_output()
+    }
+
+    // Current mixer output: the APU channels plus any cartridge audio.
+    pub fn audio_output(&self) -> f32 {
+        self.apu.output() + self.expansion_audio() * EXPANSION_AUDIO_GAIN
     }
 }
 
 impl Mem for Bus {
+    fn tick(&mut self, cycles: u8) {
+        self.cycles += cycles as usize;
+        self.mapper.borrow_mut().tick(cycles);
+
+        for _ in 0..cycles {
+            self.ppu.tick(3);
+            self.apu.tick();
+            if let Some(addr) = self.apu.dmc_fetch_addr() {
+                let data = self.mem_read(addr);
+                self.apu.dmc_fill(data);
+            }
+        }
+
+        if self.battery_save.is_some() && self.cycles - self.last_save_flush >= SAVE_FLUSH_INTERVAL
+        {
+            if let Err(err) = self.flush_battery_save() {
+                eprintln!("Failed to flush battery save: {err}");
+            }
+        }
+    }
+
+    fn poll_irq(&self) -> bool {
+        self.apu.irq_pending() || self.mapper.borrow().irq_pending()
+    }
+
+    fn poll_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.ppu.nmi_interrupt)
+    }
+
+    // I/O registers are not read, as that can have side effects; they show
+    // as $FF, like in Nintendulator's nestest log. That includes the
+    // cartridge's own registers below $6000, e.g. the FDS status ports.
+    fn peek(&mut self, addr: u16) -> u8 {
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.cpu_vram[(addr & 0x07FF) as usize],
+            PRG_RAM..=0xFFFF => self.mapper.borrow_mut().cpu_read(addr),
+            _ => 0xFF,
+        }
+    }
+
     fn mem_read(&mut self, addr: u16) -> u8 {
+        if self.watchpoints.is_empty() {
+            self.open_bus = self.read(addr);
+            return self.open_bus;
+        }
+
+        let vram_addr = self.ppu.vram_addr();
+        let data = self.read(addr);
+        self.check_watchpoints(addr, vram_addr, false, data);
+        self.open_bus = data;
+        data
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.open_bus = data;
+        if !self.watchpoints.is_empty() {
+            let vram_addr = self.ppu.vram_addr();
+            self.check_watchpoints(addr, vram_addr, true, data);
+        }
+
+        self.write(addr, data)
+    }
+
+    fn begin_instruction(&mut self, pc: u16) {
+        self.watchpoints.set_pc(pc);
+    }
+
+    fn take_break(&mut self) -> Option<WatchHit> {
+        self.watchpoints.take_break()
+    }
+}
+
+impl Bus {
+    fn read(&mut self, addr: u16) -> u8 {
         match addr {
             RAM..=RAM_MIRRORS_END => {
                 let mirror_down_addr = addr & 0x07FF;
                 self.cpu_vram[mirror_down_addr as usize]
             }
-            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 | 0x4014 => {
-                panic!("attempt to read from write-only PPU address {addr:X}")
-            }
+            // Write-only, which indexed writes still dummy read
+            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => self.open_bus,
             0x2002 => self.ppu.read_from_status(),
+            0x2004 => self.ppu.read_oam_data(),
             0x2007 => self.ppu.read_to_data(),
             0x2008..=PPU_REGISTERS_MIRROR_END => {
                 let mirror_down_addr = addr & 0x2007;
-                self.mem_read(mirror_down_addr)
-            }
-            0x8000..=0xFFFF => self.read_prg_rom(addr),
-            _ => {
-                println!("Ignoring mem access at {addr}");
-                0
+                self.read(mirror_down_addr)
             }
+            0x4015 => self.apu.read_status(),
+            0x4016 => self.joypads[0].read(),
+            0x4017 => self.joypads[1].read(),
+            CARTRIDGE_SPACE..=0xFFFF => self.mapper.borrow_mut().cpu_read(addr),
+            // The APU registers besides $4015, OAMDMA and the unused
+            // $4018-$401F are write-only too
+            _ => self.open_bus,
         }
     }
 
-    fn mem_write(&mut self, addr: u16, data: u8) {
+    fn write(&mut self, addr: u16, data: u8) {
         match addr {
             RAM..=RAM_MIRRORS_END => {
                 let mirror_down_addr = addr & 0x07FF;
                 self.cpu_vram[mirror_down_addr as usize] = data;
             }
-            0x2002 => {
-                panic!("attempt to write to read-only PPU address {addr:X}")
-            }
             0x2000 => self.ppu.write_to_ctrl(data),
             0x2001 => self.ppu.write_to_mask(data),
+            0x2003 => self.ppu.write_to_oam_addr(data),
+            0x2004 => self.ppu.write_to_oam_data(data),
+            0x2005 => self.ppu.write_to_scroll(data),
             0x2006 => self.ppu.write_to_ppu_addr(data),
             0x2007 => self.ppu.write_to_data(data),
             0x2008..=PPU_REGISTERS_MIRROR_END => {
                 let mirror_down_addr = addr & 0b00100000_00000111;
-                self.mem_write(mirror_down_addr, data);
+                self.write(mirror_down_addr, data);
             }
-            0x8000..=0xFFFF => panic!("Attempt to write to Cartridge ROM space"),
-            _ => {
-                println!("Ignoring mem write-access at {addr}");
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu.write_register(addr, data),
+            0x4014 => {
+                let mut page = [0; 256];
+                for (idx, byte) in page.iter_mut().enumerate() {
+                    *byte = self.mem_read(u16::from_be_bytes([data, idx as u8]));
+                }
+                self.ppu.write_oam_dma(&page);
+
+                for _ in 0..OAM_DMA_CYCLES {
+                    self.tick(1);
+                }
             }
+            // Both controllers are strobed together
+            0x4016 => self.joypads.iter_mut().for_each(|joypad| joypad.write(data)),
+            CARTRIDGE_SPACE..=0xFFFF => self.mapper.borrow_mut().cpu_write(addr, data),
+            // PPUSTATUS is read-only and $4018-$401F are unused
+            _ => {}
+        }
+    }
+
+    // PPUDATA accesses also count as accesses to `vram_addr`, the PPU
+    // address they went to
+    fn check_watchpoints(&mut self, addr: u16, vram_addr: u16, write: bool, data: u8) {
+        self.watchpoints.check(Space::Cpu, addr, write, data, self.cycles);
+        if (0x2000..=PPU_REGISTERS_MIRROR_END).contains(&addr) && addr & 7 == 7 {
+            self.watchpoints.check(Space::Ppu, vram_addr, write, data, self.cycles);
+        }
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush_battery_save() {
+            eprintln!("Failed to flush battery save: {err}");
         }
     }
 }
@@ -1 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod bus;
+pub mod ram;
+pub mod watch;
@@ -0,0 +1,50 @@
+use crate::cpu::cpu::Mem;
+
+// Flat 64 KiB of RAM and nothing else, for running the CPU outside the NES.
+pub struct RamBus {
+    pub memory: Vec<u8>,
+    pub cycles: usize,
+    // IRQ line as seen by the CPU, driven by whoever owns the bus
+    pub irq: bool,
+}
+
+impl RamBus {
+    pub fn new() -> Self {
+        RamBus {
+            memory: vec![0; 0x10000],
+            cycles: 0,
+            irq: false,
+        }
+    }
+
+    // Copies `data` into memory starting at `addr`, wrapping at $FFFF
+    pub fn load(&mut self, addr: u16, data: &[u8]) {
+        for (offset, &byte) in data.iter().enumerate() {
+            self.memory[addr.wrapping_add(offset as u16) as usize] = byte;
+        }
+    }
+}
+
+impl Default for RamBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mem for RamBus {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.memory[addr as usize] = data;
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.cycles += cycles as usize;
+    }
+
+    fn poll_irq(&self) -> bool {
+        self.irq
+    }
+}
@@ -0,0 +1,321 @@
+use std::{collections::BTreeMap, fmt, ops::RangeInclusive};
+
+// Hits kept for `take_hits`; later ones are still counted and can still
+// break, but are not logged
+const MAX_LOGGED_HITS: usize = 1000;
+
+// Windows of the CPU address space that repeat up to `last`, as
+// (base, last, size): the 2 KiB of RAM and the eight PPU registers
+const CPU_MIRRORS: [(u16, u16, u16); 2] = [(0x0000, 0x1FFF, 0x0800), (0x2000, 0x3FFF, 0x0008)];
+// The same for the PPU: nametables at $3000-$3EFF and the 32 palette bytes
+const PPU_MIRRORS: [(u16, u16, u16); 2] = [(0x2000, 0x3EFF, 0x1000), (0x3F00, 0x3FFF, 0x0020)];
+
+// Which address space a watchpoint covers: the CPU's, or the PPU's as
+// reached through PPUDATA ($2007)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+    Cpu,
+    Ppu,
+}
+
+// Fires when an address in `range`, or a mirror of one, is read or
+// written. Mirrors count both ways: watching $0010 catches $0810, and
+// watching $2002 catches $3FFA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub space: Space,
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+    // Stop the debugger after the instruction that hit it
+    pub break_on_hit: bool,
+    pub enabled: bool,
+    pub hits: usize,
+}
+
+// One access that hit a watchpoint. `pc` is the instruction that made it
+// and `cycle` the bus cycle count, which is that of the instruction's first
+// cycle unless the CPU is cycle-stepped. For PPU reads `value` is what
+// $2007 returned, which outside the palette is the byte read before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub id: usize,
+    pub space: Space,
+    // As accessed, before mirroring
+    pub addr: u16,
+    pub write: bool,
+    pub value: u8,
+    pub pc: u16,
+    pub cycle: usize,
+}
+
+pub struct Watchpoints {
+    list: BTreeMap<usize, Watchpoint>,
+    next_id: usize,
+    // Address of the instruction running, from the CPU
+    pc: u16,
+    hits: Vec<WatchHit>,
+    pending_break: Option<WatchHit>,
+}
+
+impl Watchpoint {
+    pub fn new(space: Space, range: RangeInclusive<u16>, read: bool, write: bool) -> Self {
+        Watchpoint {
+            space,
+            range,
+            read,
+            write,
+            break_on_hit: false,
+            enabled: true,
+            hits: 0,
+        }
+    }
+
+    fn covers(&self, addr: u16) -> bool {
+        let (addr, mirrors) = match self.space {
+            Space::Cpu => (addr, &CPU_MIRRORS),
+            // PPU addresses are 14 bits
+            Space::Ppu => (addr & 0x3FFF, &PPU_MIRRORS),
+        };
+        if self.range.contains(&addr) {
+            return true;
+        }
+
+        mirrors.iter().any(|&(base, last, size)| {
+            (base..=last).contains(&addr) && self.mirror_in_range(addr, base, last, size)
+        })
+    }
+
+    // Whether the range holds any address of base..=last with the same
+    // offset into the repeating window as `addr`
+    fn mirror_in_range(&self, addr: u16, base: u16, last: u16, size: u16) -> bool {
+        let start = (*self.range.start()).max(base) as u32;
+        let end = (*self.range.end()).min(last) as u32;
+        if start > end {
+            return false;
+        }
+
+        let (base, size) = (base as u32, size as u32);
+        let offset = (addr as u32 - base) % size;
+        let first = start + (offset + size - (start - base) % size) % size;
+        first <= end
+    }
+}
+
+impl Default for Watchpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Watchpoints {
+            list: BTreeMap::new(),
+            next_id: 1,
+            pc: 0,
+            hits: vec![],
+            pending_break: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    // Adds a watchpoint and returns its id
+    pub fn add(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.list.insert(id, watchpoint);
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        self.list.remove(&id).is_some()
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Watchpoint> {
+        self.list.get_mut(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.list.iter().map(|(&id, watchpoint)| (id, watchpoint))
+    }
+
+    // Every hit since the last call, oldest first
+    pub fn take_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.hits)
+    }
+
+    // The first hit of a breaking watchpoint since the last call
+    pub fn take_break(&mut self) -> Option<WatchHit> {
+        self.pending_break.take()
+    }
+
+    pub(crate) fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub(crate) fn check(&mut self, space: Space, addr: u16, write: bool, value: u8, cycle: usize) {
+        for (&id, watchpoint) in self.list.iter_mut() {
+            let access = if write {
+                watchpoint.write
+            } else {
+                watchpoint.read
+            };
+            if !watchpoint.enabled
+                || !access
+                || watchpoint.space != space
+                || !watchpoint.covers(addr)
+            {
+                continue;
+            }
+
+            watchpoint.hits += 1;
+            let hit = WatchHit {
+                id,
+                space,
+                addr,
+                write,
+                value,
+                pc: self.pc,
+                cycle,
+            };
+            if self.hits.len() < MAX_LOGGED_HITS {
+                self.hits.push(hit);
+            }
+            if watchpoint.break_on_hit && self.pending_break.is_none() {
+                self.pending_break = Some(hit);
+            }
+        }
+    }
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let space = match self.space {
+            Space::Cpu => "",
+            Space::Ppu => "PPU ",
+        };
+        let (verb, preposition) = match self.write {
+            true => ("wrote", "to"),
+            false => ("read", "from"),
+        };
+
+        write!(
+            f,
+            "watchpoint {}: ${:04X} {verb} ${:02X} {preposition} {space}${:04X} at cycle {}",
+            self.id, self.pc, self.value, self.addr, self.cycle
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn covers(space: Space, range: RangeInclusive<u16>, addr: u16) -> bool {
+        Watchpoint::new(space, range, true, true).covers(addr)
+    }
+
+    #[test]
+    fn test_cpu_mirrors() {
+        assert!(covers(Space::Cpu, 0x0010..=0x0010, 0x0010));
+        assert!(covers(Space::Cpu, 0x0010..=0x0010, 0x0810));
+        assert!(covers(Space::Cpu, 0x0010..=0x0010, 0x1810));
+        assert!(covers(Space::Cpu, 0x0810..=0x0810, 0x0010));
+        assert!(!covers(Space::Cpu, 0x0010..=0x0010, 0x0011));
+
+        // Straddling the end of the first copy of RAM
+        assert!(covers(Space::Cpu, 0x0700..=0x0900, 0x0100));
+        assert!(covers(Space::Cpu, 0x0700..=0x0900, 0x1F80));
+        assert!(!covers(Space::Cpu, 0x0700..=0x0900, 0x0200));
+
+        assert!(covers(Space::Cpu, 0x2002..=0x2002, 0x3FFA));
+        assert!(covers(Space::Cpu, 0x3FFA..=0x3FFA, 0x2002));
+        assert!(!covers(Space::Cpu, 0x2002..=0x2002, 0x2003));
+        assert!(covers(Space::Cpu, 0x2000..=0x2007, 0x3456));
+
+        // Nothing else is mirrored
+        assert!(!covers(Space::Cpu, 0x4016..=0x4016, 0x4036));
+        assert!(!covers(Space::Cpu, 0x8000..=0x8000, 0xC000));
+    }
+
+    #[test]
+    fn test_ppu_mirrors() {
+        assert!(covers(Space::Ppu, 0x2005..=0x2005, 0x3005));
+        assert!(covers(Space::Ppu, 0x3005..=0x3005, 0x2005));
+        assert!(covers(Space::Ppu, 0x3F00..=0x3F00, 0x3F20));
+        assert!(covers(Space::Ppu, 0x3F00..=0x3F00, 0x3FE0));
+        assert!(!covers(Space::Ppu, 0x3F00..=0x3F00, 0x3F01));
+        assert!(covers(Space::Ppu, 0x0000..=0x0000, 0x4000));
+        assert!(!covers(Space::Ppu, 0x0000..=0x0000, 0x1000));
+    }
+
+    #[test]
+    fn test_check() {
+        let mut watchpoints = Watchpoints::new();
+        let reads = watchpoints.add(Watchpoint::new(Space::Cpu, 0x10..=0x1F, true, false));
+        let mut writes = Watchpoint::new(Space::Cpu, 0x10..=0x10, false, true);
+        writes.break_on_hit = true;
+        let writes = watchpoints.add(writes);
+
+        watchpoints.set_pc(0xC000);
+        watchpoints.check(Space::Cpu, 0x0815, false, 0x42, 100);
+        watchpoints.check(Space::Ppu, 0x0010, true, 0x01, 101);
+        watchpoints.set_pc(0xC003);
+        watchpoints.check(Space::Cpu, 0x0010, true, 0x02, 102);
+        watchpoints.check(Space::Cpu, 0x0810, true, 0x03, 103);
+
+        let hit = |id, addr, write, value, pc, cycle| WatchHit {
+            id,
+            space: Space::Cpu,
+            addr,
+            write,
+            value,
+            pc,
+            cycle,
+        };
+        assert_eq!(
+            watchpoints.take_hits(),
+            [
+                hit(reads, 0x0815, false, 0x42, 0xC000, 100),
+                hit(writes, 0x0010, true, 0x02, 0xC003, 102),
+                hit(writes, 0x0810, true, 0x03, 0xC003, 103),
+            ]
+        );
+        assert!(watchpoints.take_hits().is_empty());
+        assert_eq!(
+            watchpoints.take_break(),
+            Some(hit(writes, 0x0010, true, 0x02, 0xC003, 102))
+        );
+        assert_eq!(watchpoints.take_break(), None);
+
+        watchpoints.get_mut(writes).unwrap().enabled = false;
+        watchpoints.check(Space::Cpu, 0x0010, true, 0x04, 104);
+        assert!(watchpoints.take_hits().is_empty());
+        assert_eq!(watchpoints.get_mut(writes).unwrap().hits, 2);
+
+        assert!(watchpoints.remove(reads));
+        assert!(!watchpoints.remove(reads));
+        assert_eq!(watchpoints.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_display() {
+        let hit = WatchHit {
+            id: 3,
+            space: Space::Ppu,
+            addr: 0x3F00,
+            write: true,
+            value: 0x0F,
+            pc: 0x8010,
+            cycle: 1234,
+        };
+        assert_eq!(
+            hit.to_string(),
+            "watchpoint 3: $8010 wrote $0F to PPU $3F00 at cycle 1234"
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use miniz_oxide::inflate::{decompress_to_vec_with_limit, TINFLStatus};
+
+use super::{hash::crc32, RomError, MAX_ROM_SIZE};
+
+const GZIP_TAG: &[u8] = &[0x1F, 0x8B];
+const ZIP_TAG: &[u8] = b"PK\x03\x04";
+const ZIP_END_TAG: &[u8] = b"PK\x05\x06";
+const ZIP_CENTRAL_TAG: &[u8] = b"PK\x01\x02";
+const ZIP_END_SIZE: usize = 22;
+const ZIP_CENTRAL_SIZE: usize = 46;
+const ZIP_LOCAL_SIZE: usize = 30;
+// The end record is followed by a comment of up to 64 KiB
+const ZIP_MAX_COMMENT: usize = 0xFFFF;
+
+const DEFLATE: u16 = 8;
+const STORED: u16 = 0;
+
+// Archive members worth loading, by extension
+const ROM_EXTENSIONS: &[&str] = &["nes", "fds", "nsf", "nsfe", "unf", "unif"];
+
+// Returns the image inside a zip or gzip container, or `raw` itself if it
+// is not compressed.
+pub(super) fn unpack(raw: Vec<u8>) -> Result<Vec<u8>, RomError> {
+    if raw.starts_with(ZIP_TAG) {
+        unzip(&raw)
+    } else if raw.starts_with(GZIP_TAG) {
+        gunzip(&raw)
+    } else {
+        Ok(raw)
+    }
+}
+
+fn u16_at(raw: &[u8], pos: usize) -> Result<u16, RomError> {
+    raw.get(pos..pos + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(RomError::BadArchive("archive is truncated"))
+}
+
+fn u32_at(raw: &[u8], pos: usize) -> Result<u32, RomError> {
+    raw.get(pos..pos + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(RomError::BadArchive("archive is truncated"))
+}
+
+fn is_rom_name(name: &str) -> bool {
+    name.rsplit_once('.').is_some_and(|(_, extension)| {
+        ROM_EXTENSIONS
+            .iter()
+            .any(|rom| rom.eq_ignore_ascii_case(extension))
+    })
+}
+
+// Inflates a member the archive says is `size` bytes, refusing to produce
+// more than that so that a small archive cannot expand without bound
+fn inflate(data: &[u8], expected_crc: u32, size: usize) -> Result<Vec<u8>, RomError> {
+    if size > MAX_ROM_SIZE {
+        return Err(RomError::BadArchive("archive member is too large"));
+    }
+
+    let data = decompress_to_vec_with_limit(data, size).map_err(|err| match err.status {
+        TINFLStatus::HasMoreOutput => {
+            RomError::BadArchive("archive member is larger than declared")
+        }
+        _ => RomError::BadArchive("corrupt deflate stream"),
+    })?;
+    if data.len() != size {
+        return Err(RomError::BadArchive(
+            "archive member is smaller than declared",
+        ));
+    }
+    if crc32(&data) != expected_crc {
+        return Err(RomError::BadArchive("CRC-32 mismatch"));
+    }
+    Ok(data)
+}
+
+// Walks the central directory and extracts the first ROM-like member.
+fn unzip(raw: &[u8]) -> Result<Vec<u8>, RomError> {
+    let search_start = raw.len().saturating_sub(ZIP_END_SIZE + ZIP_MAX_COMMENT);
+    let end = raw[search_start..]
+        .windows(ZIP_END_TAG.len())
+        .rposition(|window| window == ZIP_END_TAG)
+        .map(|pos| search_start + pos)
+        .ok_or(RomError::BadArchive(
+            "zip end of central directory is missing",
+        ))?;
+
+    let entries = u16_at(raw, end + 10)?;
+    let mut pos = u32_at(raw, end + 16)? as usize;
+
+    for _ in 0..entries {
+        if raw.get(pos..pos + 4) != Some(ZIP_CENTRAL_TAG) {
+            return Err(RomError::BadArchive("corrupt zip central directory"));
+        }
+
+        let method = u16_at(raw, pos + 10)?;
+        let crc = u32_at(raw, pos + 16)?;
+        let compressed_size = u32_at(raw, pos + 20)? as usize;
+        let size = u32_at(raw, pos + 24)? as usize;
+        let name_len = u16_at(raw, pos + 28)? as usize;
+        let extra_len = u16_at(raw, pos + 30)? as usize;
+        let comment_len = u16_at(raw, pos + 32)? as usize;
+        let local = u32_at(raw, pos + 42)? as usize;
+        let name = raw
+            .get(pos + ZIP_CENTRAL_SIZE..pos + ZIP_CENTRAL_SIZE + name_len)
+            .ok_or(RomError::BadArchive("archive is truncated"))?;
+        pos += ZIP_CENTRAL_SIZE + name_len + extra_len + comment_len;
+
+        if !is_rom_name(&String::from_utf8_lossy(name)) {
+            continue;
+        }
+
+        // The local header repeats the name but may have a different extra field
+        let data_start = local
+            + ZIP_LOCAL_SIZE
+            + u16_at(raw, local + 26)? as usize
+            + u16_at(raw, local + 28)? as usize;
+        let data = raw
+            .get(data_start..data_start + compressed_size)
+            .ok_or(RomError::BadArchive("archive is truncated"))?;
+
+        return match method {
+            STORED if crc32(data) == crc => Ok(data.to_vec()),
+            STORED => Err(RomError::BadArchive("CRC-32 mismatch")),
+            DEFLATE => inflate(data, crc, size),
+            _ => Err(RomError::BadArchive("unsupported zip compression method")),
+        };
+    }
+
+    Err(RomError::EmptyArchive)
+}
+
+// Single-member gzip: 10-byte header, optional fields, deflate data and a
+// CRC-32/size trailer.
+fn gunzip(raw: &[u8]) -> Result<Vec<u8>, RomError> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    if raw.len() < 18 {
+        return Err(RomError::BadArchive("archive is truncated"));
+    }
+    if u16::from(raw[2]) != DEFLATE {
+        return Err(RomError::BadArchive("unsupported gzip compression method"));
+    }
+
+    let flags = raw[3];
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        pos += 2 + u16_at(raw, pos)? as usize;
+    }
+    for field in [FNAME, FCOMMENT] {
+        if flags & field != 0 {
+            let len = raw
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&byte| byte == 0))
+                .ok_or(RomError::BadArchive("archive is truncated"))?;
+            pos += len + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    let trailer = raw.len() - 8;
+    let data = raw
+        .get(pos..trailer)
+        .ok_or(RomError::BadArchive("archive is truncated"))?;
+    // The trailer's size is modulo 2^32, which covers any ROM
+    inflate(
+        data,
+        u32_at(raw, trailer)?,
+        u32_at(raw, trailer + 4)? as usize,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::{read_image, test_image, Mirroring, Rom};
+
+    use super::*;
+
+    #[test]
+    fn test_load_from_archives() {
+        use miniz_oxide::deflate::compress_to_vec;
+
+        let image = test_image(0, 1, 1, 0x01);
+        let deflated = compress_to_vec(&image, 6);
+        let crc = crc32(&image).to_le_bytes();
+
+        let mut gzip = vec![0x1F, 0x8B, 0x08, 0x08, 0, 0, 0, 0, 0, 0xFF];
+        gzip.extend(b"game.nes\0");
+        gzip.extend(&deflated);
+        gzip.extend(crc);
+        gzip.extend((image.len() as u32).to_le_bytes());
+
+        // A readme first, then the ROM deflated
+        let mut zip = vec![];
+        let mut central = vec![];
+        for (name, method, data) in [
+            ("readme.txt", 0u16, &b"hello"[..]),
+            ("Game.NES", 8u16, &deflated[..]),
+        ] {
+            let crc = if method == 0 {
+                crc32(data)
+            } else {
+                crc32(&image)
+            };
+            let mut header = vec![];
+            header.extend(method.to_le_bytes());
+            header.extend([0; 4]);
+            header.extend(crc.to_le_bytes());
+            let size = if method == 0 { data.len() } else { image.len() };
+            header.extend((data.len() as u32).to_le_bytes());
+            header.extend((size as u32).to_le_bytes());
+            header.extend((name.len() as u16).to_le_bytes());
+            header.extend([0; 2]);
+
+            central.extend(b"PK\x01\x02\x14\x00\x14\x00\x00\x00");
+            central.extend(&header);
+            central.extend([0; 10]);
+            central.extend((zip.len() as u32).to_le_bytes());
+            central.extend(name.as_bytes());
+
+            zip.extend(b"PK\x03\x04\x14\x00\x00\x00");
+            zip.extend(&header);
+            zip.extend(name.as_bytes());
+            zip.extend(data);
+        }
+        let central_start = zip.len() as u32;
+        zip.extend(&central);
+        zip.extend(b"PK\x05\x06\x00\x00\x00\x00\x02\x00\x02\x00");
+        zip.extend((central.len() as u32).to_le_bytes());
+        zip.extend(central_start.to_le_bytes());
+        zip.extend([0; 2]);
+
+        let dir = std::env::temp_dir().join(format!("nes-archive-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, data) in [
+            ("game.nes.gz", &gzip),
+            ("game.zip", &zip),
+            ("game.nes", &image),
+        ] {
+            let path = dir.join(name);
+            std::fs::write(&path, data).unwrap();
+            assert_eq!(read_image(&path).unwrap(), image);
+            assert_eq!(
+                Rom::load(&path).unwrap().screen_mirroring,
+                Mirroring::Vertical
+            );
+        }
+
+        let mut corrupt = gzip.clone();
+        let crc_pos = corrupt.len() - 8;
+        corrupt[crc_pos] ^= 0xFF;
+        let path = dir.join("corrupt.gz");
+        std::fs::write(&path, corrupt).unwrap();
+        assert_eq!(
+            read_image(&path).err(),
+            Some(RomError::BadArchive("CRC-32 mismatch"))
+        );
+
+        // Inflating stops at the declared size
+        let with_size = |size: u32| {
+            let mut gzip = gzip.clone();
+            let size_pos = gzip.len() - 4;
+            gzip[size_pos..].copy_from_slice(&size.to_le_bytes());
+            unpack(gzip).err()
+        };
+        assert_eq!(
+            with_size(image.len() as u32 - 1),
+            Some(RomError::BadArchive(
+                "archive member is larger than declared"
+            ))
+        );
+        assert_eq!(
+            with_size(image.len() as u32 + 1),
+            Some(RomError::BadArchive(
+                "archive member is smaller than declared"
+            ))
+        );
+        assert_eq!(
+            with_size(MAX_ROM_SIZE as u32 + 1),
+            Some(RomError::BadArchive("archive member is too large"))
+        );
+
+        assert!(matches!(
+            read_image(dir.join("missing.nes")),
+            Err(RomError::Io {
+                kind: std::io::ErrorKind::NotFound,
+                ..
+            })
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
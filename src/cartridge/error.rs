@@ -0,0 +1,102 @@
+use std::{fmt, io};
+
+use super::PatchError;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RomError {
+    BadMagic,
+    TruncatedHeader {
+        len: usize,
+    },
+    TruncatedTrainer {
+        len: usize,
+    },
+    TruncatedPrg {
+        expected: usize,
+        actual: usize,
+    },
+    TruncatedChr {
+        expected: usize,
+        actual: usize,
+    },
+    UnsupportedMapper(u16),
+    UnsupportedBoard(String),
+    MissingChunk(&'static str),
+    // Disk images need the disk system BIOS, see `Rom::from_fds`
+    MissingBios,
+    // The BIOS given for a disk image is shorter than 8 KiB
+    TruncatedBios {
+        len: usize,
+    },
+    // Recognised container that this build cannot load
+    UnsupportedFormat(&'static str),
+    // Patch number `index` of those given to `Rom::with_patches` failed
+    BadPatch {
+        index: usize,
+        error: PatchError,
+    },
+    // Zip or gzip container that could not be unpacked
+    BadArchive(&'static str),
+    // Zip without any .nes/.fds/.nsf/.unf member
+    EmptyArchive,
+    Io {
+        kind: io::ErrorKind,
+        message: String,
+    },
+}
+
+impl From<io::Error> for RomError {
+    fn from(err: io::Error) -> Self {
+        RomError::Io {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::BadMagic => write!(f, "file format is not recognised"),
+            RomError::TruncatedHeader { len } => {
+                write!(f, "header is truncated: {len} of 16 bytes present")
+            }
+            RomError::TruncatedTrainer { len } => {
+                write!(f, "trainer is truncated: {len} of 512 bytes present")
+            }
+            RomError::TruncatedPrg { expected, actual } => {
+                write!(
+                    f,
+                    "PRG-ROM is truncated: expected {expected} bytes, got {actual}"
+                )
+            }
+            RomError::TruncatedChr { expected, actual } => {
+                write!(
+                    f,
+                    "CHR-ROM is truncated: expected {expected} bytes, got {actual}"
+                )
+            }
+            RomError::UnsupportedMapper(mapper) => write!(f, "mapper {mapper} is not supported"),
+            RomError::UnsupportedBoard(board) => write!(f, "board {board} is not supported"),
+            RomError::MissingChunk(chunk) => write!(f, "required {chunk} chunk is missing"),
+            RomError::MissingBios => write!(f, "disk images need the FDS BIOS to load"),
+            RomError::TruncatedBios { len } => {
+                write!(f, "FDS BIOS is truncated: {len} of 8192 bytes present")
+            }
+            RomError::UnsupportedFormat(format) => write!(f, "{format} images are not supported"),
+            RomError::BadPatch { index, error } => write!(f, "patch {}: {error}", index + 1),
+            RomError::BadArchive(reason) => write!(f, "cannot unpack archive: {reason}"),
+            RomError::EmptyArchive => write!(f, "archive contains no ROM image"),
+            RomError::Io { message, .. } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for RomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RomError::BadPatch { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
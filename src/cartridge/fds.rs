@@ -0,0 +1,190 @@
+use super::{ConsoleType, HeaderFormat, Mirroring, Rom, RomError, Timing};
+
+pub(super) const FWNES_TAG: &[u8] = b"FDS\x1A";
+pub(super) const DISK_TAG: &[u8] = b"\x01*NINTENDO-HVC*";
+const FWNES_HEADER_SIZE: usize = 16;
+pub(super) const SIDE_SIZE: usize = 65500;
+const BIOS_SIZE: usize = 0x2000;
+
+// The RAM adapter: 32 KiB of PRG-RAM at $6000-$DFFF and 8 KiB of CHR-RAM
+const PRG_RAM_SIZE: usize = 0x8000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+// iNES mapper number reserved for the Famicom Disk System
+pub const FDS_MAPPER: u16 = 20;
+
+// Lead-in before the first block and gap between blocks, in bytes
+const LEAD_IN: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+
+impl Rom {
+    // Builds a cartridge from an `.fds` image (with or without the fwNES
+    // header) and the 8 KiB disk system BIOS.
+    pub fn from_fds(image: &[u8], bios: &[u8]) -> Result<Rom, RomError> {
+        if bios.len() < BIOS_SIZE {
+            return Err(RomError::TruncatedBios { len: bios.len() });
+        }
+
+        let disk = match image.starts_with(FWNES_TAG) {
+            true => image.get(FWNES_HEADER_SIZE..).unwrap_or_default(),
+            false => image,
+        };
+        if !disk.starts_with(DISK_TAG) {
+            return Err(RomError::BadMagic);
+        }
+
+        let disk_sides = disk
+            .chunks(SIDE_SIZE)
+            .filter(|side| side.starts_with(DISK_TAG))
+            .map(add_gaps)
+            .collect();
+
+        Ok(Rom {
+            format: HeaderFormat::Fds,
+            prg_rom: bios[..BIOS_SIZE].to_vec(),
+            chr_rom: vec![],
+            chr_ram_size: CHR_RAM_SIZE,
+            chr_nvram_size: 0,
+            prg_ram_size: PRG_RAM_SIZE,
+            prg_nvram_size: 0,
+            battery: false,
+            mapper: FDS_MAPPER,
+            submapper: 0,
+            screen_mirroring: Mirroring::Horizontal,
+            timing: Timing::Ntsc,
+            console_type: ConsoleType::Nes,
+            expansion_device: 0,
+            misc_roms: 0,
+            trainer: None,
+            disk_sides,
+        })
+    }
+}
+
+// `.fds` images store bare blocks. The drive sees a stream of gaps, a $80
+// start mark before every block and a CRC after it, so rebuild that.
+fn add_gaps(side: &[u8]) -> Vec<u8> {
+    let mut disk = Vec::with_capacity(SIDE_SIZE * 2);
+    let mut pos = 0;
+    let mut file_size = 0;
+
+    while pos < side.len() {
+        let block_len = match side[pos] {
+            1 => 56,
+            2 => 2,
+            3 => {
+                if let Some(size) = side.get(pos + 13..pos + 15) {
+                    file_size = u16::from_le_bytes([size[0], size[1]]) as usize;
+                }
+                16
+            }
+            4 => 1 + file_size,
+            _ => break,
+        };
+        let block = &side[pos..(pos + block_len).min(side.len())];
+
+        let gap = if pos == 0 { LEAD_IN } else { BLOCK_GAP };
+        disk.resize(disk.len() + gap, 0);
+        disk.push(0x80);
+        disk.extend_from_slice(block);
+        disk.extend(crc(block).to_le_bytes());
+
+        pos += block_len;
+    }
+
+    disk.resize(disk.len().max(SIDE_SIZE) + BLOCK_GAP, 0);
+    disk
+}
+
+// CRC-16 as computed by the RAM adapter: reflected polynomial $8408 over
+// the start mark and the block data.
+pub(crate) fn crc(block: &[u8]) -> u16 {
+    let mut crc = 0x8000u16;
+    for &byte in block {
+        crc = crc_step(crc, byte);
+    }
+    crc_step(crc_step(crc, 0), 0)
+}
+
+pub(crate) fn crc_step(mut crc: u16, byte: u8) -> u16 {
+    for bit in 0..8 {
+        let carry = crc & 1 != 0;
+        crc = (crc >> 1) | (((byte >> bit) & 1) as u16) << 15;
+        if carry {
+            crc ^= 0x8408;
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{bus::bus::Bus, cpu::cpu::Mem};
+
+    use super::*;
+
+    #[test]
+    fn test_truncated_bios() {
+        let mut disk = DISK_TAG.to_vec();
+        disk.resize(SIDE_SIZE, 0);
+
+        assert_eq!(
+            Rom::from_fds(&disk, &[0; 0x1000]).err(),
+            Some(RomError::TruncatedBios { len: 0x1000 })
+        );
+        assert_eq!(
+            RomError::TruncatedBios { len: 0x1000 }.to_string(),
+            "FDS BIOS is truncated: 4096 of 8192 bytes present"
+        );
+        assert!(Rom::from_fds(&disk, &[0; BIOS_SIZE]).is_ok());
+    }
+
+    fn fds_side() -> Vec<u8> {
+        let mut side = vec![0x01];
+        side.extend(b"*NINTENDO-HVC*");
+        side.resize(56, 0);
+        side.extend([0x02, 0x01]);
+        side.extend([
+            0x03, 0x00, 0x00, b'F', b'I', b'L', b'E', 0, 0, 0, 0, 0x00, 0x60,
+        ]);
+        side.extend([0x02, 0x00, 0x00]);
+        side.extend([0x04, 0xAB, 0xCD]);
+        side.resize(SIDE_SIZE, 0);
+        side
+    }
+
+    #[test]
+    fn test_fds_disk_read() {
+        let mut image = b"FDS\x1A\x01".to_vec();
+        image.resize(16, 0);
+        image.extend(fds_side());
+
+        assert_eq!(Rom::new(&image).err(), Some(RomError::MissingBios));
+
+        let rom = Rom::from_fds(&image, &[0; 0x2000]).unwrap();
+        assert_eq!(rom.mapper, FDS_MAPPER);
+        assert_eq!(rom.disk_sides.len(), 1);
+
+        let mut bus = Bus::new(rom).unwrap();
+        assert_eq!(bus.disk_side_count(), 1);
+
+        // Silence the APU frame IRQ, enable disk I/O, start the motor in
+        // read mode and wait for data
+        bus.mem_write(0x4017, 0x40);
+        bus.mem_write(0x4023, 0x01);
+        bus.mem_write(0x4025, 0xC5);
+
+        let mut bytes = vec![];
+        while bytes.len() < 15 {
+            bus.tick(1);
+            if bus.poll_irq() {
+                bytes.push(bus.mem_read(0x4031));
+            }
+        }
+        assert_eq!(&bytes[..15], b"\x01*NINTENDO-HVC*");
+        assert_eq!(bus.mem_read(0x4032) & 0x01, 0);
+
+        bus.insert_disk(None);
+        assert_eq!(bus.mem_read(0x4032) & 0x01, 1);
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+
+use super::{hash, Mirroring, Rom, Timing};
+
+lazy_static! {
+    pub static ref GAME_DB: GameDb = GameDb::parse(include_str!("gamedb.txt"));
+}
+
+// Board details of a known dump, overriding what its header claims.
+#[derive(Debug, PartialEq, Clone)]
+pub struct GameInfo {
+    pub crc32: u32,
+    pub sha1: Option<[u8; 20]>,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub timing: Timing,
+    pub title: String,
+}
+
+impl GameInfo {
+    pub fn apply(&self, rom: &mut Rom) {
+        rom.mapper = self.mapper;
+        rom.submapper = self.submapper;
+        rom.screen_mirroring = self.mirroring;
+        rom.prg_ram_size = self.prg_ram_size;
+        rom.prg_nvram_size = self.prg_nvram_size;
+        rom.battery = self.prg_nvram_size > 0;
+        rom.chr_ram_size = self.chr_ram_size;
+        rom.timing = self.timing;
+    }
+}
+
+#[derive(Default)]
+pub struct GameDb {
+    entries: HashMap<u32, Vec<GameInfo>>,
+}
+
+impl GameDb {
+    // Parses the `gamedb.txt` format. The database is compiled in, so a
+    // malformed line is a bug and panics.
+    pub fn parse(text: &str) -> Self {
+        let mut db = GameDb::default();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let info = parse_line(line)
+                .unwrap_or_else(|| panic!("malformed game database line {}: {line}", idx + 1));
+            db.entries.entry(info.crc32).or_default().push(info);
+        }
+
+        db
+    }
+
+    // Looks a dump up by CRC-32, confirming with SHA-1 where the entry has one.
+    pub fn lookup(&self, prg_rom: &[u8], chr_rom: &[u8]) -> Option<&GameInfo> {
+        let parts = [prg_rom, chr_rom];
+        let candidates = self.entries.get(&hash::crc32_parts(&parts))?;
+
+        let mut sha1 = None;
+        candidates.iter().find(|info| match info.sha1 {
+            None => true,
+            Some(expected) => *sha1.get_or_insert_with(|| hash::sha1(&parts)) == expected,
+        })
+    }
+}
+
+fn parse_line(line: &str) -> Option<GameInfo> {
+    let mut fields = line.split_whitespace();
+    let mut next = || fields.next();
+
+    let crc32 = u32::from_str_radix(next()?, 16).ok()?;
+    let sha1 = match next()? {
+        "-" => None,
+        digest => Some(parse_digest(digest)?),
+    };
+    let mapper = next()?.parse().ok()?;
+    let submapper = next()?.parse().ok()?;
+    let mirroring = match next()? {
+        "H" => Mirroring::Horizontal,
+        "V" => Mirroring::Vertical,
+        "4" => Mirroring::FourScreen,
+        _ => return None,
+    };
+    let prg_ram_size = next()?.parse().ok()?;
+    let prg_nvram_size = next()?.parse().ok()?;
+    let chr_ram_size = next()?.parse().ok()?;
+    let timing = match next()? {
+        "NTSC" => Timing::Ntsc,
+        "PAL" => Timing::Pal,
+        "MULTI" => Timing::MultiRegion,
+        "DENDY" => Timing::Dendy,
+        _ => return None,
+    };
+    let title = fields.collect::<Vec<_>>().join(" ");
+
+    Some(GameInfo {
+        crc32,
+        sha1,
+        mapper,
+        submapper,
+        mirroring,
+        prg_ram_size,
+        prg_nvram_size,
+        chr_ram_size,
+        timing,
+        title,
+    })
+}
+
+fn parse_digest(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 {
+        return None;
+    }
+
+    let mut digest = [0; 20];
+    for (idx, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(idx * 2..idx * 2 + 2)?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::test_image;
+
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let mut raw = test_image(0, 1, 0, 0);
+        raw[16] = 0xAA;
+        let crc32 = hash::crc32(&raw[16..]);
+        let db = GameDb::parse(&format!(
+            "# comment\n{crc32:08X} - 19 1 V 0 8192 0 PAL Some Game\n"
+        ));
+
+        let mut rom = Rom::new(&raw).unwrap();
+        let info = db.lookup(&rom.prg_rom, &rom.chr_rom).unwrap();
+        assert_eq!(info.title, "Some Game");
+        info.apply(&mut rom);
+        assert_eq!(rom.mapper, 19);
+        assert_eq!(rom.submapper, 1);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert_eq!(rom.prg_nvram_size, 8192);
+        assert!(rom.battery);
+        assert_eq!(rom.timing, Timing::Pal);
+
+        // A SHA-1 mismatch rules out a CRC-32 collision
+        let db = GameDb::parse(&format!(
+            "{crc32:08X} {} 19 0 V 0 0 0 NTSC",
+            "00".repeat(20)
+        ));
+        assert_eq!(db.lookup(&rom.prg_rom, &rom.chr_rom), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "malformed game database line 2")]
+    fn test_malformed_line() {
+        GameDb::parse("# crc32 ...\n12345678 - 0 0 X 0 0 0 NTSC");
+    }
+
+    #[test]
+    fn test_built_in_database_fixes_bad_headers() {
+        // snake.nes is NROM with vertical mirroring and CHR-RAM; claim
+        // mapper 3 with horizontal mirroring instead
+        let mut raw = include_bytes!("../../roms/snake.nes").to_vec();
+        raw[6] = 0x30;
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.mapper, 0);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert_eq!(rom.chr_ram_size, 8192);
+
+        // nestest.nes with a battery and PAL timing it does not have
+        let mut raw = include_bytes!("../../roms/nestest.nes").to_vec();
+        raw[6] |= 0x02;
+        raw[9] = 0x01;
+        let rom = Rom::new(&raw).unwrap();
+        assert!(!rom.battery);
+        assert_eq!(rom.prg_nvram_size, 0);
+        assert_eq!(rom.prg_ram_size, 8192);
+        assert_eq!(rom.timing, Timing::Ntsc);
+
+        // NES 2.0 headers are trusted as they are
+        let mut raw = include_bytes!("../../roms/snake.nes").to_vec();
+        raw[6] = 0x30;
+        raw[7] = 0x08;
+        assert_eq!(Rom::new(&raw).unwrap().mapper, 3);
+    }
+}
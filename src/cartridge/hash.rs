@@ -0,0 +1,140 @@
+// Checksums used to identify and verify dumps.
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut idx = 0;
+    while idx < 256 {
+        let mut crc = idx as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[idx] = crc;
+        idx += 1;
+    }
+    table
+}
+
+// CRC-32 (IEEE, as in zip and the patch formats)
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_parts(&[data])
+}
+
+// CRC-32 of `parts` one after another, without joining them first
+pub fn crc32_parts(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in parts.iter().copied().flatten() {
+        crc = (crc >> 8) ^ CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize];
+    }
+    !crc
+}
+
+// SHA-1 of `parts` one after another, as cartridge databases hash PRG-ROM
+// followed by CHR-ROM
+pub fn sha1(parts: &[&[u8]]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let len: usize = parts.iter().map(|part| part.len()).sum();
+
+    // The message, a 1 bit, zeros up to 8 bytes short of a block, then the
+    // length in bits
+    let zeros = (64 + 55 - len % 64) % 64;
+    let padding = std::iter::once(0x80)
+        .chain(std::iter::repeat_n(0, zeros))
+        .chain((len as u64 * 8).to_be_bytes());
+
+    let mut block = [0; 64];
+    let mut filled = 0;
+    for byte in parts.iter().copied().flatten().copied().chain(padding) {
+        block[filled] = byte;
+        filled += 1;
+        if filled == block.len() {
+            sha1_block(&mut state, &block);
+            filled = 0;
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn sha1_block(state: &mut [u32; 5], block: &[u8; 64]) {
+    let mut words = [0u32; 80];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes(bytes.try_into().unwrap());
+    }
+    for idx in 16..80 {
+        words[idx] =
+            (words[idx - 3] ^ words[idx - 8] ^ words[idx - 14] ^ words[idx - 16]).rotate_left(1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+    for (idx, &word) in words.iter().enumerate() {
+        let (f, k) = match idx {
+            0..=19 => ((b & c) | (!b & d), 0x5A827999),
+            20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+            _ => (b ^ c ^ d, 0xCA62C1D6),
+        };
+        let temp = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+
+    for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+        *value = value.wrapping_add(add);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32_parts(&[b"1234", b"", b"56789"]), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_sha1() {
+        assert_eq!(
+            sha1(&[b"abc"]),
+            [
+                0xA9, 0x99, 0x3E, 0x36, 0x47, 0x06, 0x81, 0x6A, 0xBA, 0x3E, 0x25, 0x71, 0x78, 0x50,
+                0xC2, 0x6C, 0x9C, 0xD0, 0xD8, 0x9D
+            ]
+        );
+
+        // Split across parts and blocks, with padding spilling into a
+        // second block
+        let data: Vec<u8> = (0..200).map(|idx| idx as u8).collect();
+        let (head, tail) = data.split_at(61);
+        assert_eq!(sha1(&[head, tail]), sha1(&[&data[..]]));
+        let text = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(
+            sha1(&[&text[..30], &text[30..]]),
+            [
+                0x84, 0x98, 0x3E, 0x44, 0x1C, 0x3B, 0xD2, 0x6E, 0xBA, 0xAE, 0x4A, 0xA1, 0xF9, 0x51,
+                0x29, 0xE5, 0xE5, 0x46, 0x70, 0xF1
+            ]
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{
+    eeprom::{Eeprom, EepromChip},
+    read_banked, write_banked, Chr, Mapper,
+};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+// Mappers 16, 153, 157 and 159: Bandai FCG-1/FCG-2 and LZ93D50.
+//
+// Registers are selected by the low four address bits:
+//   $0-$7  1 KiB CHR banks (153: bit 0 selects the 256 KiB PRG outer bank)
+//   $8     16 KiB PRG bank at $8000 ($C000 is the last bank)
+//   $9     mirroring
+//   $A     IRQ control
+//   $B-$C  IRQ counter (FCG) or reload latch (LZ93D50) low/high
+//   $D     EEPROM I2C lines (153: PRG-RAM enable)
+//
+// FCG-1/2 decode the registers at $6000-$7FFF, the LZ93D50 at $8000-$FFFF;
+// mapper 16 dumps exist for both so it listens on both ranges.
+pub struct BandaiFcg {
+    mapper: u16,
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    prg_ram_enabled: bool,
+    battery: bool,
+
+    chr_banks: [u8; 8],
+    prg_bank: u8,
+    outer_bank: u8,
+    mirroring: Mirroring,
+
+    irq_enabled: bool,
+    irq_counter: u16,
+    irq_latch: u16,
+    irq: bool,
+
+    eeprom: Option<Eeprom>,
+}
+
+impl BandaiFcg {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram_size = rom.prg_ram_total();
+        let chr_ram_size = rom.chr_ram_total();
+        let eeprom = match rom.mapper {
+            16 | 157 => Some(Eeprom::new(EepromChip::C24C02)),
+            159 => Some(Eeprom::new(EepromChip::X24C01)),
+            _ => None,
+        };
+        let prg_ram = match rom.mapper {
+            153 => vec![0; prg_ram_size],
+            _ => vec![],
+        };
+
+        Self {
+            mapper: rom.mapper,
+            prg_rom: rom.prg_rom,
+            chr: Chr::new(rom.chr_rom, chr_ram_size),
+            prg_ram,
+            prg_ram_enabled: false,
+            battery: rom.battery,
+            chr_banks: [0; 8],
+            prg_bank: 0,
+            outer_bank: 0,
+            mirroring: rom.screen_mirroring,
+            irq_enabled: false,
+            irq_counter: 0,
+            irq_latch: 0,
+            irq: false,
+            eeprom,
+        }
+    }
+
+    fn prg_bank_base(&self) -> usize {
+        (self.outer_bank as usize) << 4
+    }
+
+    fn last_prg_bank(&self) -> usize {
+        match self.mapper {
+            153 => self.prg_bank_base() | 0x0F,
+            _ => (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1),
+        }
+    }
+
+    fn write_register(&mut self, reg: u8, data: u8, latched_irq: bool) {
+        match reg {
+            0x0..=0x7 => {
+                self.chr_banks[reg as usize] = data;
+                if self.mapper == 153 {
+                    self.outer_bank = data & 0x01;
+                }
+            }
+            0x8 => self.prg_bank = data & 0x0F,
+            0x9 => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            0xA => {
+                self.irq_enabled = data & 0x01 != 0;
+                self.irq = false;
+                if latched_irq {
+                    self.irq_counter = self.irq_latch;
+                }
+            }
+            0xB | 0xC => {
+                let target = if latched_irq {
+                    &mut self.irq_latch
+                } else {
+                    &mut self.irq_counter
+                };
+                *target = if reg == 0xB {
+                    (*target & 0xFF00) | data as u16
+                } else {
+                    (*target & 0x00FF) | (data as u16) << 8
+                };
+            }
+            0xD => match &mut self.eeprom {
+                Some(eeprom) => eeprom.write(data & 0x20 != 0, data & 0x40 != 0),
+                None => self.prg_ram_enabled = data & 0x20 != 0,
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for BandaiFcg {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => match &self.eeprom {
+                Some(eeprom) => (eeprom.read() as u8) << 4,
+                None if self.prg_ram_enabled => read_banked(&self.prg_ram, 0, 0x2000, addr),
+                None => 0,
+            },
+            0x8000..=0xBFFF => {
+                let bank = self.prg_bank_base() | self.prg_bank as usize;
+                read_banked(&self.prg_rom, bank, PRG_BANK_SIZE, addr)
+            }
+            0xC000..=0xFFFF => {
+                read_banked(&self.prg_rom, self.last_prg_bank(), PRG_BANK_SIZE, addr)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.mapper == 153 && self.prg_ram_enabled => {
+                write_banked(&mut self.prg_ram, 0, 0x2000, addr, data)
+            }
+            0x6000..=0x7FFF if self.mapper == 16 => {
+                self.write_register(addr as u8 & 0x0F, data, false)
+            }
+            0x8000..=0xFFFF => self.write_register(addr as u8 & 0x0F, data, true),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        if self.mapper == 153 {
+            // CHR-RAM board, the CHR registers only carry the outer PRG bank.
+            return self.chr.read(0, 0x2000, addr);
+        }
+
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE];
+        self.chr.read(bank as usize, CHR_BANK_SIZE, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.mapper == 153 {
+            return self.chr.write(0, 0x2000, addr, data);
+        }
+
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE];
+        self.chr.write(bank as usize, CHR_BANK_SIZE, addr, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        Some(self.prg_ram.as_mut_slice())
+    }
+
+    fn battery_ram(&mut self) -> Option<&mut [u8]> {
+        match &mut self.eeprom {
+            // The EEPROM is non-volatile regardless of the header battery bit.
+            Some(eeprom) => Some(eeprom.data.as_mut_slice()),
+            None => self.battery.then_some(self.prg_ram.as_mut_slice()),
+        }
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        if !self.irq_enabled {
+            return;
+        }
+
+        for _ in 0..cycles {
+            if self.irq_counter == 0 {
+                self.irq = true;
+            }
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::mapper::test_banked_rom;
+
+    use super::*;
+
+    #[test]
+    fn test_bank_switching() {
+        let mut fcg = BandaiFcg::new(test_banked_rom(16, 8, 2));
+        // 16 KiB banks, so the 8 KiB bank number read back is doubled
+        fcg.cpu_write(0x8008, 2);
+        assert_eq!(fcg.cpu_read(0x8000), 4);
+        assert_eq!(fcg.cpu_read(0xBFFF), 5);
+        assert_eq!(fcg.cpu_read(0xC000), 14);
+
+        // Mapper 16 also decodes the registers at $6000
+        fcg.cpu_write(0x6008, 3);
+        assert_eq!(fcg.cpu_read(0x8000), 6);
+
+        fcg.cpu_write(0x8003, 10);
+        assert_eq!(fcg.ppu_read(0x0C00), 10);
+        fcg.cpu_write(0x6009, 2);
+        assert_eq!(fcg.mirroring(), Mirroring::SingleScreenLower);
+
+        // The LZ93D50 boards do not
+        let mut fcg = BandaiFcg::new(test_banked_rom(159, 8, 2));
+        fcg.cpu_write(0x6008, 3);
+        assert_eq!(fcg.cpu_read(0x8000), 0);
+    }
+
+    #[test]
+    fn test_outer_bank() {
+        let mut fcg = BandaiFcg::new(test_banked_rom(153, 32, 0));
+        fcg.cpu_write(0x8008, 2);
+        assert_eq!(fcg.cpu_read(0x8000), 4);
+        assert_eq!(fcg.cpu_read(0xC000), 30);
+
+        // Bit 0 of any CHR register picks the second 256 KiB
+        fcg.cpu_write(0x8005, 1);
+        assert_eq!(fcg.cpu_read(0x8000), 36);
+        assert_eq!(fcg.cpu_read(0xC000), 62);
+
+        fcg.cpu_write(0x6000, 0x42);
+        assert_eq!(fcg.cpu_read(0x6000), 0);
+        fcg.cpu_write(0x800D, 0x20);
+        fcg.cpu_write(0x6000, 0x42);
+        assert_eq!(fcg.cpu_read(0x6000), 0x42);
+    }
+
+    #[test]
+    fn test_last_bank_of_small_rom() {
+        let mut rom = test_banked_rom(16, 1, 1);
+        rom.prg_rom.truncate(0x2000);
+        let mut fcg = BandaiFcg::new(rom);
+        assert_eq!(fcg.cpu_read(0xC000), 0);
+    }
+
+    #[test]
+    fn test_irq_timing() {
+        // FCG-1/2 write the counter itself
+        let mut fcg = BandaiFcg::new(test_banked_rom(16, 2, 1));
+        fcg.cpu_write(0x600B, 2);
+        fcg.cpu_write(0x600C, 0);
+        fcg.tick(5);
+        assert!(!fcg.irq_pending());
+
+        // Fires on the cycle the counter is found at zero
+        fcg.cpu_write(0x600A, 1);
+        fcg.tick(2);
+        assert!(!fcg.irq_pending());
+        fcg.tick(1);
+        assert!(fcg.irq_pending());
+        fcg.cpu_write(0x600A, 0);
+        assert!(!fcg.irq_pending());
+
+        // The LZ93D50 writes a latch, copied to the counter on enabling
+        let mut fcg = BandaiFcg::new(test_banked_rom(16, 2, 1));
+        fcg.cpu_write(0x800B, 2);
+        fcg.cpu_write(0x800C, 0);
+        assert_eq!(fcg.irq_counter, 0);
+        fcg.cpu_write(0x800A, 1);
+        fcg.cpu_write(0x800B, 100);
+        fcg.tick(2);
+        assert!(!fcg.irq_pending());
+        fcg.tick(1);
+        assert!(fcg.irq_pending());
+    }
+
+    #[test]
+    fn test_eeprom_lines() {
+        fn lines(fcg: &mut BandaiFcg, scl: bool, sda: bool) -> bool {
+            fcg.cpu_write(0x800D, (scl as u8) << 5 | (sda as u8) << 6);
+            fcg.cpu_read(0x6000) & 0x10 != 0
+        }
+
+        // A start condition and the 24C02's device address, which the chip
+        // acknowledges by pulling SDA low, read back on bit 4
+        let mut fcg = BandaiFcg::new(test_banked_rom(16, 2, 1));
+        lines(&mut fcg, true, true);
+        lines(&mut fcg, true, false);
+        for bit in (0..8).rev() {
+            let sda = 0xA0 & (1 << bit) != 0;
+            lines(&mut fcg, false, sda);
+            lines(&mut fcg, true, sda);
+        }
+        lines(&mut fcg, false, true);
+        assert!(!lines(&mut fcg, true, true));
+    }
+}
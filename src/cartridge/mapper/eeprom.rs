@@ -0,0 +1,318 @@
+// Serial I2C EEPROMs found on Bandai FCG boards.
+//
+// The 24C02 (256 bytes) speaks standard I2C: a device address byte, a word
+// address byte and then data, all MSB first. The X24C01 (128 bytes) skips
+// the device address and sends a 7-bit word address plus the R/W bit,
+// LSB first.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EepromChip {
+    X24C01,
+    C24C02,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Phase {
+    Standby,
+    DeviceAddress,
+    WordAddress,
+    WriteData,
+    ReadData,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Transfer {
+    Receive,
+    AckOut,
+    Send,
+    AckIn,
+}
+
+pub struct Eeprom {
+    chip: EepromChip,
+    pub data: Vec<u8>,
+
+    phase: Phase,
+    next_phase: Phase,
+    transfer: Transfer,
+    shift: u8,
+    bits: u8,
+    address: u8,
+
+    scl: bool,
+    sda: bool,
+    output: bool,
+}
+
+impl Eeprom {
+    pub fn new(chip: EepromChip) -> Self {
+        let size = match chip {
+            EepromChip::X24C01 => 128,
+            EepromChip::C24C02 => 256,
+        };
+
+        Self {
+            chip,
+            data: vec![0; size],
+            phase: Phase::Standby,
+            next_phase: Phase::Standby,
+            transfer: Transfer::Receive,
+            shift: 0,
+            bits: 0,
+            address: 0,
+            scl: false,
+            sda: false,
+            output: true,
+        }
+    }
+
+    pub fn read(&self) -> bool {
+        self.output
+    }
+
+    pub fn write(&mut self, scl: bool, sda: bool) {
+        if self.scl && scl {
+            match (self.sda, sda) {
+                (true, false) => self.start(),
+                (false, true) => self.stop(),
+                _ => (),
+            }
+        } else if !self.scl && scl {
+            self.clock(sda);
+        }
+
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    fn start(&mut self) {
+        self.phase = match self.chip {
+            EepromChip::X24C01 => Phase::WordAddress,
+            EepromChip::C24C02 => Phase::DeviceAddress,
+        };
+        self.transfer = Transfer::Receive;
+        self.shift = 0;
+        self.bits = 0;
+        self.output = true;
+    }
+
+    fn stop(&mut self) {
+        self.phase = Phase::Standby;
+        self.output = true;
+    }
+
+    fn clock(&mut self, sda: bool) {
+        if self.phase == Phase::Standby {
+            return;
+        }
+
+        match self.transfer {
+            Transfer::Receive => {
+                self.output = true;
+                self.shift = match self.chip {
+                    EepromChip::X24C01 => (self.shift >> 1) | (sda as u8) << 7,
+                    EepromChip::C24C02 => (self.shift << 1) | sda as u8,
+                };
+                self.bits += 1;
+                if self.bits == 8 {
+                    self.byte_received();
+                }
+            }
+            Transfer::AckOut => {
+                self.output = false;
+                self.phase = self.next_phase;
+                self.bits = 0;
+                if self.phase == Phase::ReadData {
+                    self.shift = self.data[self.address as usize];
+                    self.transfer = Transfer::Send;
+                } else {
+                    self.transfer = Transfer::Receive;
+                }
+            }
+            Transfer::Send => {
+                self.output = match self.chip {
+                    EepromChip::X24C01 => self.shift & (1 << self.bits) != 0,
+                    EepromChip::C24C02 => self.shift & (0x80 >> self.bits) != 0,
+                };
+                self.bits += 1;
+                if self.bits == 8 {
+                    self.transfer = Transfer::AckIn;
+                }
+            }
+            Transfer::AckIn => {
+                self.output = true;
+                if sda {
+                    // No acknowledge from the master ends the read.
+                    self.phase = Phase::Standby;
+                } else {
+                    self.address = self.wrap_address(self.address.wrapping_add(1), 0xFF);
+                    self.shift = self.data[self.address as usize];
+                    self.transfer = Transfer::Send;
+                    self.bits = 0;
+                }
+            }
+        }
+    }
+
+    fn byte_received(&mut self) {
+        let byte = self.shift;
+        self.transfer = Transfer::AckOut;
+
+        self.next_phase = match (self.chip, self.phase) {
+            (EepromChip::C24C02, Phase::DeviceAddress) => {
+                if byte & 0xF0 != 0xA0 {
+                    // Not addressed to us, do not acknowledge.
+                    self.phase = Phase::Standby;
+                    return;
+                }
+
+                if byte & 0x01 != 0 {
+                    Phase::ReadData
+                } else {
+                    Phase::WordAddress
+                }
+            }
+            (EepromChip::C24C02, Phase::WordAddress) => {
+                self.address = byte;
+                Phase::WriteData
+            }
+            (EepromChip::X24C01, Phase::WordAddress) => {
+                self.address = byte & 0x7F;
+                if byte & 0x80 != 0 {
+                    Phase::ReadData
+                } else {
+                    Phase::WriteData
+                }
+            }
+            (_, Phase::WriteData) => {
+                self.data[self.address as usize] = byte;
+                // Page writes wrap inside the current page.
+                let page_mask = match self.chip {
+                    EepromChip::X24C01 => 0x03,
+                    EepromChip::C24C02 => 0x07,
+                };
+                self.address = self.wrap_address(self.address.wrapping_add(1), page_mask)
+                    | (self.address & !page_mask);
+                Phase::WriteData
+            }
+            _ => Phase::Standby,
+        };
+    }
+
+    fn wrap_address(&self, addr: u8, mask: u8) -> u8 {
+        addr & mask & (self.data.len() - 1) as u8
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Drives the clock and data lines the way the game does through the
+    // board's register, leaving SCL high after each step
+    fn start(eeprom: &mut Eeprom) {
+        eeprom.write(false, true);
+        eeprom.write(true, true);
+        eeprom.write(true, false);
+    }
+
+    fn stop(eeprom: &mut Eeprom) {
+        eeprom.write(false, false);
+        eeprom.write(true, false);
+        eeprom.write(true, true);
+    }
+
+    fn clock(eeprom: &mut Eeprom, sda: bool) -> bool {
+        eeprom.write(false, sda);
+        eeprom.write(true, sda);
+        eeprom.read()
+    }
+
+    // Sends a byte and returns whether the chip acknowledged it
+    fn send(eeprom: &mut Eeprom, byte: u8) -> bool {
+        for bit in bit_order(eeprom) {
+            clock(eeprom, byte & bit != 0);
+        }
+        !clock(eeprom, true)
+    }
+
+    fn receive(eeprom: &mut Eeprom, ack: bool) -> u8 {
+        let mut byte = 0;
+        for bit in bit_order(eeprom) {
+            if clock(eeprom, true) {
+                byte |= bit;
+            }
+        }
+        clock(eeprom, !ack);
+        byte
+    }
+
+    fn bit_order(eeprom: &Eeprom) -> Vec<u8> {
+        match eeprom.chip {
+            EepromChip::X24C01 => (0..8).map(|bit| 1 << bit).collect(),
+            EepromChip::C24C02 => (0..8).rev().map(|bit| 1 << bit).collect(),
+        }
+    }
+
+    #[test]
+    fn test_24c02_write_and_read() {
+        let mut eeprom = Eeprom::new(EepromChip::C24C02);
+        start(&mut eeprom);
+        assert!(send(&mut eeprom, 0xA0));
+        assert!(send(&mut eeprom, 0x10));
+        assert!(send(&mut eeprom, 0x12));
+        assert!(send(&mut eeprom, 0x34));
+        stop(&mut eeprom);
+        assert_eq!(eeprom.data[0x10..0x12], [0x12, 0x34]);
+
+        // Random read: set the address with a write, then restart to read
+        start(&mut eeprom);
+        assert!(send(&mut eeprom, 0xA0));
+        assert!(send(&mut eeprom, 0x10));
+        start(&mut eeprom);
+        assert!(send(&mut eeprom, 0xA1));
+        assert_eq!(receive(&mut eeprom, true), 0x12);
+        assert_eq!(receive(&mut eeprom, false), 0x34);
+        stop(&mut eeprom);
+        assert!(eeprom.read());
+    }
+
+    #[test]
+    fn test_24c02_page_wrap() {
+        let mut eeprom = Eeprom::new(EepromChip::C24C02);
+        start(&mut eeprom);
+        assert!(send(&mut eeprom, 0xA0));
+        assert!(send(&mut eeprom, 0x0F));
+        assert!(send(&mut eeprom, 0x01));
+        assert!(send(&mut eeprom, 0x02));
+        stop(&mut eeprom);
+        assert_eq!(eeprom.data[0x0F], 0x01);
+        assert_eq!(eeprom.data[0x08], 0x02);
+        assert_eq!(eeprom.data[0x10], 0x00);
+    }
+
+    #[test]
+    fn test_24c02_other_device() {
+        let mut eeprom = Eeprom::new(EepromChip::C24C02);
+        start(&mut eeprom);
+        assert!(!send(&mut eeprom, 0xB0));
+        assert!(!send(&mut eeprom, 0x00));
+        stop(&mut eeprom);
+        assert!(eeprom.data.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_x24c01_write_and_read() {
+        let mut eeprom = Eeprom::new(EepromChip::X24C01);
+        start(&mut eeprom);
+        assert!(send(&mut eeprom, 0x05));
+        assert!(send(&mut eeprom, 0xAB));
+        stop(&mut eeprom);
+        assert_eq!(eeprom.data[0x05], 0xAB);
+
+        // The address byte carries the read bit, no device address
+        start(&mut eeprom);
+        assert!(send(&mut eeprom, 0x80 | 0x05));
+        assert_eq!(receive(&mut eeprom, false), 0xAB);
+        stop(&mut eeprom);
+    }
+}
@@ -0,0 +1,455 @@
+use crate::cartridge::{fds::crc_step, Mirroring, Rom};
+
+use super::{fds_audio::FdsAudio, Chr, Mapper};
+
+// CPU cycles per byte at the drive's ~96.4 kbit/s transfer rate
+const BYTE_CYCLES: u32 = 149;
+// Time the head takes to return to the start of the disk
+const REWIND_CYCLES: u32 = 50000;
+
+// Famicom Disk System RAM adapter and drive (iNES mapper 20).
+//
+//   $4020-$4021  timer IRQ reload low/high
+//   $4022        timer IRQ control (bit 0 repeat, bit 1 enable)
+//   $4023        master I/O enable (bit 0 disk, bit 1 sound)
+//   $4024        write data
+//   $4025        control (motor, transfer reset, read/write mode,
+//                mirroring, CRC, transfer start, transfer IRQ)
+//   $4030-$4033  status, read data, drive status, external connector
+//   $4040-$4097  wavetable audio
+//   $6000-$DFFF  32 KiB PRG-RAM
+//   $E000-$FFFF  BIOS
+pub struct Fds {
+    bios: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+
+    disk_sides: Vec<Vec<u8>>,
+    side: Option<usize>,
+
+    disk_io_enabled: bool,
+    sound_io_enabled: bool,
+
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_repeat: bool,
+    timer_enabled: bool,
+    timer_irq: bool,
+
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    mirroring: Mirroring,
+    crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+
+    disk_position: usize,
+    delay: u32,
+    scanning: bool,
+    end_of_head: bool,
+    gap_ended: bool,
+    previous_crc_control: bool,
+    crc: u16,
+    read_data: u8,
+    write_data: u8,
+    transfer_complete: bool,
+    disk_irq: bool,
+
+    audio: FdsAudio,
+}
+
+impl Fds {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram_size = rom.prg_ram_total();
+        let chr_ram_size = rom.chr_ram_total();
+        let side = (!rom.disk_sides.is_empty()).then_some(0);
+
+        Self {
+            bios: rom.prg_rom,
+            prg_ram: vec![0; prg_ram_size],
+            chr: Chr::new(rom.chr_rom, chr_ram_size),
+            disk_sides: rom.disk_sides,
+            side,
+            disk_io_enabled: false,
+            sound_io_enabled: false,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: false,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            mirroring: rom.screen_mirroring,
+            crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            disk_position: 0,
+            delay: 0,
+            scanning: false,
+            end_of_head: true,
+            gap_ended: false,
+            previous_crc_control: false,
+            crc: 0,
+            read_data: 0,
+            write_data: 0,
+            transfer_complete: false,
+            disk_irq: false,
+            audio: FdsAudio::new(),
+        }
+    }
+
+    fn write_control(&mut self, data: u8) {
+        self.motor_on = data & 0x01 != 0;
+        self.reset_transfer = data & 0x02 != 0;
+        self.read_mode = data & 0x04 != 0;
+        self.mirroring = if data & 0x08 != 0 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        };
+        self.crc_control = data & 0x10 != 0;
+        self.disk_ready = data & 0x40 != 0;
+        self.disk_irq_enabled = data & 0x80 != 0;
+        self.disk_irq = false;
+    }
+
+    fn tick_timer(&mut self) {
+        if !self.timer_enabled || !self.disk_io_enabled {
+            return;
+        }
+
+        if self.timer_counter == 0 {
+            self.timer_irq = true;
+            self.timer_counter = self.timer_reload;
+            if !self.timer_repeat {
+                self.timer_enabled = false;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    fn tick_drive(&mut self) {
+        let Some(side) = self.side else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+        if !self.motor_on {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        }
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+
+        if self.end_of_head {
+            self.delay = REWIND_CYCLES;
+            self.end_of_head = false;
+            self.disk_position = 0;
+            self.gap_ended = false;
+            return;
+        }
+
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let mut irq = self.disk_irq_enabled;
+
+        if self.read_mode {
+            let data = self.disk_sides[side][self.disk_position];
+
+            if !self.disk_ready {
+                self.gap_ended = false;
+            } else if data != 0 && !self.gap_ended {
+                // The start mark is readable but raises no IRQ
+                self.gap_ended = true;
+                irq = false;
+            }
+
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                self.disk_irq |= irq;
+            }
+        } else {
+            let mut data = self.write_data;
+            if !self.crc_control {
+                self.transfer_complete = true;
+                self.disk_irq |= irq;
+            }
+
+            if !self.disk_ready {
+                data = 0;
+                self.crc = 0;
+            }
+
+            if self.crc_control {
+                if !self.previous_crc_control {
+                    self.crc = crc_step(crc_step(self.crc, 0), 0);
+                }
+                data = self.crc as u8;
+                self.crc >>= 8;
+            } else if self.disk_ready {
+                self.crc = crc_step(self.crc, data);
+            }
+
+            self.disk_sides[side][self.disk_position] = data;
+            self.gap_ended = false;
+        }
+
+        self.previous_crc_control = self.crc_control;
+        self.disk_position += 1;
+        if self.disk_position >= self.disk_sides[side].len() {
+            self.motor_on = false;
+            self.end_of_head = true;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+
+    fn disk_status(&mut self) -> u8 {
+        let status = self.timer_irq as u8
+            | (self.transfer_complete as u8) << 1
+            | (self.end_of_head as u8) << 6
+            | (self.disk_io_enabled as u8) << 7;
+
+        self.timer_irq = false;
+        self.transfer_complete = false;
+        self.disk_irq = false;
+        status
+    }
+
+    fn drive_status(&self) -> u8 {
+        let inserted = self.side.is_some();
+        let ready = inserted && self.scanning;
+
+        (!inserted as u8) | (!ready as u8) << 1 | (!inserted as u8) << 2
+    }
+}
+
+impl Mapper for Fds {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4030 if self.disk_io_enabled => self.disk_status(),
+            0x4031 if self.disk_io_enabled => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                self.read_data
+            }
+            0x4032 if self.disk_io_enabled => self.drive_status(),
+            // Battery good, nothing on the expansion port
+            0x4033 if self.disk_io_enabled => 0x80,
+            0x4040..=0x4097 if self.sound_io_enabled => self.audio.read(addr),
+            0x6000..=0xDFFF => self.prg_ram[addr as usize - 0x6000],
+            0xE000..=0xFFFF => self.bios[addr as usize - 0xE000],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4023 => {
+                self.disk_io_enabled = data & 0x01 != 0;
+                self.sound_io_enabled = data & 0x02 != 0;
+                if !self.disk_io_enabled {
+                    self.timer_enabled = false;
+                    self.timer_irq = false;
+                }
+            }
+            0x4020..=0x4026 if !self.disk_io_enabled => {}
+            0x4020 => self.timer_reload = (self.timer_reload & 0xFF00) | data as u16,
+            0x4021 => self.timer_reload = (self.timer_reload & 0x00FF) | (data as u16) << 8,
+            0x4022 => {
+                self.timer_repeat = data & 0x01 != 0;
+                self.timer_enabled = data & 0x02 != 0;
+                if self.timer_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4024 => {
+                self.write_data = data;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 => self.write_control(data),
+            0x4040..=0x4097 if self.sound_io_enabled => self.audio.write(addr, data),
+            0x6000..=0xDFFF => self.prg_ram[addr as usize - 0x6000] = data,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(0, 0x2000, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(0, 0x2000, addr, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        Some(self.prg_ram.as_mut_slice())
+    }
+
+    fn disk_side_count(&self) -> usize {
+        self.disk_sides.len()
+    }
+
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.side = side.filter(|&side| side < self.disk_sides.len());
+        self.end_of_head = true;
+        self.scanning = false;
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.tick_timer();
+            self.tick_drive();
+            self.audio.tick();
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::{
+        fds::{DISK_TAG, SIDE_SIZE},
+        mapper, test_image, RomError,
+    };
+
+    use super::*;
+
+    // A two-sided disk whose disk info blocks differ in the byte after
+    // the tag, the manufacturer code
+    fn two_sided_fds() -> Fds {
+        let mut image = vec![];
+        for side in 0..2 {
+            let mut data = vec![0; SIDE_SIZE];
+            data[..DISK_TAG.len()].copy_from_slice(DISK_TAG);
+            data[DISK_TAG.len()] = 0xA0 + side;
+            image.extend(data);
+        }
+
+        Fds::new(Rom::from_fds(&image, &[0; 0x2000]).unwrap())
+    }
+
+    // Spins the drive until `count` bytes have been read
+    fn read_bytes(fds: &mut Fds, count: usize) -> Vec<u8> {
+        let mut bytes = vec![];
+        for _ in 0..1_000_000 {
+            fds.tick(1);
+            if fds.transfer_complete {
+                bytes.push(fds.cpu_read(0x4031));
+                if bytes.len() == count {
+                    break;
+                }
+            }
+        }
+        bytes
+    }
+
+    fn start_reading(fds: &mut Fds) {
+        fds.cpu_write(0x4023, 0x01);
+        // Motor on, read mode, past the gap
+        fds.cpu_write(0x4025, 0x45);
+    }
+
+    #[test]
+    fn test_only_for_disk_images() {
+        let rom = Rom::new(&test_image(20, 1, 1, 0)).unwrap();
+        assert!(matches!(
+            mapper::new(rom),
+            Err(RomError::UnsupportedMapper(20))
+        ));
+    }
+
+    #[test]
+    fn test_timer_irq() {
+        let mut fds = two_sided_fds();
+
+        // Ignored while disk I/O is disabled
+        fds.cpu_write(0x4020, 3);
+        fds.cpu_write(0x4022, 0x02);
+        fds.tick(10);
+        assert!(!fds.irq_pending());
+
+        fds.cpu_write(0x4023, 0x01);
+        fds.cpu_write(0x4020, 3);
+        fds.cpu_write(0x4021, 0);
+        fds.cpu_write(0x4022, 0x02);
+        fds.tick(3);
+        assert!(!fds.irq_pending());
+        fds.tick(1);
+        assert!(fds.irq_pending());
+
+        // Reading the status acknowledges it, and a one-shot timer stops
+        assert_eq!(fds.cpu_read(0x4030) & 0x01, 0x01);
+        assert!(!fds.irq_pending());
+        fds.tick(20);
+        assert!(!fds.irq_pending());
+
+        // Repeating, every reload + 1 cycles
+        fds.cpu_write(0x4022, 0x03);
+        for _ in 0..3 {
+            fds.tick(3);
+            assert!(!fds.irq_pending());
+            fds.tick(1);
+            assert!(fds.irq_pending());
+            fds.cpu_read(0x4030);
+        }
+
+        // Turning disk I/O off stops the timer and drops the IRQ
+        fds.tick(4);
+        fds.cpu_write(0x4023, 0x00);
+        assert!(!fds.irq_pending());
+        fds.tick(20);
+        assert!(!fds.irq_pending());
+    }
+
+    #[test]
+    fn test_side_switching() {
+        let mut fds = two_sided_fds();
+        assert_eq!(fds.disk_side_count(), 2);
+        start_reading(&mut fds);
+        // The start mark, then the disk info block
+        let side_a = read_bytes(&mut fds, DISK_TAG.len() + 2);
+        assert_eq!(side_a[0], 0x80);
+        assert_eq!(side_a[1..=DISK_TAG.len()], *DISK_TAG);
+        assert_eq!(side_a[DISK_TAG.len() + 1], 0xA0);
+
+        // Ejected: no disk, not ready
+        fds.insert_disk(None);
+        assert_eq!(fds.cpu_read(0x4032) & 0x07, 0x07);
+
+        // The other side reads from its start
+        fds.insert_disk(Some(1));
+        assert_eq!(fds.cpu_read(0x4032) & 0x01, 0x00);
+        start_reading(&mut fds);
+        let side_b = read_bytes(&mut fds, DISK_TAG.len() + 2);
+        assert_eq!(side_b[DISK_TAG.len() + 1], 0xA1);
+
+        // A side the disk does not have ejects it
+        fds.insert_disk(Some(2));
+        assert_eq!(fds.cpu_read(0x4032) & 0x01, 0x01);
+    }
+}
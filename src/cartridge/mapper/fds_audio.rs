@@ -0,0 +1,244 @@
+// FDS wavetable channel: a 64-step, 6-bit wave played back at a pitch that
+// is bent by a second modulation table, with volume and modulation
+// envelopes.
+//
+//   $4040-$407F  wave RAM (writable while $4089 bit 7 is set)
+//   $4080        volume envelope
+//   $4082-$4083  wave frequency, wave/envelope halt
+//   $4084        modulation envelope
+//   $4085        modulation counter
+//   $4086-$4087  modulation frequency, modulation halt
+//   $4088        modulation table write
+//   $4089        wave RAM write enable, master volume
+//   $408A        envelope speed
+//   $4090/$4092  volume/modulation gain (read)
+pub struct FdsAudio {
+    wave_ram: [u8; 64],
+    mod_table: [u8; 64],
+
+    wave_write: bool,
+    master_volume: u8,
+    envelope_speed: u8,
+    envelopes_halted: bool,
+
+    wave_freq: u16,
+    wave_halted: bool,
+    wave_accum: u32,
+    wave_pos: usize,
+
+    mod_freq: u16,
+    mod_halted: bool,
+    mod_accum: u32,
+    mod_pos: usize,
+    mod_counter: i8,
+
+    volume: Envelope,
+    modulation: Envelope,
+
+    output: u8,
+}
+
+#[derive(Default)]
+struct Envelope {
+    speed: u8,
+    increase: bool,
+    disabled: bool,
+    gain: u8,
+    counter: u32,
+}
+
+impl Envelope {
+    fn write(&mut self, data: u8) {
+        self.speed = data & 0x3F;
+        self.increase = data & 0x40 != 0;
+        self.disabled = data & 0x80 != 0;
+        self.counter = 0;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+    }
+
+    fn tick(&mut self, master_speed: u8) {
+        if self.disabled {
+            return;
+        }
+
+        self.counter += 1;
+        if self.counter < 8 * (self.speed as u32 + 1) * master_speed as u32 {
+            return;
+        }
+        self.counter = 0;
+
+        if self.increase && self.gain < 32 {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        Self {
+            wave_ram: [0; 64],
+            mod_table: [0; 64],
+            wave_write: false,
+            master_volume: 0,
+            envelope_speed: 0xE8,
+            envelopes_halted: false,
+            wave_freq: 0,
+            wave_halted: true,
+            wave_accum: 0,
+            wave_pos: 0,
+            mod_freq: 0,
+            mod_halted: true,
+            mod_accum: 0,
+            mod_pos: 0,
+            mod_counter: 0,
+            volume: Envelope::default(),
+            modulation: Envelope::default(),
+            output: 0,
+        }
+    }
+
+    pub fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x407F => self.wave_ram[addr as usize - 0x4040] | 0x40,
+            0x4090 => self.volume.gain | 0x40,
+            0x4092 => self.modulation.gain | 0x40,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x407F if self.wave_write => {
+                self.wave_ram[addr as usize - 0x4040] = data & 0x3F
+            }
+            0x4080 => self.volume.write(data),
+            0x4082 => self.wave_freq = (self.wave_freq & 0x0F00) | data as u16,
+            0x4083 => {
+                self.wave_freq = (self.wave_freq & 0x00FF) | ((data & 0x0F) as u16) << 8;
+                self.wave_halted = data & 0x80 != 0;
+                self.envelopes_halted = data & 0x40 != 0;
+                if self.wave_halted {
+                    self.wave_accum = 0;
+                    self.wave_pos = 0;
+                }
+            }
+            0x4084 => self.modulation.write(data),
+            // 7-bit signed counter
+            0x4085 => self.mod_counter = ((data & 0x7F) << 1) as i8 >> 1,
+            0x4086 => self.mod_freq = (self.mod_freq & 0x0F00) | data as u16,
+            0x4087 => {
+                self.mod_freq = (self.mod_freq & 0x00FF) | ((data & 0x0F) as u16) << 8;
+                self.mod_halted = data & 0x80 != 0;
+                if self.mod_halted {
+                    self.mod_accum = 0;
+                }
+            }
+            0x4088 if self.mod_halted => {
+                self.mod_table[self.mod_pos] = data & 0x07;
+                self.mod_table[(self.mod_pos + 1) & 0x3F] = data & 0x07;
+                self.mod_pos = (self.mod_pos + 2) & 0x3F;
+            }
+            0x4089 => {
+                self.wave_write = data & 0x80 != 0;
+                self.master_volume = data & 0x03;
+            }
+            0x408A => self.envelope_speed = data,
+            _ => {}
+        }
+    }
+
+    pub fn tick(&mut self) {
+        if !self.envelopes_halted && !self.wave_halted && self.envelope_speed != 0 {
+            self.volume.tick(self.envelope_speed);
+            self.modulation.tick(self.envelope_speed);
+        }
+
+        if !self.mod_halted && self.mod_freq != 0 {
+            self.mod_accum += self.mod_freq as u32;
+            if self.mod_accum >= 0x10000 {
+                self.mod_accum -= 0x10000;
+                self.step_modulation();
+            }
+        }
+
+        if !self.wave_halted && !self.wave_write {
+            self.wave_accum += self.pitch() as u32;
+            if self.wave_accum >= 0x10000 {
+                self.wave_accum -= 0x10000;
+                self.wave_pos = (self.wave_pos + 1) & 0x3F;
+            }
+            self.output = self.wave_ram[self.wave_pos];
+        }
+    }
+
+    fn step_modulation(&mut self) {
+        let step = self.mod_table[self.mod_pos];
+        self.mod_pos = (self.mod_pos + 1) & 0x3F;
+
+        let counter = self.mod_counter as i16
+            + match step {
+                0 => 0,
+                1 => 1,
+                2 => 2,
+                3 => 4,
+                5 => -4,
+                6 => -2,
+                7 => -1,
+                _ => {
+                    self.mod_counter = 0;
+                    return;
+                }
+            };
+
+        // Wrap into the 7-bit signed range
+        self.mod_counter = (((counter as u8) & 0x7F) << 1) as i8 >> 1;
+    }
+
+    // Wave frequency bent by the modulation unit, see the nesdev wiki's
+    // description of the FDS pitch calculation.
+    fn pitch(&self) -> u16 {
+        if self.mod_halted || self.mod_freq == 0 {
+            return self.wave_freq;
+        }
+
+        let mut temp = self.mod_counter as i32 * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        let mut temp = self.wave_freq as i32 * temp;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+
+        (self.wave_freq as i32 + temp).clamp(0, 0xFFFF) as u16
+    }
+
+    pub fn output(&self) -> f32 {
+        // Master volume scales by 2/2, 2/3, 2/4 and 2/5
+        let master = 2.0 / (self.master_volume as f32 + 2.0);
+        let gain = self.volume.gain.min(32) as f32;
+
+        self.output as f32 * gain * master / (63.0 * 32.0)
+    }
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,253 @@
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{read_banked, sunsoft5b::Sunsoft5b, write_banked, Chr, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+// Mapper 69: Sunsoft FME-7 and its 5A/5B variants.
+//
+// $8000-$9FFF selects one of 16 internal registers, $A000-$BFFF writes it:
+//   $0-$7  1 KiB CHR banks
+//   $8     $6000 bank (bit 7 RAM enable, bit 6 RAM/ROM select)
+//   $9-$B  8 KiB PRG banks at $8000, $A000, $C000 ($E000 is the last bank)
+//   $C     mirroring
+//   $D     IRQ control (bit 0 IRQ enable, bit 7 counter enable)
+//   $E-$F  IRQ counter low/high
+// $C000-$FFFF is the 5B audio register select/write pair.
+pub struct Fme7 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    battery: bool,
+
+    command: u8,
+    chr_banks: [u8; 8],
+    prg_banks: [u8; 4],
+    mirroring: Mirroring,
+
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq: bool,
+
+    audio: Sunsoft5b,
+}
+
+impl Fme7 {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram_size = rom.prg_ram_total();
+        let chr_ram_size = rom.chr_ram_total();
+        Self {
+            prg_rom: rom.prg_rom,
+            chr: Chr::new(rom.chr_rom, chr_ram_size),
+            prg_ram: vec![0; prg_ram_size],
+            battery: rom.battery,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            mirroring: rom.screen_mirroring,
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq: false,
+            audio: Sunsoft5b::new(),
+        }
+    }
+
+    fn last_prg_bank(&self) -> usize {
+        (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1)
+    }
+
+    fn write_parameter(&mut self, data: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = data,
+            0x8..=0xB => self.prg_banks[self.command as usize - 0x8] = data,
+            0xC => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            0xD => {
+                self.irq_enabled = data & 0x01 != 0;
+                self.irq_counter_enabled = data & 0x80 != 0;
+                self.irq = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | data as u16,
+            0xF => self.irq_counter = (self.irq_counter & 0x00FF) | (data as u16) << 8,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => {
+                let bank = self.prg_banks[0];
+                match (bank & 0x40 != 0, bank & 0x80 != 0) {
+                    (true, true) => {
+                        read_banked(&self.prg_ram, (bank & 0x3F) as usize, PRG_BANK_SIZE, addr)
+                    }
+                    (true, false) => 0, // open bus
+                    (false, _) => {
+                        read_banked(&self.prg_rom, (bank & 0x3F) as usize, PRG_BANK_SIZE, addr)
+                    }
+                }
+            }
+            0x8000..=0xDFFF => {
+                let bank = self.prg_banks[1 + (addr as usize - 0x8000) / PRG_BANK_SIZE];
+                read_banked(&self.prg_rom, (bank & 0x3F) as usize, PRG_BANK_SIZE, addr)
+            }
+            0xE000..=0xFFFF => {
+                read_banked(&self.prg_rom, self.last_prg_bank(), PRG_BANK_SIZE, addr)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => {
+                let bank = self.prg_banks[0];
+                if bank & 0xC0 == 0xC0 {
+                    let bank = (bank & 0x3F) as usize;
+                    write_banked(&mut self.prg_ram, bank, PRG_BANK_SIZE, addr, data);
+                }
+            }
+            0x8000..=0x9FFF => self.command = data & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(data),
+            0xC000..=0xDFFF => self.audio.select(data),
+            0xE000..=0xFFFF => self.audio.write(data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE];
+        self.chr.read(bank as usize, CHR_BANK_SIZE, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE];
+        self.chr.write(bank as usize, CHR_BANK_SIZE, addr, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        Some(self.prg_ram.as_mut_slice())
+    }
+
+    fn battery_ram(&mut self) -> Option<&mut [u8]> {
+        self.battery.then_some(self.prg_ram.as_mut_slice())
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            if self.irq_counter_enabled {
+                self.irq_counter = self.irq_counter.wrapping_sub(1);
+                if self.irq_counter == 0xFFFF && self.irq_enabled {
+                    self.irq = true;
+                }
+            }
+
+            self.audio.tick();
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::mapper::test_banked_rom;
+
+    use super::*;
+
+    fn write_register(fme7: &mut Fme7, command: u8, data: u8) {
+        fme7.cpu_write(0x8000, command);
+        fme7.cpu_write(0xA000, data);
+    }
+
+    #[test]
+    fn test_bank_switching() {
+        let mut fme7 = Fme7::new(test_banked_rom(69, 8, 1));
+        write_register(&mut fme7, 0x9, 5);
+        write_register(&mut fme7, 0xA, 6);
+        write_register(&mut fme7, 0xB, 7);
+        assert_eq!(fme7.cpu_read(0x8000), 5);
+        assert_eq!(fme7.cpu_read(0xA000), 6);
+        assert_eq!(fme7.cpu_read(0xDFFF), 7);
+        assert_eq!(fme7.cpu_read(0xE000), 15);
+
+        write_register(&mut fme7, 0x3, 6);
+        assert_eq!(fme7.ppu_read(0x0C00), 6);
+        assert_eq!(fme7.ppu_read(0x1000), 0);
+
+        // $6000 maps ROM, then RAM once bits 6 and 7 are set
+        write_register(&mut fme7, 0x8, 0x02);
+        assert_eq!(fme7.cpu_read(0x6000), 2);
+        write_register(&mut fme7, 0x8, 0xC0);
+        fme7.cpu_write(0x6000, 0x42);
+        assert_eq!(fme7.cpu_read(0x6000), 0x42);
+        write_register(&mut fme7, 0x8, 0x40);
+        assert_eq!(fme7.cpu_read(0x6000), 0);
+
+        write_register(&mut fme7, 0xC, 1);
+        assert_eq!(fme7.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_last_bank_of_small_rom() {
+        let mut fme7 = Fme7::new(test_banked_rom(69, 1, 1));
+        assert_eq!(fme7.cpu_read(0xE000), 1);
+
+        let mut rom = test_banked_rom(69, 1, 1);
+        rom.prg_rom.truncate(0x1000);
+        let mut fme7 = Fme7::new(rom);
+        assert_eq!(fme7.cpu_read(0xE000), 0);
+    }
+
+    #[test]
+    fn test_irq_timing() {
+        let mut fme7 = Fme7::new(test_banked_rom(69, 2, 1));
+        write_register(&mut fme7, 0xE, 3);
+        write_register(&mut fme7, 0xF, 0);
+
+        // Counting without the IRQ enabled
+        write_register(&mut fme7, 0xD, 0x80);
+        fme7.tick(4);
+        assert!(!fme7.irq_pending());
+        assert_eq!(fme7.irq_counter, 0xFFFF);
+
+        // Fires on the cycle the counter wraps from 0 to $FFFF
+        write_register(&mut fme7, 0xE, 3);
+        write_register(&mut fme7, 0xF, 0);
+        write_register(&mut fme7, 0xD, 0x81);
+        fme7.tick(3);
+        assert!(!fme7.irq_pending());
+        fme7.tick(1);
+        assert!(fme7.irq_pending());
+
+        // Writing the control register acknowledges it
+        write_register(&mut fme7, 0xD, 0x81);
+        assert!(!fme7.irq_pending());
+
+        write_register(&mut fme7, 0xE, 0);
+        write_register(&mut fme7, 0xD, 0x01);
+        fme7.tick(10);
+        assert!(!fme7.irq_pending());
+    }
+}
@@ -0,0 +1,149 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::cartridge::{fds::FDS_MAPPER, HeaderFormat, Mirroring, Rom, RomError};
+
+mod bandai_fcg;
+mod eeprom;
+mod fds;
+pub(crate) mod fds_audio;
+mod fme7;
+mod namco163;
+pub(crate) mod namco163_audio;
+mod nrom;
+pub(crate) mod sunsoft5b;
+
+pub use bandai_fcg::BandaiFcg;
+pub use fds::Fds;
+pub use fme7::Fme7;
+pub use namco163::Namco163;
+pub use nrom::Nrom;
+
+pub type MapperRef = Rc<RefCell<dyn Mapper>>;
+
+// Cartridge board as seen by the Bus (CPU $4020-$FFFF) and the PPU
+// (pattern tables at $0000-$1FFF).
+pub trait Mapper {
+    fn cpu_read(&mut self, addr: u16) -> u8;
+    fn cpu_write(&mut self, addr: u16, data: u8);
+    fn ppu_read(&mut self, addr: u16) -> u8;
+    fn ppu_write(&mut self, addr: u16, data: u8);
+    fn mirroring(&self) -> Mirroring;
+
+    // Called by the Bus with the number of CPU cycles that have passed: one
+    // at a time when the CPU is cycle-stepped, otherwise a whole
+    // instruction's worth at once. Drives IRQ counters and expansion audio.
+    fn tick(&mut self, _cycles: u8) {}
+
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    // Work RAM mapped at $6000-$7FFF, if the board has any.
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    // Battery-backed memory persisted to the .sav file, if the board has any.
+    fn battery_ram(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    // Disk drive of the Famicom Disk System; `None` ejects the disk.
+    fn disk_side_count(&self) -> usize {
+        0
+    }
+
+    fn insert_disk(&mut self, _side: Option<usize>) {}
+
+    // Expansion audio output in the -1.0..=1.0 range, mixed in by the Bus.
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+}
+
+pub fn new(rom: Rom) -> Result<MapperRef, RomError> {
+    let mapper: MapperRef = match rom.mapper {
+        0 => Rc::new(RefCell::new(Nrom::new(rom))),
+        16 | 153 | 157 | 159 => Rc::new(RefCell::new(BandaiFcg::new(rom))),
+        19 => Rc::new(RefCell::new(Namco163::new(rom))),
+        // Only disk images carry the disks and BIOS the RAM adapter needs
+        FDS_MAPPER if rom.format == HeaderFormat::Fds => Rc::new(RefCell::new(Fds::new(rom))),
+        69 => Rc::new(RefCell::new(Fme7::new(rom))),
+        mapper => return Err(RomError::UnsupportedMapper(mapper)),
+    };
+
+    Ok(mapper)
+}
+
+// Offset of `bank` of `size` bytes inside `mem`, wrapping banks that are
+// out of range the same way unconnected address lines do on the board.
+pub(crate) fn bank_offset(mem: &[u8], bank: usize, size: usize) -> usize {
+    let banks = (mem.len() / size).max(1);
+    (bank % banks) * size
+}
+
+pub(crate) fn read_banked(mem: &[u8], bank: usize, size: usize, addr: u16) -> u8 {
+    if mem.is_empty() {
+        return 0;
+    }
+
+    mem[(bank_offset(mem, bank, size) + (addr as usize % size)) % mem.len()]
+}
+
+pub(crate) fn write_banked(mem: &mut [u8], bank: usize, size: usize, addr: u16, data: u8) {
+    if mem.is_empty() {
+        return;
+    }
+
+    let idx = (bank_offset(mem, bank, size) + (addr as usize % size)) % mem.len();
+    mem[idx] = data;
+}
+
+// Pattern table memory of a board: the CHR-ROM from the image, or CHR-RAM
+// when the cartridge has none. Writes to CHR-ROM are ignored.
+pub(crate) struct Chr {
+    mem: Vec<u8>,
+    writable: bool,
+}
+
+impl Chr {
+    pub fn new(chr_rom: Vec<u8>, chr_ram_size: usize) -> Self {
+        if chr_rom.is_empty() {
+            Self {
+                mem: vec![0; chr_ram_size],
+                writable: true,
+            }
+        } else {
+            Self {
+                mem: chr_rom,
+                writable: false,
+            }
+        }
+    }
+
+    pub fn read(&self, bank: usize, size: usize, addr: u16) -> u8 {
+        read_banked(&self.mem, bank, size, addr)
+    }
+
+    pub fn write(&mut self, bank: usize, size: usize, addr: u16, data: u8) {
+        if self.writable {
+            write_banked(&mut self.mem, bank, size, addr, data);
+        }
+    }
+}
+
+// A cartridge for `mapper` with the given 16 KiB PRG and 8 KiB CHR page
+// counts, where every 8 KiB of PRG and every 1 KiB of CHR is filled with
+// its own bank number
+#[cfg(test)]
+pub(crate) fn test_banked_rom(mapper: u8, prg_pages: u8, chr_pages: u8) -> Rom {
+    let raw = crate::cartridge::test_image(mapper, prg_pages, chr_pages, 0);
+    let mut rom = Rom::new(&raw).unwrap();
+    for (idx, byte) in rom.prg_rom.iter_mut().enumerate() {
+        *byte = (idx / 0x2000) as u8;
+    }
+    for (idx, byte) in rom.chr_rom.iter_mut().enumerate() {
+        *byte = (idx / 0x0400) as u8;
+    }
+    rom
+}
@@ -0,0 +1,251 @@
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{namco163_audio::Namco163Audio, read_banked, write_banked, Chr, Mapper};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+// Mapper 19: Namco 163 (and the pin-compatible 129).
+//
+//   $4800-$4FFF  internal 128-byte RAM data port
+//   $5000-$5FFF  15-bit IRQ counter (bit 7 of the high byte enables IRQ)
+//   $6000-$7FFF  PRG-RAM
+//   $8000-$BFFF  1 KiB CHR banks
+//   $C000-$DFFF  nametable banks, $E0-$FF select CIRAM pages
+//   $E000-$F7FF  8 KiB PRG banks at $8000, $A000, $C000 ($E000 is fixed)
+//   $F800-$FFFF  internal RAM address port (bit 7 auto-increment)
+pub struct Namco163 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    audio: Namco163Audio,
+
+    chr_banks: [u8; 8],
+    nametable_banks: [u8; 4],
+    prg_banks: [u8; 3],
+    mirroring: Mirroring,
+
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq: bool,
+
+    sound_disabled: bool,
+}
+
+impl Namco163 {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram_size = rom.prg_ram_total();
+        let chr_ram_size = rom.chr_ram_total();
+        let nametable_banks = match rom.screen_mirroring {
+            Mirroring::Horizontal => [0xE0, 0xE0, 0xE1, 0xE1],
+            _ => [0xE0, 0xE1, 0xE0, 0xE1],
+        };
+
+        Self {
+            prg_rom: rom.prg_rom,
+            chr: Chr::new(rom.chr_rom, chr_ram_size),
+            prg_ram: vec![0; prg_ram_size],
+            battery: rom.battery,
+            audio: Namco163Audio::new(),
+            chr_banks: [0; 8],
+            nametable_banks,
+            prg_banks: [0; 3],
+            mirroring: rom.screen_mirroring,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq: false,
+            sound_disabled: false,
+        }
+    }
+
+    fn last_prg_bank(&self) -> usize {
+        (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1)
+    }
+
+    // The PPU only knows the four standard arrangements, so nametable
+    // banks pointing at CHR-ROM are not supported; CIRAM page selections
+    // are folded back into a mirroring mode.
+    fn update_mirroring(&mut self) {
+        if self.nametable_banks.iter().any(|&bank| bank < 0xE0) {
+            return;
+        }
+
+        let pages = self.nametable_banks.map(|bank| bank & 0x01);
+        self.mirroring = match pages {
+            [0, 1, 0, 1] => Mirroring::Vertical,
+            [0, 0, 1, 1] => Mirroring::Horizontal,
+            [0, 0, 0, 0] => Mirroring::SingleScreenLower,
+            [1, 1, 1, 1] => Mirroring::SingleScreenUpper,
+            _ => self.mirroring,
+        };
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => self.audio.read(),
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7,
+            0x6000..=0x7FFF => read_banked(&self.prg_ram, 0, 0x2000, addr),
+            0x8000..=0xDFFF => {
+                let bank = self.prg_banks[(addr as usize - 0x8000) / PRG_BANK_SIZE];
+                read_banked(&self.prg_rom, bank as usize, PRG_BANK_SIZE, addr)
+            }
+            0xE000..=0xFFFF => {
+                read_banked(&self.prg_rom, self.last_prg_bank(), PRG_BANK_SIZE, addr)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4800..=0x4FFF => self.audio.write(data),
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | data as u16;
+                self.irq = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | ((data & 0x7F) as u16) << 8;
+                self.irq_enabled = data & 0x80 != 0;
+                self.irq = false;
+            }
+            0x6000..=0x7FFF => write_banked(&mut self.prg_ram, 0, 0x2000, addr, data),
+            0x8000..=0xBFFF => self.chr_banks[(addr as usize - 0x8000) / 0x800] = data,
+            0xC000..=0xDFFF => {
+                self.nametable_banks[(addr as usize - 0xC000) / 0x800] = data;
+                self.update_mirroring();
+            }
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = data & 0x3F;
+                self.sound_disabled = data & 0x40 != 0;
+            }
+            0xE800..=0xEFFF => self.prg_banks[1] = data & 0x3F,
+            0xF000..=0xF7FF => self.prg_banks[2] = data & 0x3F,
+            0xF800..=0xFFFF => self.audio.set_addr(data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE];
+        self.chr.read(bank as usize, CHR_BANK_SIZE, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE];
+        self.chr.write(bank as usize, CHR_BANK_SIZE, addr, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        Some(self.prg_ram.as_mut_slice())
+    }
+
+    fn battery_ram(&mut self) -> Option<&mut [u8]> {
+        self.battery.then_some(self.prg_ram.as_mut_slice())
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            if self.irq_enabled && self.irq_counter < 0x7FFF {
+                self.irq_counter += 1;
+                if self.irq_counter == 0x7FFF {
+                    self.irq = true;
+                }
+            }
+
+            self.audio.tick();
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq
+    }
+
+    fn audio_output(&self) -> f32 {
+        if self.sound_disabled {
+            return 0.0;
+        }
+
+        self.audio.output()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::mapper::test_banked_rom;
+
+    use super::*;
+
+    #[test]
+    fn test_bank_switching() {
+        let mut n163 = Namco163::new(test_banked_rom(19, 8, 2));
+        n163.cpu_write(0xE000, 3);
+        n163.cpu_write(0xE800, 4);
+        n163.cpu_write(0xF000, 5);
+        assert_eq!(n163.cpu_read(0x8000), 3);
+        assert_eq!(n163.cpu_read(0xA000), 4);
+        assert_eq!(n163.cpu_read(0xC000), 5);
+        assert_eq!(n163.cpu_read(0xE000), 15);
+
+        n163.cpu_write(0x8800, 9);
+        n163.cpu_write(0xB800, 12);
+        assert_eq!(n163.ppu_read(0x0400), 9);
+        assert_eq!(n163.ppu_read(0x1FFF), 12);
+
+        for addr in [0xC000, 0xC800, 0xD000, 0xD800] {
+            n163.cpu_write(addr, 0xE1);
+        }
+        assert_eq!(n163.mirroring(), Mirroring::SingleScreenUpper);
+        // Arrangements the PPU has no mode for leave it as it was
+        n163.cpu_write(0xC800, 0xE0);
+        n163.cpu_write(0xD800, 0xE0);
+        assert_eq!(n163.mirroring(), Mirroring::SingleScreenUpper);
+        n163.cpu_write(0xC000, 0xE0);
+        n163.cpu_write(0xC800, 0xE1);
+        n163.cpu_write(0xD000, 0xE0);
+        n163.cpu_write(0xD800, 0xE1);
+        assert_eq!(n163.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_last_bank_of_small_rom() {
+        let mut rom = test_banked_rom(19, 1, 1);
+        rom.prg_rom.truncate(0x1000);
+        let mut n163 = Namco163::new(rom);
+        assert_eq!(n163.cpu_read(0xE000), 0);
+    }
+
+    #[test]
+    fn test_irq_timing() {
+        let mut n163 = Namco163::new(test_banked_rom(19, 2, 1));
+        n163.cpu_write(0x5000, 0xFD);
+        n163.cpu_write(0x5800, 0xFF);
+        assert_eq!(n163.cpu_read(0x5000), 0xFD);
+        assert_eq!(n163.cpu_read(0x5800), 0xFF);
+
+        // Counts up and fires on reaching $7FFF, where it stops
+        n163.tick(1);
+        assert!(!n163.irq_pending());
+        n163.tick(1);
+        assert!(n163.irq_pending());
+        n163.tick(5);
+        assert_eq!(n163.irq_counter, 0x7FFF);
+
+        // Writing either counter byte acknowledges it
+        n163.cpu_write(0x5000, 0x00);
+        assert!(!n163.irq_pending());
+
+        // Stopped while bit 7 of the high byte is clear
+        n163.cpu_write(0x5800, 0x7F);
+        n163.tick(10);
+        assert_eq!(n163.irq_counter, 0x7F00);
+        assert!(!n163.irq_pending());
+    }
+}
@@ -0,0 +1,102 @@
+// Wavetable channels are serviced round-robin, one every 15 CPU cycles.
+const CHANNEL_UPDATE_CYCLES: u8 = 15;
+
+// Namco 163 internal RAM and wavetable sound. The CPU reaches the RAM
+// through an address port ($F800) and a data port ($4800).
+//
+// The upper 64 bytes of the internal RAM hold up to eight wavetable
+// channels, eight bytes each, starting with channel 0 at $40.
+pub struct Namco163Audio {
+    internal_ram: [u8; 128],
+    ram_addr: u8,
+
+    cycles: u8,
+    channel: usize,
+    channel_outputs: [f32; 8],
+}
+
+impl Namco163Audio {
+    pub fn new() -> Self {
+        Self {
+            internal_ram: [0; 128],
+            ram_addr: 0,
+            cycles: 0,
+            channel: 7,
+            channel_outputs: [0.0; 8],
+        }
+    }
+
+    // Bit 7 of the address enables auto-increment after each data access
+    pub fn set_addr(&mut self, data: u8) {
+        self.ram_addr = data;
+    }
+
+    fn access_internal_ram(&mut self) -> usize {
+        let idx = (self.ram_addr & 0x7F) as usize;
+        if self.ram_addr & 0x80 != 0 {
+            self.ram_addr = 0x80 | (self.ram_addr.wrapping_add(1) & 0x7F);
+        }
+        idx
+    }
+
+    pub fn read(&mut self) -> u8 {
+        let idx = self.access_internal_ram();
+        self.internal_ram[idx]
+    }
+
+    pub fn write(&mut self, data: u8) {
+        let idx = self.access_internal_ram();
+        self.internal_ram[idx] = data;
+    }
+
+    fn enabled_channels(&self) -> usize {
+        ((self.internal_ram[0x7F] >> 4) & 0x07) as usize + 1
+    }
+
+    fn update_channel(&mut self, channel: usize) {
+        let base = 0x40 + channel * 8;
+        let ram = &mut self.internal_ram;
+
+        let freq = u32::from_le_bytes([ram[base], ram[base + 2], ram[base + 4] & 0x03, 0]);
+        let phase = u32::from_le_bytes([ram[base + 1], ram[base + 3], ram[base + 5], 0]);
+        let length = 256 - (ram[base + 4] & 0xFC) as u32;
+        let offset = ram[base + 6] as u32;
+        let volume = (ram[base + 7] & 0x0F) as f32;
+
+        let phase = (phase + freq) % (length << 16);
+        let [phase_lo, phase_mid, phase_hi, _] = phase.to_le_bytes();
+        ram[base + 1] = phase_lo;
+        ram[base + 3] = phase_mid;
+        ram[base + 5] = phase_hi;
+
+        let sample_addr = (((phase >> 16) + offset) & 0xFF) as usize;
+        let sample = (ram[sample_addr >> 1] >> ((sample_addr & 1) * 4)) & 0x0F;
+
+        self.channel_outputs[channel] = (sample as f32 - 8.0) * volume;
+    }
+
+    // Clocked every CPU cycle
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+        if self.cycles < CHANNEL_UPDATE_CYCLES {
+            return;
+        }
+        self.cycles = 0;
+
+        let first = 8 - self.enabled_channels();
+        self.update_channel(self.channel);
+        self.channel = if self.channel <= first {
+            7
+        } else {
+            self.channel - 1
+        };
+    }
+
+    pub fn output(&self) -> f32 {
+        // Channels are time-multiplexed on a single DAC, so the effective
+        // level is the average of the enabled ones.
+        let enabled = self.enabled_channels();
+        let sum: f32 = self.channel_outputs[8 - enabled..].iter().sum();
+        sum / enabled as f32 / 120.0
+    }
+}
@@ -0,0 +1,101 @@
+use crate::cartridge::{Mirroring, Rom};
+
+use super::{read_banked, write_banked, Chr, Mapper};
+
+// Mapper 0: 16 or 32 KiB of PRG-ROM, 8 KiB of CHR and optional PRG-RAM at
+// $6000-$7FFF, no banking.
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram_size = rom.prg_ram_total();
+        let chr_ram_size = rom.chr_ram_total();
+        Self {
+            prg_rom: rom.prg_rom,
+            chr: Chr::new(rom.chr_rom, chr_ram_size),
+            prg_ram: vec![0; prg_ram_size],
+            battery: rom.battery,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => read_banked(&self.prg_ram, 0, 0x2000, addr),
+            0x8000..=0xFFFF => {
+                // Smaller ROMs repeat through the 32 KiB window
+                let mask = self.prg_rom.len().saturating_sub(1);
+                let addr = (addr - 0x8000) as usize & mask;
+                self.prg_rom.get(addr).copied().unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        // Writes to ROM go nowhere
+        if let 0x6000..=0x7FFF = addr {
+            write_banked(&mut self.prg_ram, 0, 0x2000, addr, data);
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr.read(0, 0x2000, addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr.write(0, 0x2000, addr, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        Some(self.prg_ram.as_mut_slice())
+    }
+
+    fn battery_ram(&mut self) -> Option<&mut [u8]> {
+        self.battery.then_some(self.prg_ram.as_mut_slice())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::test_rom;
+
+    use super::*;
+
+    #[test]
+    fn test_prg_mirroring() {
+        for size in [0x2000, 0x4000, 0x8000] {
+            let mut rom = test_rom(&[], &[]);
+            rom.prg_rom = (0..size).map(|i| (i / 0x1000) as u8).collect();
+            let mut nrom = Nrom::new(rom);
+
+            for addr in [0x8000, 0x9FFF, 0xA000, 0xC000, 0xFFFF] {
+                let expected = ((addr - 0x8000) as usize % size / 0x1000) as u8;
+                assert_eq!(
+                    nrom.cpu_read(addr),
+                    expected,
+                    "{size:#X} bytes at {addr:04X}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rom_writes_ignored() {
+        let mut nrom = Nrom::new(test_rom(&[0xEA], &[]));
+        nrom.cpu_write(0x8000, 0x42);
+        assert_eq!(nrom.cpu_read(0x8000), 0xEA);
+    }
+}
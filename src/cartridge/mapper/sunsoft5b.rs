@@ -0,0 +1,187 @@
+// Sunsoft 5B expansion audio, a licensed YM2149F (AY-3-8910 compatible):
+// three square tone channels, one noise generator and a shared envelope.
+//
+//   $0-$5  tone period A/B/C (12 bit, low/high)
+//   $6     noise period (5 bit)
+//   $7     mixer, bits 0-2 disable tone A/B/C, bits 3-5 disable noise A/B/C
+//   $8-$A  channel volume A/B/C (bit 4 selects the envelope)
+//   $B-$C  envelope period low/high
+//   $D     envelope shape (continue, attack, alternate, hold)
+pub struct Sunsoft5b {
+    select: u8,
+    write_enabled: bool,
+    regs: [u8; 16],
+
+    divider: u8,
+    half_rate: bool,
+
+    tone_counters: [u16; 3],
+    tone_outputs: [bool; 3],
+
+    noise_counter: u8,
+    noise_lfsr: u32,
+
+    env_counter: u16,
+    env_step: u8,
+    env_attack: bool,
+    env_holding: bool,
+}
+
+impl Sunsoft5b {
+    pub fn new() -> Self {
+        Self {
+            select: 0,
+            write_enabled: true,
+            regs: [0; 16],
+            divider: 0,
+            half_rate: false,
+            tone_counters: [0; 3],
+            tone_outputs: [false; 3],
+            noise_counter: 0,
+            noise_lfsr: 1,
+            env_counter: 0,
+            env_step: 0,
+            env_attack: false,
+            env_holding: false,
+        }
+    }
+
+    pub fn select(&mut self, data: u8) {
+        // Writes with any of the upper four bits set disable the data port.
+        self.write_enabled = data & 0xF0 == 0;
+        self.select = data & 0x0F;
+    }
+
+    pub fn write(&mut self, data: u8) {
+        if !self.write_enabled {
+            return;
+        }
+
+        self.regs[self.select as usize] = data;
+        if self.select == 0xD {
+            self.env_step = 0;
+            self.env_counter = 0;
+            self.env_holding = false;
+            self.env_attack = data & 0x04 != 0;
+        }
+    }
+
+    pub fn tick(&mut self) {
+        // Tone generators run at CPU/16, noise and envelope at CPU/32.
+        self.divider += 1;
+        if self.divider < 16 {
+            return;
+        }
+        self.divider = 0;
+
+        for channel in 0..3 {
+            let period = self.tone_period(channel).max(1);
+            self.tone_counters[channel] += 1;
+            if self.tone_counters[channel] >= period {
+                self.tone_counters[channel] = 0;
+                self.tone_outputs[channel] = !self.tone_outputs[channel];
+            }
+        }
+
+        self.half_rate = !self.half_rate;
+        if !self.half_rate {
+            return;
+        }
+
+        self.noise_counter += 1;
+        if self.noise_counter >= (self.regs[6] & 0x1F).max(1) {
+            self.noise_counter = 0;
+            let bit = (self.noise_lfsr ^ (self.noise_lfsr >> 3)) & 1;
+            self.noise_lfsr = (self.noise_lfsr >> 1) | (bit << 16);
+        }
+
+        let env_period = u16::from_le_bytes([self.regs[0xB], self.regs[0xC]]).max(1);
+        self.env_counter += 1;
+        if self.env_counter >= env_period {
+            self.env_counter = 0;
+            self.step_envelope();
+        }
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        u16::from_le_bytes([self.regs[channel * 2], self.regs[channel * 2 + 1] & 0x0F])
+    }
+
+    fn step_envelope(&mut self) {
+        if self.env_holding {
+            return;
+        }
+
+        self.env_step += 1;
+        if self.env_step < 32 {
+            return;
+        }
+
+        let shape = self.regs[0xD];
+        let (cont, alternate, hold) = (shape & 0x08 != 0, shape & 0x02 != 0, shape & 0x01 != 0);
+
+        if !cont {
+            self.env_holding = true;
+            self.env_attack = false;
+            self.env_step = 31;
+        } else if hold {
+            self.env_holding = true;
+            self.env_attack ^= alternate;
+            self.env_step = 31;
+        } else {
+            self.env_attack ^= alternate;
+            self.env_step = 0;
+        }
+    }
+
+    fn envelope_level(&self) -> u8 {
+        if self.env_attack {
+            self.env_step
+        } else {
+            31 - self.env_step
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        let mixer = self.regs[7];
+        let noise = self.noise_lfsr & 1 != 0;
+
+        let sum: f32 = (0..3)
+            .map(|channel| {
+                let tone_off = mixer & (1 << channel) != 0;
+                let noise_off = mixer & (1 << (channel + 3)) != 0;
+                if !((self.tone_outputs[channel] || tone_off) && (noise || noise_off)) {
+                    return 0.0;
+                }
+
+                let volume = self.regs[8 + channel];
+                let level = if volume & 0x10 != 0 {
+                    self.envelope_level()
+                } else if volume & 0x0F == 0 {
+                    0
+                } else {
+                    (volume & 0x0F) * 2 + 1
+                };
+
+                volume_amplitude(level)
+            })
+            .sum();
+
+        sum / 3.0
+    }
+}
+
+impl Default for Sunsoft5b {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The 5B DAC is logarithmic: 1.5 dB per envelope step, 3 dB per volume step.
+fn volume_amplitude(level: u8) -> f32 {
+    if level == 0 {
+        return 0.0;
+    }
+
+    10f32.powf((level as f32 - 31.0) * 1.5 / 20.0)
+}
@@ -1,43 +1,143 @@
+mod archive;
+mod error;
+mod fds;
+pub mod gamedb;
+pub(crate) mod hash;
+pub mod mapper;
+pub mod patch;
+pub mod save;
+mod unif;
+
+pub use error::RomError;
+pub use patch::PatchError;
+
+use std::{fs, path::Path};
+
 const NES_TAG: &[u8] = &[0x4E, 0x45, 0x53, 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
 const PRG_ROM_PAGE_SIZE: usize = 0x4000;
 const CHR_ROM_PAGE_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+const PRG_RAM_PAGE_SIZE: usize = 0x2000;
+// Larger than any real cartridge or disk image; sizes read from patches
+// and archives are checked against it before anything is allocated
+pub(crate) const MAX_ROM_SIZE: usize = 64 * 1024 * 1024;
+
+// Magic numbers of other dump formats, recognised to report them precisely
+const FOREIGN_TAGS: &[(&[u8], &str)] = &[(b"NESM\x1A", "NSF"), (b"NSFE", "NSFe")];
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Mirroring {
     Vertical,
     Horizontal,
     FourScreen,
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HeaderFormat {
+    INes,
+    Nes20,
+    Unif,
+    Fds,
+}
+
+// CPU/PPU timing the cartridge was made for
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConsoleType {
+    Nes,
+    // PPU model and hardware type, see the NES 2.0 Vs. System byte
+    VsSystem { ppu: u8, hardware: u8 },
+    Playchoice10,
+    // Famiclones, VT0x, etc. identified by the NES 2.0 extended console type
+    Extended(u8),
 }
 
 pub struct Rom {
+    pub format: HeaderFormat,
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
-    pub mapper: u8,
+    // Cartridges without CHR-ROM carry writable CHR-RAM instead
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub battery: bool,
+    pub mapper: u16,
+    pub submapper: u8,
     pub screen_mirroring: Mirroring,
+    pub timing: Timing,
+    pub console_type: ConsoleType,
+    // NES 2.0 default expansion device id (0 = unspecified)
+    pub expansion_device: u8,
+    pub misc_roms: u8,
+    // 512 bytes the Bus loads to $7000-$71FF on power-up
+    pub trainer: Option<Vec<u8>>,
+    // Famicom Disk System sides, as the drive sees them (with gaps and CRCs)
+    pub disk_sides: Vec<Vec<u8>>,
+}
+
+// Reads a dump from disk, unpacking it first if it is zipped or gzipped.
+pub fn read_image<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, RomError> {
+    archive::unpack(fs::read(path)?)
 }
 
 impl Rom {
-    pub fn new(raw: &Vec<u8>) -> Result<Rom, String> {
-        if &raw[0..4] != NES_TAG {
-            return Err("File is not in iNES format".into());
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Rom, RomError> {
+        Rom::new(&read_image(path)?)
+    }
+
+    // Applies IPS/UPS/BPS patches in order to the raw image, then parses it.
+    pub fn with_patches<P: AsRef<[u8]>>(raw: &[u8], patches: &[P]) -> Result<Rom, RomError> {
+        let mut image = raw.to_vec();
+        for (index, patch) in patches.iter().enumerate() {
+            image = patch::apply(&image, patch.as_ref())
+                .map_err(|error| RomError::BadPatch { index, error })?;
         }
 
-        let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
-        let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
+        Rom::new(&image)
+    }
+
+    pub fn new(raw: &[u8]) -> Result<Rom, RomError> {
+        if raw.starts_with(unif::UNIF_TAG) {
+            return unif::parse(raw);
+        }
+        if raw.starts_with(fds::FWNES_TAG) || raw.starts_with(fds::DISK_TAG) {
+            return Err(RomError::MissingBios);
+        }
+        if !raw.starts_with(NES_TAG) {
+            return Err(
+                match FOREIGN_TAGS.iter().find(|(tag, _)| raw.starts_with(tag)) {
+                    Some((_, format)) => RomError::UnsupportedFormat(format),
+                    None => RomError::BadMagic,
+                },
+            );
+        }
+        if raw.len() < HEADER_SIZE {
+            return Err(RomError::TruncatedHeader { len: raw.len() });
+        }
 
         let control_byte_1 = raw[6];
         let control_byte_2 = raw[7];
 
-        let _size_prg_8kb = raw[8];
+        let format = match (control_byte_2 >> 2) & 0x03 {
+            2 => HeaderFormat::Nes20,
+            _ => HeaderFormat::INes,
+        };
 
         // cb1 contains 4 lower bits of mapper type
         // cb2 contains 4 upper bits of mapper type
-        let mapper = (control_byte_2 & 0xF0) | (control_byte_1 >> 4);
-
-        let version = (control_byte_2 >> 2) & 0x03;
-        if version != 0 {
-            return Err("Doesn't currently support iNES 2.0 format".into());
-        }
+        let mut mapper = ((control_byte_2 & 0xF0) | (control_byte_1 >> 4)) as u16;
 
         let four_screen = control_byte_1 & 0x08 != 0;
         let vertical_mirroring = control_byte_1 & 0x01 != 0;
@@ -47,20 +147,356 @@ impl Rom {
             (false, false) => Mirroring::Horizontal,
         };
 
-        let skip_trainer = control_byte_1 & 0x04 != 0;
+        let battery = control_byte_1 & 0x02 != 0;
+        let has_trainer = control_byte_1 & 0x04 != 0;
 
-        let prg_rom_start = 16 + if skip_trainer { 512 } else { 0 };
-        let chr_rom_start = prg_rom_start + prg_rom_size;
-
-        Ok(Rom {
-            prg_rom: raw[prg_rom_start..(prg_rom_start + prg_rom_size)].into(),
-            chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].into(),
+        let mut rom = Rom {
+            format,
+            prg_rom: vec![],
+            chr_rom: vec![],
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            battery,
             mapper,
+            submapper: 0,
             screen_mirroring,
-        })
+            timing: Timing::Ntsc,
+            console_type: ConsoleType::Nes,
+            expansion_device: 0,
+            misc_roms: 0,
+            trainer: None,
+            disk_sides: vec![],
+        };
+
+        let (prg_rom_size, chr_rom_size) = match format {
+            HeaderFormat::Nes20 => {
+                mapper |= ((raw[8] & 0x0F) as u16) << 8;
+                rom.mapper = mapper;
+                rom.submapper = raw[8] >> 4;
+
+                rom.prg_ram_size = shift_size(raw[10] & 0x0F);
+                rom.prg_nvram_size = shift_size(raw[10] >> 4);
+                rom.chr_ram_size = shift_size(raw[11] & 0x0F);
+                rom.chr_nvram_size = shift_size(raw[11] >> 4);
+
+                rom.timing = match raw[12] & 0x03 {
+                    0 => Timing::Ntsc,
+                    1 => Timing::Pal,
+                    2 => Timing::MultiRegion,
+                    _ => Timing::Dendy,
+                };
+                rom.console_type = match control_byte_2 & 0x03 {
+                    0 => ConsoleType::Nes,
+                    1 => ConsoleType::VsSystem {
+                        ppu: raw[13] & 0x0F,
+                        hardware: raw[13] >> 4,
+                    },
+                    2 => ConsoleType::Playchoice10,
+                    _ => ConsoleType::Extended(raw[13] & 0x0F),
+                };
+                rom.misc_roms = raw[14] & 0x03;
+                rom.expansion_device = raw[15] & 0x3F;
+
+                (
+                    nes20_rom_size(raw[4], raw[9] & 0x0F, PRG_ROM_PAGE_SIZE),
+                    nes20_rom_size(raw[5], raw[9] >> 4, CHR_ROM_PAGE_SIZE),
+                )
+            }
+            HeaderFormat::INes => {
+                // Headers with junk in the padding (e.g. "DiskDude!") also
+                // have junk in the upper mapper nibble.
+                if raw[12..16].iter().any(|&byte| byte != 0) {
+                    rom.mapper = mapper & 0x0F;
+                }
+
+                // 0 means 8 KiB for compatibility with headers that predate the field
+                let prg_ram_size = raw[8].max(1) as usize * PRG_RAM_PAGE_SIZE;
+                if battery {
+                    rom.prg_nvram_size = prg_ram_size;
+                } else {
+                    rom.prg_ram_size = prg_ram_size;
+                }
+
+                rom.timing = match raw[9] & 0x01 {
+                    0 => Timing::Ntsc,
+                    _ => Timing::Pal,
+                };
+                rom.console_type = match control_byte_2 & 0x03 {
+                    1 => ConsoleType::VsSystem {
+                        ppu: 0,
+                        hardware: 0,
+                    },
+                    2 => ConsoleType::Playchoice10,
+                    _ => ConsoleType::Nes,
+                };
+
+                (
+                    raw[4] as usize * PRG_ROM_PAGE_SIZE,
+                    raw[5] as usize * CHR_ROM_PAGE_SIZE,
+                )
+            }
+            HeaderFormat::Unif | HeaderFormat::Fds => unreachable!("not an iNES header"),
+        };
+
+        if format == HeaderFormat::INes && chr_rom_size == 0 {
+            rom.chr_ram_size = CHR_RAM_SIZE;
+        }
+
+        if has_trainer {
+            let trainer = raw.get(HEADER_SIZE..HEADER_SIZE + TRAINER_SIZE).ok_or(
+                RomError::TruncatedTrainer {
+                    len: raw.len() - HEADER_SIZE,
+                },
+            )?;
+            rom.trainer = Some(trainer.to_vec());
+        }
+
+        let prg_rom_start = HEADER_SIZE + if has_trainer { TRAINER_SIZE } else { 0 };
+        let prg = raw.get(prg_rom_start..).unwrap_or_default();
+        if prg.len() < prg_rom_size {
+            return Err(RomError::TruncatedPrg {
+                expected: prg_rom_size,
+                actual: prg.len(),
+            });
+        }
+
+        let chr = &prg[prg_rom_size..];
+        if chr.len() < chr_rom_size {
+            return Err(RomError::TruncatedChr {
+                expected: chr_rom_size,
+                actual: chr.len(),
+            });
+        }
+
+        rom.prg_rom = prg[..prg_rom_size].into();
+        rom.chr_rom = chr[..chr_rom_size].into();
+
+        // Plain iNES headers are often wrong; NES 2.0 ones are trusted.
+        if format == HeaderFormat::INes {
+            if let Some(info) = gamedb::GAME_DB.lookup(&rom.prg_rom, &rom.chr_rom) {
+                info.apply(&mut rom);
+            }
+        }
+
+        // The trainer needs somewhere to live even if the header
+        // declares no work RAM.
+        if rom.trainer.is_some() && rom.prg_ram_total() < PRG_RAM_PAGE_SIZE {
+            rom.prg_ram_size = PRG_RAM_PAGE_SIZE - rom.prg_nvram_size;
+        }
+
+        Ok(rom)
+    }
+
+    // Volatile and battery-backed PRG-RAM share the $6000-$7FFF window.
+    pub fn prg_ram_total(&self) -> usize {
+        self.prg_ram_size + self.prg_nvram_size
+    }
+
+    pub fn chr_ram_total(&self) -> usize {
+        self.chr_ram_size + self.chr_nvram_size
+    }
+}
+
+// NES 2.0 ROM sizes are either a 12-bit page count or, when the MSB nibble
+// is $F, an exponent-multiplier pair packed as EEEEEEMM.
+fn nes20_rom_size(lsb: u8, msb: u8, page_size: usize) -> usize {
+    if msb == 0x0F {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        1usize
+            .checked_shl(exponent)
+            .unwrap_or(usize::MAX)
+            .saturating_mul(multiplier)
+    } else {
+        ((msb as usize) << 8 | lsb as usize) * page_size
     }
 }
 
-pub fn test_rom() -> Rom {
-    todo!()
+// NES 2.0 RAM sizes are stored as a shift count: 64 << n bytes, 0 = none.
+fn shift_size(shift: u8) -> usize {
+    match shift {
+        0 => 0,
+        _ => 64 << shift,
+    }
+}
+
+// A blank iNES image for `mapper`, with the given 16 KiB PRG and 8 KiB CHR
+// page counts and flags 6 bits besides the mapper
+#[cfg(test)]
+pub(crate) fn test_image(mapper: u8, prg_pages: u8, chr_pages: u8, flags_6: u8) -> Vec<u8> {
+    let mut raw = NES_TAG.to_vec();
+    raw.extend_from_slice(&[prg_pages, chr_pages, flags_6 | mapper << 4, mapper & 0xF0]);
+    raw.resize(
+        HEADER_SIZE
+            + prg_pages as usize * PRG_ROM_PAGE_SIZE
+            + chr_pages as usize * CHR_ROM_PAGE_SIZE,
+        0,
+    );
+    raw
+}
+
+// An NROM-128 cartridge running `program` from $8000, where the reset
+// vector points, with `chr` at the start of CHR ROM
+#[cfg(test)]
+pub(crate) fn test_rom(program: &[u8], chr: &[u8]) -> Rom {
+    let mut raw = test_image(0, 1, 1, 0);
+
+    let prg = HEADER_SIZE;
+    raw[prg..prg + program.len()].copy_from_slice(program);
+    raw[prg + 0x3FFC..prg + 0x3FFE].copy_from_slice(&0x8000u16.to_le_bytes());
+    let chr_start = prg + PRG_ROM_PAGE_SIZE;
+    raw[chr_start..chr_start + chr.len()].copy_from_slice(chr);
+
+    Rom::new(&raw).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{bus::bus::Bus, cpu::cpu::Mem};
+
+    #[test]
+    fn test_chr_ram_without_chr_rom() {
+        let rom = Rom::new(&test_image(0, 1, 0, 0)).unwrap();
+        assert!(rom.chr_rom.is_empty());
+        assert_eq!(rom.chr_ram_size, CHR_RAM_SIZE);
+
+        let mapper = mapper::new(rom).unwrap();
+        mapper.borrow_mut().ppu_write(0x1234, 0x55);
+        assert_eq!(mapper.borrow_mut().ppu_read(0x1234), 0x55);
+    }
+
+    #[test]
+    fn test_chr_rom_is_read_only() {
+        let rom = Rom::new(&test_image(0, 1, 1, 0)).unwrap();
+        assert_eq!(rom.chr_ram_size, 0);
+
+        let mapper = mapper::new(rom).unwrap();
+        mapper.borrow_mut().ppu_write(0x0010, 0x55);
+        assert_eq!(mapper.borrow_mut().ppu_read(0x0010), 0x00);
+    }
+
+    #[test]
+    fn test_prg_ram_at_6000() {
+        let rom = Rom::new(&test_image(0, 1, 1, 0x02)).unwrap();
+        assert!(rom.battery);
+        assert_eq!(rom.prg_nvram_size, PRG_RAM_PAGE_SIZE);
+
+        let mapper = mapper::new(rom).unwrap();
+        mapper.borrow_mut().cpu_write(0x6000, 0xAA);
+        mapper.borrow_mut().cpu_write(0x7FFF, 0xBB);
+        assert_eq!(mapper.borrow_mut().cpu_read(0x6000), 0xAA);
+        assert_eq!(mapper.borrow_mut().cpu_read(0x7FFF), 0xBB);
+        assert_eq!(mapper.borrow_mut().battery_ram().unwrap()[0x1FFF], 0xBB);
+    }
+
+    #[test]
+    fn test_nes20_header() {
+        let mut raw = test_image(1, 2, 1, 0x03);
+        raw[7] = 0x48 | 0x01; // NES 2.0, Vs. System, mapper bits 4-7
+        raw[8] = 0x21; // submapper 2, mapper bits 8-11
+        raw[10] = 0x70; // 8 KiB PRG-NVRAM
+        raw[11] = 0x07; // 8 KiB CHR-RAM
+        raw[12] = 0x01;
+        raw[13] = 0x34;
+        raw[15] = 0x01;
+
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.format, HeaderFormat::Nes20);
+        assert_eq!(rom.mapper, 0x141);
+        assert_eq!(rom.submapper, 2);
+        assert_eq!(rom.prg_rom.len(), 2 * PRG_ROM_PAGE_SIZE);
+        assert_eq!(rom.chr_rom.len(), CHR_ROM_PAGE_SIZE);
+        assert_eq!(rom.prg_ram_size, 0);
+        assert_eq!(rom.prg_nvram_size, 0x2000);
+        assert_eq!(rom.chr_ram_size, 0x2000);
+        assert_eq!(rom.timing, Timing::Pal);
+        assert_eq!(
+            rom.console_type,
+            ConsoleType::VsSystem {
+                ppu: 4,
+                hardware: 3
+            }
+        );
+        assert_eq!(rom.expansion_device, 1);
+    }
+
+    #[test]
+    fn test_nes20_exponent_multiplier_size() {
+        assert_eq!(
+            nes20_rom_size(0x02, 0x00, PRG_ROM_PAGE_SIZE),
+            2 * PRG_ROM_PAGE_SIZE
+        );
+        assert_eq!(
+            nes20_rom_size(0x02, 0x01, PRG_ROM_PAGE_SIZE),
+            0x102 * PRG_ROM_PAGE_SIZE
+        );
+        // 2^10 * 3
+        assert_eq!(nes20_rom_size(0b0010_1001, 0x0F, PRG_ROM_PAGE_SIZE), 3072);
+    }
+
+    #[test]
+    fn test_trainer_is_kept() {
+        let mut raw = test_image(0, 1, 1, 0x04);
+        raw.splice(HEADER_SIZE..HEADER_SIZE, (0..TRAINER_SIZE).map(|i| i as u8));
+        raw[HEADER_SIZE + TRAINER_SIZE] = 0xEA;
+
+        let rom = Rom::new(&raw).unwrap();
+        let trainer = rom.trainer.as_ref().unwrap();
+        assert_eq!(trainer.len(), TRAINER_SIZE);
+        assert_eq!(trainer[0x1FF], 0xFF);
+        assert_eq!(rom.prg_rom[0], 0xEA);
+
+        let mut bus = Bus::new(rom).unwrap();
+        assert_eq!(bus.mem_read(0x7000), 0x00);
+        assert_eq!(bus.mem_read(0x71FF), 0xFF);
+
+        // Cut short inside the trainer, before any PRG-ROM
+        raw.truncate(HEADER_SIZE + 100);
+        assert_eq!(
+            Rom::new(&raw).err(),
+            Some(RomError::TruncatedTrainer { len: 100 })
+        );
+    }
+
+    #[test]
+    fn test_load_errors() {
+        assert_eq!(Rom::new(b"NOPE").err(), Some(RomError::BadMagic));
+        assert_eq!(
+            Rom::new(b"NESM\x1A\x01").err(),
+            Some(RomError::UnsupportedFormat("NSF"))
+        );
+        assert_eq!(
+            Rom::new(NES_TAG).err(),
+            Some(RomError::TruncatedHeader { len: 4 })
+        );
+
+        let mut raw = test_image(0, 2, 1, 0);
+        raw.truncate(HEADER_SIZE + PRG_ROM_PAGE_SIZE);
+        assert_eq!(
+            Rom::new(&raw).err(),
+            Some(RomError::TruncatedPrg {
+                expected: 2 * PRG_ROM_PAGE_SIZE,
+                actual: PRG_ROM_PAGE_SIZE
+            })
+        );
+
+        let mut raw = test_image(0, 1, 1, 0);
+        raw.pop();
+        assert_eq!(
+            Rom::new(&raw).err(),
+            Some(RomError::TruncatedChr {
+                expected: CHR_ROM_PAGE_SIZE,
+                actual: CHR_ROM_PAGE_SIZE - 1
+            })
+        );
+
+        let rom = Rom::new(&test_image(0xFF, 1, 1, 0)).unwrap();
+        assert_eq!(
+            mapper::new(rom).err(),
+            Some(RomError::UnsupportedMapper(0xFF))
+        );
+    }
 }
@@ -0,0 +1,471 @@
+use std::fmt;
+
+use super::{hash::crc32, MAX_ROM_SIZE};
+
+const IPS_TAG: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const UPS_TAG: &[u8] = b"UPS1";
+const BPS_TAG: &[u8] = b"BPS1";
+// UPS and BPS end with the source, target and patch CRC32s
+const FOOTER_SIZE: usize = 12;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum PatchError {
+    UnknownFormat,
+    // Patch ends in the middle of a record
+    Truncated,
+    // A record reads or writes past the end of the ROM
+    OutOfBounds { offset: usize },
+    // A UPS/BPS number that does not fit in a usize
+    BadNumber { offset: usize },
+    TargetTooLarge { size: usize },
+    SourceSize { expected: usize, actual: usize },
+    SourceChecksum { expected: u32, actual: u32 },
+    TargetChecksum { expected: u32, actual: u32 },
+    PatchChecksum { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "patch is not in IPS, UPS or BPS format"),
+            PatchError::Truncated => write!(f, "patch is truncated"),
+            PatchError::OutOfBounds { offset } => {
+                write!(f, "patch record at offset {offset:#X} is out of bounds")
+            }
+            PatchError::BadNumber { offset } => {
+                write!(f, "patch number at offset {offset:#X} is too large")
+            }
+            PatchError::TargetTooLarge { size } => write!(
+                f,
+                "patched ROM would be {size} bytes, more than the {MAX_ROM_SIZE} allowed"
+            ),
+            PatchError::SourceSize { expected, actual } => {
+                write!(f, "patch expects a {expected} byte ROM, got {actual}")
+            }
+            PatchError::SourceChecksum { expected, actual } => write!(
+                f,
+                "patch expects ROM CRC32 {expected:08X}, got {actual:08X}"
+            ),
+            PatchError::TargetChecksum { expected, actual } => write!(
+                f,
+                "patched ROM CRC32 should be {expected:08X}, got {actual:08X}"
+            ),
+            PatchError::PatchChecksum { expected, actual } => {
+                write!(f, "patch CRC32 should be {expected:08X}, got {actual:08X}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+// Applies an IPS, UPS or BPS patch, picked by its magic number.
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_TAG) {
+        apply_ips(source, patch)
+    } else if patch.starts_with(UPS_TAG) {
+        apply_ups(source, patch)
+    } else if patch.starts_with(BPS_TAG) {
+        apply_bps(source, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.pos.checked_add(len).ok_or(PatchError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(PatchError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16_be(&mut self) -> Result<usize, PatchError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+    }
+
+    fn u24_be(&mut self) -> Result<usize, PatchError> {
+        let bytes = self.bytes(3)?;
+        Ok(u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]) as usize)
+    }
+
+    fn u32_le(&mut self) -> Result<u32, PatchError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    // UPS/BPS variable-length number: 7 bits per byte, low first, with the
+    // high bit marking the last byte and each continuation adding one.
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let start = self.pos;
+        let overflow = || PatchError::BadNumber { offset: start };
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.byte()?;
+            value = ((byte & 0x7F) as usize)
+                .checked_mul(shift)
+                .and_then(|bits| value.checked_add(bits))
+                .ok_or_else(overflow)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(overflow)?;
+            value = value.checked_add(shift).ok_or_else(overflow)?;
+        }
+    }
+}
+
+// IPS: big-endian 24-bit offset and 16-bit size records, size 0 meaning a
+// run of one byte, until "EOF" and an optional 24-bit truncation length.
+fn apply_ips(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut target = source.to_vec();
+    let mut reader = Reader::new(patch, IPS_TAG.len());
+
+    loop {
+        if reader.bytes(3)? == IPS_EOF {
+            break;
+        }
+        reader.pos -= 3;
+
+        let offset = reader.u24_be()?;
+        let (len, fill) = match reader.u16_be()? {
+            0 => {
+                let len = reader.u16_be()?;
+                (len, Some(reader.byte()?))
+            }
+            len => (len, None),
+        };
+
+        if target.len() < offset + len {
+            target.resize(offset + len, 0);
+        }
+        match fill {
+            Some(byte) => target[offset..offset + len].fill(byte),
+            None => target[offset..offset + len].copy_from_slice(reader.bytes(len)?),
+        }
+    }
+
+    if let Ok(len) = reader.u24_be() {
+        target.truncate(len);
+    }
+    Ok(target)
+}
+
+// Checks the CRC32 footer shared by UPS and BPS and returns the target CRC.
+fn check_footer(source: &[u8], patch: &[u8]) -> Result<u32, PatchError> {
+    if patch.len() < FOOTER_SIZE {
+        return Err(PatchError::Truncated);
+    }
+
+    let mut footer = Reader::new(patch, patch.len() - FOOTER_SIZE);
+    let source_crc = footer.u32_le()?;
+    let target_crc = footer.u32_le()?;
+    let patch_crc = footer.u32_le()?;
+
+    let actual = crc32(&patch[..patch.len() - 4]);
+    if actual != patch_crc {
+        return Err(PatchError::PatchChecksum {
+            expected: patch_crc,
+            actual,
+        });
+    }
+
+    let actual = crc32(source);
+    if actual != source_crc {
+        return Err(PatchError::SourceChecksum {
+            expected: source_crc,
+            actual,
+        });
+    }
+
+    Ok(target_crc)
+}
+
+fn check_target(target: &[u8], expected: u32) -> Result<(), PatchError> {
+    let actual = crc32(target);
+    if actual != expected {
+        return Err(PatchError::TargetChecksum { expected, actual });
+    }
+    Ok(())
+}
+
+// UPS: source and target sizes, then hunks of a relative skip followed by
+// bytes XORed onto the ROM up to and including a zero byte.
+fn apply_ups(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_crc = check_footer(source, patch)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = Reader::new(&patch[..end], UPS_TAG.len());
+
+    let source_size = reader.varint()?;
+    let target_size = check_target_size(reader.varint()?)?;
+    if source_size != source.len() {
+        return Err(PatchError::SourceSize {
+            expected: source_size,
+            actual: source.len(),
+        });
+    }
+
+    let mut target = source.to_vec();
+    target.resize(target_size, 0);
+
+    let mut offset = 0usize;
+    while reader.pos < end {
+        offset = offset
+            .checked_add(reader.varint()?)
+            .ok_or(PatchError::OutOfBounds { offset })?;
+        loop {
+            let byte = reader.byte()?;
+            if offset < target_size {
+                target[offset] = source.get(offset).copied().unwrap_or(0) ^ byte;
+            }
+            offset = offset.saturating_add(1);
+            if byte == 0 {
+                break;
+            }
+        }
+    }
+
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+// BPS: source, target and metadata sizes, then actions copying runs from
+// the source, the patch, or relative offsets into the source or the target.
+fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_crc = check_footer(source, patch)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = Reader::new(&patch[..end], BPS_TAG.len());
+
+    let source_size = reader.varint()?;
+    let target_size = check_target_size(reader.varint()?)?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+    if source_size != source.len() {
+        return Err(PatchError::SourceSize {
+            expected: source_size,
+            actual: source.len(),
+        });
+    }
+
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset = 0isize;
+    let mut target_offset = 0isize;
+
+    while reader.pos < end {
+        let action = reader.varint()?;
+        let len = (action >> 2) + 1;
+        let out = target.len();
+        if out + len > target_size {
+            return Err(PatchError::OutOfBounds { offset: out });
+        }
+
+        match action & 0x03 {
+            // SourceRead
+            0 => {
+                let bytes = source
+                    .get(out..out + len)
+                    .ok_or(PatchError::OutOfBounds { offset: out })?;
+                target.extend_from_slice(bytes);
+            }
+            // TargetRead
+            1 => target.extend_from_slice(reader.bytes(len)?),
+            // SourceCopy
+            2 => {
+                let bounds = || PatchError::OutOfBounds { offset: out };
+                source_offset = source_offset
+                    .checked_add(relative_offset(reader.varint()?))
+                    .ok_or_else(bounds)?;
+                let bytes = usize::try_from(source_offset)
+                    .ok()
+                    .and_then(|start| source.get(start..start.checked_add(len)?))
+                    .ok_or_else(bounds)?;
+                target.extend_from_slice(bytes);
+                // In range, having just been read
+                source_offset += len as isize;
+            }
+            // TargetCopy, byte by byte since the runs may overlap
+            _ => {
+                target_offset = target_offset
+                    .checked_add(relative_offset(reader.varint()?))
+                    .ok_or(PatchError::OutOfBounds { offset: out })?;
+                for _ in 0..len {
+                    let byte = usize::try_from(target_offset)
+                        .ok()
+                        .and_then(|idx| target.get(idx).copied())
+                        .ok_or(PatchError::OutOfBounds { offset: out })?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+// Target sizes come from the patch, so are checked before allocating
+fn check_target_size(size: usize) -> Result<usize, PatchError> {
+    match size > MAX_ROM_SIZE {
+        true => Err(PatchError::TargetTooLarge { size }),
+        false => Ok(size),
+    }
+}
+
+// Lowest bit is the sign, the rest the magnitude
+fn relative_offset(data: usize) -> isize {
+    let magnitude = (data >> 1) as isize;
+    if data & 1 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::{test_image, Rom, RomError};
+
+    use super::*;
+
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut bytes = vec![];
+        loop {
+            let low = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | low);
+                return bytes;
+            }
+            bytes.push(low);
+            value -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend(crc32(source).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_ips_patch() {
+        let mut ips = b"PATCH".to_vec();
+        ips.extend([0x00, 0x00, 0x20, 0x00, 0x01, 0xEA]);
+        ips.extend([0x00, 0x00, 0x30, 0x00, 0x00, 0x00, 0x04, 0x11]);
+        ips.extend(b"EOF");
+
+        let rom = Rom::with_patches(&test_image(0, 1, 0, 0), &[ips]).unwrap();
+        assert_eq!(rom.prg_rom[0x10], 0xEA);
+        assert_eq!(rom.prg_rom[0x20..0x25], [0x11, 0x11, 0x11, 0x11, 0x00]);
+    }
+
+    #[test]
+    fn test_ups_and_bps_patches() {
+        let source = test_image(0, 1, 0, 0);
+        let mut target = source.clone();
+        target[0x20] = 0xEA;
+
+        let mut ups = b"UPS1".to_vec();
+        ups.extend(varint(source.len()));
+        ups.extend(varint(target.len()));
+        ups.extend(varint(0x20));
+        ups.extend([0xEA, 0x00]);
+        let ups = with_footer(ups, &source, &target);
+
+        let mut bps = b"BPS1".to_vec();
+        bps.extend(varint(source.len()));
+        bps.extend(varint(target.len()));
+        bps.extend(varint(0));
+        bps.extend(varint((0x20 - 1) << 2));
+        bps.extend(varint(1));
+        bps.push(0xEA);
+        bps.extend(varint((source.len() - 0x21 - 1) << 2));
+        let bps = with_footer(bps, &source, &target);
+
+        for patch in [&ups, &bps] {
+            let rom = Rom::with_patches(&source, &[patch]).unwrap();
+            assert_eq!(rom.prg_rom[0x10], 0xEA);
+        }
+
+        // The second patch no longer applies to the already patched ROM
+        assert_eq!(
+            Rom::with_patches(&source, &[&ups, &bps]).err(),
+            Some(RomError::BadPatch {
+                index: 1,
+                error: PatchError::SourceChecksum {
+                    expected: crc32(&source),
+                    actual: crc32(&target),
+                },
+            })
+        );
+
+        let mut corrupt = bps.clone();
+        corrupt[10] ^= 0xFF;
+        assert!(matches!(
+            Rom::with_patches(&source, &[corrupt]).err(),
+            Some(RomError::BadPatch {
+                error: PatchError::PatchChecksum { .. },
+                ..
+            })
+        ));
+        assert_eq!(
+            Rom::with_patches(&source, &[b"NOPE"]).err(),
+            Some(RomError::BadPatch {
+                index: 0,
+                error: PatchError::UnknownFormat,
+            })
+        );
+    }
+
+    #[test]
+    fn test_hostile_patches() {
+        let source = test_image(0, 1, 0, 0);
+        let bps = |body: &[u8]| {
+            let mut bps = b"BPS1".to_vec();
+            bps.extend(varint(source.len()));
+            bps.extend(body);
+            apply(&source, &with_footer(bps, &source, &source)).err()
+        };
+
+        // Sizes are checked before anything is allocated
+        let size = MAX_ROM_SIZE + 1;
+        assert_eq!(
+            bps(&varint(size)),
+            Some(PatchError::TargetTooLarge { size })
+        );
+        assert_eq!(
+            bps(&[0x00; 12]),
+            Some(PatchError::BadNumber {
+                offset: 4 + varint(source.len()).len()
+            })
+        );
+
+        let mut body = varint(source.len());
+        body.extend(varint(usize::MAX));
+        assert_eq!(bps(&body), Some(PatchError::Truncated));
+
+        // SourceCopy from far beyond the source
+        let mut body = varint(source.len());
+        body.extend(varint(0));
+        body.extend(varint(2));
+        body.extend(varint(usize::MAX - 1));
+        assert_eq!(bps(&body), Some(PatchError::OutOfBounds { offset: 0 }));
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// Battery-backed cartridge RAM persisted to a `.sav` file next to the ROM.
+pub struct BatterySave {
+    path: PathBuf,
+    flushed: Vec<u8>,
+}
+
+impl BatterySave {
+    pub fn for_rom<P: AsRef<Path>>(rom_path: P) -> Self {
+        Self {
+            path: rom_path.as_ref().with_extension("sav"),
+            flushed: vec![],
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Fills `ram` from the save file. A missing file leaves `ram` untouched.
+    pub fn load(&mut self, ram: &mut [u8]) -> io::Result<()> {
+        match fs::read(&self.path) {
+            Ok(data) => {
+                let len = data.len().min(ram.len());
+                ram[..len].copy_from_slice(&data[..len]);
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err),
+        }
+
+        self.flushed = ram.to_vec();
+        Ok(())
+    }
+
+    // Writes `ram` to the save file if it changed since the last flush.
+    pub fn flush(&mut self, ram: &[u8]) -> io::Result<()> {
+        if self.flushed == ram {
+            return Ok(());
+        }
+
+        fs::write(&self.path, ram)?;
+        self.flushed = ram.to_vec();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        cartridge::test_image,
+        cpu::{cpu::Mem, CPU},
+    };
+
+    use super::*;
+
+    // A directory of its own, so parallel and concurrent runs do not share
+    // save files
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nes-save-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_battery_save_roundtrip() {
+        let dir = temp_dir("roundtrip");
+        let rom_path = dir.join("game.nes");
+        let mut save = BatterySave::for_rom(&rom_path);
+        assert_eq!(save.path(), dir.join("game.sav"));
+
+        let mut ram = vec![0; 16];
+        save.load(&mut ram).unwrap();
+        assert_eq!(ram, vec![0; 16]);
+
+        ram[3] = 0x42;
+        save.flush(&ram).unwrap();
+
+        let mut reloaded = vec![0; 16];
+        BatterySave::for_rom(&rom_path).load(&mut reloaded).unwrap();
+        assert_eq!(reloaded[3], 0x42);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rom_file_keeps_battery_ram() {
+        let dir = temp_dir("rom-file");
+        let rom_path = dir.join("game.nes");
+        let save_path = dir.join("game.sav");
+        fs::write(&rom_path, test_image(0, 1, 1, 0x02)).unwrap();
+        fs::write(&save_path, [0x11, 0x22]).unwrap();
+
+        // Loaded on start, flushed when the console goes away
+        let mut cpu = CPU::load_rom_file(&rom_path).unwrap();
+        assert_eq!(cpu.mem_read(0x6001), 0x22);
+        cpu.mem_write(0x6001, 0x33);
+        drop(cpu);
+        assert_eq!(fs::read(&save_path).unwrap()[..2], [0x11, 0x33]);
+
+        // Headless runs leave it alone
+        let mut cpu = CPU::load_rom_file_without_save(&rom_path).unwrap();
+        assert_eq!(cpu.mem_read(0x6001), 0x00);
+        cpu.mem_write(0x6001, 0x44);
+        drop(cpu);
+        assert_eq!(fs::read(&save_path).unwrap()[..2], [0x11, 0x33]);
+
+        // Boards without a battery get no save file
+        let rom_path = dir.join("plain.nes");
+        fs::write(&rom_path, test_image(0, 1, 1, 0)).unwrap();
+        let mut cpu = CPU::load_rom_file(&rom_path).unwrap();
+        cpu.mem_write(0x6000, 0x55);
+        drop(cpu);
+        assert!(!dir.join("plain.sav").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+use super::{ConsoleType, HeaderFormat, Mirroring, Rom, RomError, Timing};
+
+pub(super) const UNIF_TAG: &[u8] = b"UNIF";
+const UNIF_HEADER_SIZE: usize = 32;
+const CHUNK_HEADER_SIZE: usize = 8;
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+// UNIF board names (with the NES-/HVC-/UNL-/BTL-/BMC- prefix stripped)
+// and the iNES mapper implementing them.
+const BOARDS: &[(&str, u16)] = &[
+    ("NROM", 0),
+    ("NROM-128", 0),
+    ("NROM-256", 0),
+    ("RROM", 0),
+    ("RROM-128", 0),
+    ("BTR", 69),
+    ("JLROM", 69),
+    ("JSROM", 69),
+    ("SUNSOFT-5B", 69),
+    ("NAMCOT-163", 19),
+    ("FCG-1", 16),
+    ("FCG-2", 16),
+    ("LZ93D50", 16),
+    ("LZ93D50+24C02", 16),
+    ("LZ93D50+24C01", 159),
+    ("LZ93D50+SRAM", 153),
+    ("DATACH", 157),
+];
+
+const BOARD_PREFIXES: &[&str] = &["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "BANDAI-"];
+
+// Parses a UNIF image: a 32-byte header followed by tagged chunks
+// (4-byte id, little-endian u32 length, payload).
+pub(super) fn parse(raw: &[u8]) -> Result<Rom, RomError> {
+    if raw.len() < UNIF_HEADER_SIZE {
+        return Err(RomError::TruncatedHeader { len: raw.len() });
+    }
+
+    let mut board = None;
+    let mut prg_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut chr_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut screen_mirroring = Mirroring::Horizontal;
+    let mut battery = false;
+    let mut timing = Timing::Ntsc;
+
+    let mut pos = UNIF_HEADER_SIZE;
+    while pos + CHUNK_HEADER_SIZE <= raw.len() {
+        let id = &raw[pos..pos + 4];
+        let len = u32::from_le_bytes(raw[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        pos += CHUNK_HEADER_SIZE;
+
+        let available = raw.len() - pos;
+        let data = &raw[pos..pos + len.min(available)];
+        if len > available {
+            match &id[..3] {
+                b"PRG" => {
+                    return Err(RomError::TruncatedPrg {
+                        expected: len,
+                        actual: available,
+                    })
+                }
+                b"CHR" => {
+                    return Err(RomError::TruncatedChr {
+                        expected: len,
+                        actual: available,
+                    })
+                }
+                // A cut-off trailing chunk that carries no ROM data is harmless
+                _ => break,
+            }
+        }
+        pos += len;
+
+        match (&id[..3], chunk_index(id[3])) {
+            (b"PRG", Some(idx)) => prg_chunks[idx] = Some(data),
+            (b"CHR", Some(idx)) => chr_chunks[idx] = Some(data),
+            _ => match id {
+                b"MAPR" => {
+                    let name = data.split(|&byte| byte == 0).next().unwrap_or_default();
+                    board = Some(String::from_utf8_lossy(name).trim().to_string());
+                }
+                b"MIRR" => {
+                    screen_mirroring = match data.first() {
+                        Some(1) => Mirroring::Vertical,
+                        Some(2) => Mirroring::SingleScreenLower,
+                        Some(3) => Mirroring::SingleScreenUpper,
+                        Some(4) => Mirroring::FourScreen,
+                        // 0 and 5 (mapper controlled) start out horizontal
+                        _ => Mirroring::Horizontal,
+                    }
+                }
+                b"BATR" => battery = data.first().is_none_or(|&byte| byte != 0),
+                b"TVCI" => {
+                    timing = match data.first() {
+                        Some(1) => Timing::Pal,
+                        Some(2) => Timing::MultiRegion,
+                        _ => Timing::Ntsc,
+                    }
+                }
+                _ => (),
+            },
+        }
+    }
+
+    let board = board.ok_or(RomError::MissingChunk("MAPR"))?;
+    let mapper = board_mapper(&board).ok_or(RomError::UnsupportedBoard(board))?;
+
+    let prg_rom: Vec<u8> = prg_chunks
+        .iter()
+        .flatten()
+        .flat_map(|chunk| chunk.iter().copied())
+        .collect();
+    let chr_rom: Vec<u8> = chr_chunks
+        .iter()
+        .flatten()
+        .flat_map(|chunk| chunk.iter().copied())
+        .collect();
+    if prg_rom.is_empty() {
+        return Err(RomError::MissingChunk("PRG0"));
+    }
+
+    let (prg_ram_size, prg_nvram_size) = if battery {
+        (0, PRG_RAM_SIZE)
+    } else {
+        (PRG_RAM_SIZE, 0)
+    };
+
+    Ok(Rom {
+        format: HeaderFormat::Unif,
+        chr_ram_size: if chr_rom.is_empty() { CHR_RAM_SIZE } else { 0 },
+        chr_nvram_size: 0,
+        prg_rom,
+        chr_rom,
+        prg_ram_size,
+        prg_nvram_size,
+        battery,
+        mapper,
+        submapper: 0,
+        screen_mirroring,
+        timing,
+        console_type: ConsoleType::Nes,
+        expansion_device: 0,
+        misc_roms: 0,
+        trainer: None,
+        disk_sides: vec![],
+    })
+}
+
+fn chunk_index(digit: u8) -> Option<usize> {
+    (digit as char).to_digit(16).map(|idx| idx as usize)
+}
+
+pub fn board_mapper(board: &str) -> Option<u16> {
+    let name = BOARD_PREFIXES
+        .iter()
+        .find_map(|prefix| board.strip_prefix(prefix))
+        .unwrap_or(board);
+
+    BOARDS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map(|&(_, mapper)| mapper)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::{CHR_ROM_PAGE_SIZE, PRG_ROM_PAGE_SIZE};
+
+    use super::*;
+
+    fn unif_chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        chunk
+    }
+
+    #[test]
+    fn test_unif_image() {
+        let mut raw = b"UNIF".to_vec();
+        raw.extend(7u32.to_le_bytes());
+        raw.resize(32, 0);
+        raw.extend(unif_chunk(b"MAPR", b"NES-NROM-256\0"));
+        raw.extend(unif_chunk(b"PRG1", &[0x22; PRG_ROM_PAGE_SIZE]));
+        raw.extend(unif_chunk(b"PRG0", &[0x11; PRG_ROM_PAGE_SIZE]));
+        raw.extend(unif_chunk(b"CHR0", &[0x33; CHR_ROM_PAGE_SIZE]));
+        raw.extend(unif_chunk(b"MIRR", &[1]));
+        raw.extend(unif_chunk(b"BATR", &[1]));
+
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.format, HeaderFormat::Unif);
+        assert_eq!(rom.mapper, 0);
+        assert_eq!(rom.prg_rom.len(), 2 * PRG_ROM_PAGE_SIZE);
+        assert_eq!(rom.prg_rom[0], 0x11);
+        assert_eq!(rom.prg_rom[PRG_ROM_PAGE_SIZE], 0x22);
+        assert_eq!(rom.chr_rom.len(), CHR_ROM_PAGE_SIZE);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert!(rom.battery);
+
+        raw.truncate(32);
+        raw.extend(unif_chunk(b"MAPR", b"UNL-SOMETHING\0"));
+        raw.extend(unif_chunk(b"PRG0", &[0; PRG_ROM_PAGE_SIZE]));
+        assert_eq!(
+            Rom::new(&raw).err(),
+            Some(RomError::UnsupportedBoard("UNL-SOMETHING".into()))
+        );
+    }
+}
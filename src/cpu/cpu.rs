@@ -1,30 +1,88 @@
 use constants::{BREAK, BREAK_2};
 pub const STACK_RESET: u8 = 0xFD;
+// Value the unstable XAA and LXA opcodes OR into A before masking. It
+// differs between chip revisions: 0xEE on most, 0xFF or 0x00 on others.
+pub const UNSTABLE_MAGIC: u8 = 0xEE;
+
+use std::path::Path;
 
 use crate::{
-    bus::bus::Bus,
-    cartridge::Rom,
+    bus::{bus::Bus, watch::WatchHit},
+    cartridge::{Rom, RomError},
     cpu::constants::{DECIMAL_MODE, INTERRUPT_DISABLE},
 };
 
 use self::constants::{CARRY_FLAG, NEGATIVE_FLAG, OVERFLOW_FLAG, ZERO_FLAG};
 
-use super::opcodes::OPCODES;
+use super::opcodes::{Access, Instruction, Opcode, CMOS_OPCODES, OPCODES};
+
+// Which 6502 the core behaves as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    // The NES CPU: the decimal flag exists but ADC and SBC stay binary
+    Ricoh2A03,
+    // A stock NMOS 6502, with BCD arithmetic in decimal mode
+    Nmos6502,
+    // The CMOS 65C02: extra opcodes, undefined ones are NOPs, JMP ($xxFF)
+    // reads across the page and N/Z are valid in decimal mode
+    Cmos65C02,
+}
 
-pub struct CPU {
+pub struct CPU<B: Mem = Bus> {
     pub register_a: u8,
     pub register_x: u8,
     pub register_y: u8,
     pub stack_pointer: u8,
     pub flags: u8,
     pub program_counter: u16,
-    pub bus: Bus,
+    pub bus: B,
+    pub variant: Variant,
+    // Tick the bus on every memory access, in hardware order and including
+    // dummy reads and writes, instead of once per instruction. The access
+    // order is the NMOS one for every variant.
+    pub cycle_stepped: bool,
+    // ORed into A by XAA and LXA, see `UNSTABLE_MAGIC`
+    pub unstable_magic: u8,
+    // Address of the KIL/JAM opcode that halted the CPU; cleared by `reset`
+    pub jammed: Option<u16>,
+    access: Access,
+    last_read: u8,
+    // Page-cross and branch penalties of the current instruction, added to
+    // its table cycles when not cycle-stepped
+    extra_cycles: u8,
 }
 
 pub trait Mem {
     fn mem_read(&mut self, addr: u16) -> u8;
     fn mem_write(&mut self, addr: u16, data: u8);
 
+    // Clocks whatever else is on the bus by `cycles` CPU cycles
+    fn tick(&mut self, _cycles: u8) {}
+
+    // Whether something on the bus is holding the IRQ line low
+    fn poll_irq(&self) -> bool {
+        false
+    }
+
+    // Whether an NMI edge is pending; polling it acknowledges it
+    fn poll_nmi(&mut self) -> bool {
+        false
+    }
+
+    // Called before each instruction, or the interrupt taken in its place,
+    // with its address
+    fn begin_instruction(&mut self, _pc: u16) {}
+
+    // A watchpoint hit that asked to stop the debugger; taking it clears it
+    fn take_break(&mut self) -> Option<WatchHit> {
+        None
+    }
+
+    // Reads for debugging output, which should not disturb anything
+    fn peek(&mut self, addr: u16) -> u8 {
+        self.mem_read(addr)
+    }
+
     fn mem_read_u16(&mut self, pos: u16) -> u16 {
         let lo = self.mem_read(pos);
         let hi = self.mem_read(pos.wrapping_add(1));
@@ -39,7 +97,7 @@ pub trait Mem {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(non_camel_case_types)]
 pub enum AddressingMode {
     Accumulator,
@@ -53,165 +111,305 @@ pub enum AddressingMode {
     Indirect,
     Indirect_X,
     Indirect_Y,
+    // 65C02 only: ($zp) and JMP ($abs,X)
+    ZeroPage_Indirect,
+    Absolute_Indirect_X,
     NoneAddressing,
     Implied,
     Relative,
 }
 
+// Constructors for the NES, on its own Bus
 impl CPU {
-    pub fn load_rom(raw: Vec<u8>) -> Result<Self, String> {
+    pub fn load_rom(raw: Vec<u8>) -> Result<Self, RomError> {
         let rom = Rom::new(&raw)?;
 
-        Ok(CPU {
+        Ok(CPU::new(Bus::new(rom)?))
+    }
+
+    // Like `load_rom`, from a .nes/.unf file or a zip/gzip holding one.
+    // Battery-backed RAM is loaded from and kept in the .sav file next to it.
+    pub fn load_rom_file<P: AsRef<Path>>(path: P) -> Result<Self, RomError> {
+        let mut bus = Bus::new(Rom::load(&path)?)?;
+        bus.attach_battery_save(&path)?;
+
+        Ok(CPU::new(bus))
+    }
+
+    // The same without the .sav file, for headless runs that have to start
+    // from the same power-on state every time.
+    pub fn load_rom_file_without_save<P: AsRef<Path>>(path: P) -> Result<Self, RomError> {
+        Ok(CPU::new(Bus::new(Rom::load(path)?)?))
+    }
+}
+
+impl<B: Mem> CPU<B> {
+    pub fn new(bus: B) -> Self {
+        CPU {
             register_a: 0,
             register_x: 0,
             register_y: 0,
             stack_pointer: STACK_RESET,
             flags: 0,
             program_counter: 0,
-            bus: Bus::new(rom),
-        })
+            bus,
+            variant: Variant::Ricoh2A03,
+            cycle_stepped: false,
+            unstable_magic: UNSTABLE_MAGIC,
+            jammed: None,
+            access: Access::Read,
+            last_read: 0,
+            extra_cycles: 0,
+        }
     }
 
     pub fn run_with_callback<F>(&mut self, mut callback: F)
     where
-        F: FnMut(&mut CPU),
+        F: FnMut(&mut CPU<B>),
     {
         loop {
-            callback(self);
-
-            let opscode = self.mem_read(self.program_counter);
-            self.inc_prg();
-            let pc_state = self.program_counter;
-
-            let opcode = OPCODES
-                .get(&opscode)
-                .expect(&format!("That is really fucked up opcode: {opscode:02X}"));
-
-            match opcode.mnemonic {
-                "BRK" => return,
-                "*AAC" => self.aac(&opcode.mode),
-                "*SAX" => self.aax(&opcode.mode),
-                "ADC" => self.adc(&opcode.mode),
-                "AND" => self.and(&opcode.mode),
-                "*ARR" => self.arr(&opcode.mode),
-                "ASL" => self.asl(&opcode.mode),
-                "*ASR" => self.asr(&opcode.mode),
-                "*ATX" => self.atx(&opcode.mode),
-                "*AXA" => self.axa(&opcode.mode),
-                "*AXS" => self.axs(&opcode.mode),
-                "BCC" => self.branch(!self.check_flag(CARRY_FLAG)),
-                "BCS" => self.branch(self.check_flag(CARRY_FLAG)),
-                "BEQ" => self.branch(self.check_flag(ZERO_FLAG)),
-                "BIT" => self.bit(&opcode.mode),
-                "BMI" => self.branch(self.check_flag(NEGATIVE_FLAG)),
-                "BNE" => self.branch(!self.check_flag(ZERO_FLAG)),
-                "BPL" => self.branch(!self.check_flag(NEGATIVE_FLAG)),
-                "BVC" => self.branch(!self.check_flag(OVERFLOW_FLAG)),
-                "BVS" => self.branch(self.check_flag(OVERFLOW_FLAG)),
-                "CLC" => self.remove_flag(CARRY_FLAG),
-                "CLD" => self.remove_flag(DECIMAL_MODE),
-                "CLI" => self.remove_flag(INTERRUPT_DISABLE),
-                "CLV" => self.remove_flag(OVERFLOW_FLAG),
-                "CMP" => self.compare(&opcode.mode, self.register_a),
-                "CPX" => self.compare(&opcode.mode, self.register_x),
-                "CPY" => self.compare(&opcode.mode, self.register_y),
-                "*DCP" => self.dcp(&opcode.mode),
-                "DEC" => self.dec(&opcode.mode),
-                "DEX" => self.dex(),
-                "DEY" => self.dey(),
-                "*DOP" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let _data = self.mem_read(addr); // Dummy read
-                }, // Double NOP
-                "EOR" => self.eor(&opcode.mode),
-                "INC" => self.inc(&opcode.mode),
-                "INX" => self.inx(),
-                "INY" => self.iny(),
-                "*ISB" => self.isc(&opcode.mode),
-                "JMP" => self.jmp(&opcode.mode),
-                "JSR" => self.jsr(&opcode.mode),
-                "*KIL" => todo!("Я не знаю как оно должно работать"),
-                "*LAR" => self.lar(&opcode.mode),
-                "*LAX" => self.lax(&opcode.mode),
-                "LDA" => self.lda(&opcode.mode),
-                "LDX" => self.ldx(&opcode.mode),
-                "LDY" => self.ldy(&opcode.mode),
-                "LSR" => self.lsr(&opcode.mode),
-                "NOP" => (),
-                "*NOP" => (),
-                "ORA" => self.ora(&opcode.mode),
-                "PHA" => self.push(self.register_a),
-                "PHP" => {
-                    let flags = self.flags;
-                    let flags = flags | BREAK | BREAK_2;
-                    self.push(flags)
-                },
-                "PLA" => self.pla(),
-                "PLP" => self.plp(),
-                "*RLA" => self.rla(&opcode.mode),
-                "ROL" => self.rol(&opcode.mode),
-                "ROR" => self.ror(&opcode.mode),
-                "*RRA" => self.rra(&opcode.mode),
-                "RTI" => self.rti(),
-                "RTS" => self.rts(),
-                "SBC" | "*SBC" => self.sbc(&opcode.mode),
-                "SEC" => self.set_flag(CARRY_FLAG),
-                "SED" => self.set_flag(DECIMAL_MODE),
-                "SEI" => self.set_flag(INTERRUPT_DISABLE),
-                "*SLO" => self.slo(&opcode.mode),
-                "*SRE" => self.sre(&opcode.mode),
-                "STA" => self.sta(&opcode.mode),
-                "STX" => self.stx(&opcode.mode),
-                "STY" => self.sty(&opcode.mode),
-                "*SXA" => self.sxa(&opcode.mode),
-                "*SYA" => self.sya(&opcode.mode),
-                "TAX" => self.tax(),
-                "TAY" => self.tay(),
-                "*TOP" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let _data = self.mem_read(addr); // Dummy read
-                }, // Triple NOP
-                "TSX" => self.tsx(),
-                "TXA" => self.txa(),
-                "TXS" => self.txs(),
-                "TYA" => self.tya(),
-                "*XAS" => self.xas(&opcode.mode),
-                _ => unreachable!(),
+            // A jammed CPU only comes back through `reset`
+            if self.jammed.is_some() {
+                return;
             }
 
-            if self.program_counter == pc_state {
-                self.inc_prg_by(opcode.bytes);
+            self.bus.begin_instruction(self.program_counter);
+            self.poll_interrupts();
+            callback(self);
+            if !self.execute() {
+                return;
             }
         }
     }
 
+    // Runs one instruction, taking a pending interrupt first. Returns false
+    // when it was a BRK or the CPU is jammed, where `run_with_callback` stops.
+    pub fn step(&mut self) -> bool {
+        if self.jammed.is_some() {
+            return false;
+        }
+
+        self.bus.begin_instruction(self.program_counter);
+        self.poll_interrupts();
+        self.execute()
+    }
+
+    fn poll_interrupts(&mut self) {
+        if self.bus.poll_nmi() {
+            self.interrupt(0xFFFA);
+        } else if self.bus.poll_irq() && !self.check_flag(INTERRUPT_DISABLE) {
+            self.interrupt(0xFFFE);
+        }
+    }
+
+    fn execute(&mut self) -> bool {
+        let opcodes = self.opcodes();
+
+        let opscode = self.mem_read(self.program_counter);
+        self.inc_prg();
+        let pc_state = self.program_counter;
+
+        let opcode = &opcodes[opscode as usize];
+        self.access = opcode.instruction.access();
+
+        // Single-byte instructions still read the byte after the opcode
+        if opcode.bytes == 1 {
+            self.dummy_read(self.program_counter);
+        }
+
+        match opcode.instruction {
+            Instruction::Brk => return false,
+            Instruction::Aac => self.aac(&opcode.mode),
+            Instruction::Sax => self.aax(&opcode.mode),
+            Instruction::Adc => self.adc(&opcode.mode),
+            Instruction::And => self.and(&opcode.mode),
+            Instruction::Arr => self.arr(&opcode.mode),
+            Instruction::Asl => self.asl(&opcode.mode),
+            Instruction::Asr => self.asr(&opcode.mode),
+            Instruction::Atx => self.atx(&opcode.mode),
+            Instruction::Axa => self.axa(&opcode.mode),
+            Instruction::Axs => self.axs(&opcode.mode),
+            Instruction::Bcc => self.branch(!self.check_flag(CARRY_FLAG)),
+            Instruction::Bcs => self.branch(self.check_flag(CARRY_FLAG)),
+            Instruction::Beq => self.branch(self.check_flag(ZERO_FLAG)),
+            Instruction::Bit => self.bit(&opcode.mode),
+            Instruction::Bmi => self.branch(self.check_flag(NEGATIVE_FLAG)),
+            Instruction::Bne => self.branch(!self.check_flag(ZERO_FLAG)),
+            Instruction::Bpl => self.branch(!self.check_flag(NEGATIVE_FLAG)),
+            Instruction::Bra => self.branch(true),
+            Instruction::Bvc => self.branch(!self.check_flag(OVERFLOW_FLAG)),
+            Instruction::Bvs => self.branch(self.check_flag(OVERFLOW_FLAG)),
+            Instruction::Clc => self.remove_flag(CARRY_FLAG),
+            Instruction::Cld => self.remove_flag(DECIMAL_MODE),
+            Instruction::Cli => self.remove_flag(INTERRUPT_DISABLE),
+            Instruction::Clv => self.remove_flag(OVERFLOW_FLAG),
+            Instruction::Cmp => self.compare(&opcode.mode, self.register_a),
+            Instruction::Cpx => self.compare(&opcode.mode, self.register_x),
+            Instruction::Cpy => self.compare(&opcode.mode, self.register_y),
+            Instruction::Dcp => self.dcp(&opcode.mode),
+            Instruction::Dec => self.dec(&opcode.mode),
+            Instruction::Dex => self.dex(),
+            Instruction::Dey => self.dey(),
+            Instruction::Dop => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let _data = self.mem_read(addr); // Dummy read
+            }, // Double NOP
+            Instruction::Eor => self.eor(&opcode.mode),
+            Instruction::Inc => self.inc(&opcode.mode),
+            Instruction::Inx => self.inx(),
+            Instruction::Iny => self.iny(),
+            Instruction::Isb => self.isc(&opcode.mode),
+            Instruction::Jmp => self.jmp(&opcode.mode),
+            Instruction::Jsr => self.jsr(),
+            Instruction::Kil => {
+                self.program_counter = pc_state.wrapping_sub(1);
+                self.jammed = Some(self.program_counter);
+                return false;
+            },
+            Instruction::Lar => self.lar(&opcode.mode),
+            Instruction::Lax => self.lax(&opcode.mode),
+            Instruction::Lda => self.lda(&opcode.mode),
+            Instruction::Ldx => self.ldx(&opcode.mode),
+            Instruction::Ldy => self.ldy(&opcode.mode),
+            Instruction::Lsr => self.lsr(&opcode.mode),
+            Instruction::Nop => (),
+            Instruction::Ora => self.ora(&opcode.mode),
+            Instruction::Pha => self.push(self.register_a),
+            Instruction::Php => {
+                let flags = self.flags;
+                let flags = flags | BREAK | BREAK_2;
+                self.push(flags)
+            },
+            Instruction::Phx => self.push(self.register_x),
+            Instruction::Phy => self.push(self.register_y),
+            Instruction::Pla => self.pla(),
+            Instruction::Plp => self.plp(),
+            Instruction::Plx => self.plx(),
+            Instruction::Ply => self.ply(),
+            Instruction::Rla => self.rla(&opcode.mode),
+            Instruction::Rol => self.rol(&opcode.mode),
+            Instruction::Ror => self.ror(&opcode.mode),
+            Instruction::Rra => self.rra(&opcode.mode),
+            Instruction::Rti => self.rti(),
+            Instruction::Rts => self.rts(),
+            Instruction::Sbc => self.sbc(&opcode.mode),
+            Instruction::Sec => self.set_flag(CARRY_FLAG),
+            Instruction::Sed => self.set_flag(DECIMAL_MODE),
+            Instruction::Sei => self.set_flag(INTERRUPT_DISABLE),
+            Instruction::Slo => self.slo(&opcode.mode),
+            Instruction::Sre => self.sre(&opcode.mode),
+            Instruction::Sta => self.sta(&opcode.mode),
+            Instruction::Stx => self.stx(&opcode.mode),
+            Instruction::Sty => self.sty(&opcode.mode),
+            Instruction::Sxa => self.sxa(&opcode.mode),
+            Instruction::Sya => self.sya(&opcode.mode),
+            Instruction::Stz => self.stz(&opcode.mode),
+            Instruction::Tax => self.tax(),
+            Instruction::Tay => self.tay(),
+            Instruction::Top => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let _data = self.mem_read(addr); // Dummy read
+            }, // Triple NOP
+            Instruction::Trb => self.trb(&opcode.mode),
+            Instruction::Tsb => self.tsb(&opcode.mode),
+            Instruction::Tsx => self.tsx(),
+            Instruction::Txa => self.txa(),
+            Instruction::Txs => self.txs(),
+            Instruction::Tya => self.tya(),
+            Instruction::Xas => self.xas(&opcode.mode),
+            Instruction::Xaa => self.xaa(&opcode.mode),
+        }
+
+        if self.program_counter == pc_state {
+            self.inc_prg_by(opcode.bytes);
+        }
+
+        if !self.cycle_stepped {
+            let cycles = opcode.cycles + std::mem::take(&mut self.extra_cycles);
+            self.bus.tick(cycles);
+        }
+
+        true
+    }
+
+    pub(super) fn opcodes(&self) -> &'static [Opcode; 256] {
+        match self.variant {
+            Variant::Cmos65C02 => &CMOS_OPCODES,
+            Variant::Ricoh2A03 | Variant::Nmos6502 => &OPCODES,
+        }
+    }
+
+    // Hardware interrupt through `vector`: $FFFA for NMI, $FFFE for IRQ
+    fn interrupt(&mut self, vector: u16) {
+        self.dummy_read(self.program_counter);
+        self.dummy_read(self.program_counter);
+
+        self.push_u16(self.program_counter);
+        let flags = (self.flags & !BREAK) | BREAK_2;
+        self.push(flags);
+        self.set_flag(INTERRUPT_DISABLE);
+        if self.variant == Variant::Cmos65C02 {
+            self.remove_flag(DECIMAL_MODE);
+        }
+
+        if !self.cycle_stepped {
+            self.bus.tick(7);
+        }
+        self.program_counter = self.mem_read_u16(vector);
+    }
+
+    // Bus read the hardware makes and discards; only issued when cycle-stepped
+    fn dummy_read(&mut self, addr: u16) {
+        if self.cycle_stepped {
+            self.mem_read(addr);
+        }
+    }
+
+    // Indexed modes read from the un-carried address first whenever the
+    // index crosses a page, and always for writes and read-modify-writes.
+    fn dummy_read_indexed(&mut self, base: u16, addr: u16) {
+        let crossed = base & 0xFF00 != addr & 0xFF00;
+        if crossed || self.access != Access::Read {
+            self.dummy_read((base & 0xFF00) | (addr & 0x00FF));
+        }
+
+        // Writes always take the extra cycle, the table counts it already
+        if crossed && self.access == Access::Read && !self.cycle_stepped {
+            self.extra_cycles += 1;
+        }
+    }
+
+    // The unstable stores AND their value with the high byte of the
+    // un-indexed address plus one. When the index crosses a page the
+    // stored value also becomes the high byte of the address written.
+    fn unstable_store(&mut self, addr: u16, index: u8, value: u8) {
+        let base = addr.wrapping_sub(index as u16);
+        let [_, hi] = base.to_le_bytes();
+        let data = value & hi.wrapping_add(1);
+
+        let addr = if base & 0xFF00 != addr & 0xFF00 {
+            u16::from_le_bytes([addr as u8, data])
+        } else {
+            addr
+        };
+        self.mem_write(addr, data);
+    }
+
     fn xas(&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        let [_, hi] = addr.to_le_bytes();
 
         self.stack_pointer = self.register_a & self.register_x;
-        let res = self.stack_pointer & hi.wrapping_add(1);
-
-        self.mem_write(addr, res);
+        self.unstable_store(addr, self.register_y, self.stack_pointer);
     }
 
     fn sya(&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        
-        let [_, hi] = addr.to_le_bytes();
-        let res = self.register_y & hi.wrapping_add(1);
-
-        self.mem_write(addr, res);
+        self.unstable_store(addr, self.register_x, self.register_y);
     }
 
     fn sxa(&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        
-        let [_, hi] = addr.to_le_bytes();
-        let res = self.register_x & hi.wrapping_add(1);
-
-        self.mem_write(addr, res);
+        self.unstable_store(addr, self.register_y, self.register_x);
     }
 
     fn sre(&mut self, mode: &AddressingMode) {
@@ -227,7 +425,7 @@ impl CPU {
         let res = data >> 1;
         self.mem_write(addr, res);
         
-        self.register_a = self.register_a ^ res;
+        self.register_a ^= res;
         self.update_neg_and_zero_status(self.register_a);
     }
 
@@ -244,7 +442,7 @@ impl CPU {
         let res = data << 1;
         self.mem_write(addr, res);
         
-        self.register_a = self.register_a | res;
+        self.register_a |= res;
         self.update_neg_and_zero_status(self.register_a);
     }
 
@@ -279,11 +477,11 @@ impl CPU {
         } else {
             self.remove_flag(CARRY_FLAG);
         }
-        data = data << 1;
-        data = data | old_carry;
+        data <<= 1;
+        data |= old_carry;
         self.mem_write(addr, data);
 
-        self.register_a = self.register_a & data;
+        self.register_a &= data;
         self.update_neg_and_zero_status(self.register_a);
     }
 
@@ -351,36 +549,42 @@ impl CPU {
 
     fn axa(&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
-        let data = self.register_a & self.register_x & (addr >> 8) as u8;
-        self.mem_write(addr, data);
+        self.unstable_store(addr, self.register_y, self.register_a & self.register_x);
     }
 
     fn atx(&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
         let data = self.mem_read(addr);
 
-        self.register_a = self.register_a & data;
+        self.register_a = (self.register_a | self.unstable_magic) & data;
         self.register_x = self.register_a;
         self.update_neg_and_zero_status(self.register_x);
     }
 
+    fn xaa(&mut self, mode: &AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let data = self.mem_read(addr);
+
+        self.register_a = (self.register_a | self.unstable_magic) & self.register_x & data;
+        self.update_neg_and_zero_status(self.register_a);
+    }
+
     fn aac(&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
         let data = self.mem_read(addr);
 
         let res = self.register_a & data;
-        if data & NEGATIVE_FLAG != 0 {
+        if res & NEGATIVE_FLAG != 0 {
             self.set_flag(CARRY_FLAG);
         } else {
             self.remove_flag(CARRY_FLAG);
         }
 
         self.update_neg_and_zero_status(res);
+        self.register_a = res;
     }
 
     fn aax(&mut self, mode: &AddressingMode) {
-        0xe6;
-        0x64;
         let addr = self.get_operand_address(mode);
         
         let res = self.register_a & self.register_x;
@@ -395,6 +599,14 @@ impl CPU {
     }
 
     fn add_with_carry(&mut self, a: u8, b: u8) -> u8 {
+        if self.decimal_enabled() {
+            self.decimal_add(a, b)
+        } else {
+            self.binary_add(a, b)
+        }
+    }
+
+    fn binary_add(&mut self, a: u8, b: u8) -> u8 {
         let a_u16 = a as u16;
         let b_u16 = b as u16;
         let carry = self.get_carry() as u16;
@@ -438,9 +650,10 @@ impl CPU {
         let data = self.mem_read(addr);
 
         let res = self.register_a & data;
-        let res = res.rotate_right(1);
+        let res = (res >> 1) | (self.get_carry() << 7);
 
-        let (a, b) = (res & 0x10, res & 0x20);
+        // C is bit 6 of the result and V is bit 6 xor bit 5
+        let (a, b) = (res & 0x40, res & 0x20);
         match (a, b) {
             (0, 0) => {
                 self.remove_flag(CARRY_FLAG);
@@ -496,11 +709,11 @@ impl CPU {
         let addr = self.get_operand_address(mode);
         let data = self.mem_read(addr);
 
-        let res = self.register_a & data;
-        let res = res >> 1;
+        let and = self.register_a & data;
+        let res = and >> 1;
         
         self.update_neg_and_zero_status(res);
-        if res & CARRY_FLAG != 0 {
+        if and & CARRY_FLAG != 0 {
             self.set_flag(CARRY_FLAG);
         } else {
             self.remove_flag(CARRY_FLAG);
@@ -510,12 +723,18 @@ impl CPU {
     }
 
     fn branch(&mut self, condition: bool) {
+        let jmp = self.mem_read(self.program_counter) as i8;
+
         if condition {
-            let jmp = self.mem_read(self.program_counter) as i8;
-            let jmp_addr = self
-                .program_counter
-                .wrapping_add(1)
-                .wrapping_add(jmp as u16);
+            let next = self.program_counter.wrapping_add(1);
+            let jmp_addr = next.wrapping_add(jmp as u16);
+
+            self.dummy_read(next);
+            if next & 0xFF00 != jmp_addr & 0xFF00 {
+                self.dummy_read((next & 0xFF00) | (jmp_addr & 0x00FF));
+                self.extra_cycles += !self.cycle_stepped as u8;
+            }
+            self.extra_cycles += !self.cycle_stepped as u8;
 
             self.program_counter = jmp_addr;
         }
@@ -526,6 +745,11 @@ impl CPU {
         let data = self.mem_read(addr);
 
         let res = self.register_a & data;
+        // 65C02 BIT #imm only touches Z
+        if let AddressingMode::Immediate = mode {
+            self.update_zero_status(res);
+            return;
+        }
         self.update_neg_and_zero_status(res);
 
         if data & OVERFLOW_FLAG == 0 {
@@ -555,6 +779,12 @@ impl CPU {
     }
 
     fn dec(&mut self, mode: &AddressingMode) {
+        if let AddressingMode::Accumulator = mode {
+            self.register_a = self.register_a.wrapping_sub(1);
+            self.update_neg_and_zero_status(self.register_a);
+            return;
+        }
+
         let addr = self.get_operand_address(mode);
         let data = self.mem_read(addr);
 
@@ -578,11 +808,17 @@ impl CPU {
         let addr = self.get_operand_address(mode);
         let data = self.mem_read(addr);
 
-        self.register_a = self.register_a ^ data;
+        self.register_a ^= data;
         self.update_neg_and_zero_status(self.register_a);
     }
 
     fn inc(&mut self, mode: &AddressingMode) {
+        if let AddressingMode::Accumulator = mode {
+            self.register_a = self.register_a.wrapping_add(1);
+            self.update_neg_and_zero_status(self.register_a);
+            return;
+        }
+
         let addr = self.get_operand_address(mode);
         let data = self.mem_read(addr);
 
@@ -609,11 +845,17 @@ impl CPU {
         self.program_counter = addr;
     }
 
-    fn jsr(&mut self, mode: &AddressingMode) {
-        let return_point = self.program_counter + 2;
-        self.push_u16(return_point - 1);
+    // The target's high byte is only fetched after the return address is
+    // pushed, as on hardware.
+    fn jsr(&mut self) {
+        let lo = self.mem_read(self.program_counter);
+        self.dummy_read(0x100 + self.stack_pointer as u16);
+
+        let return_point = self.program_counter.wrapping_add(2);
+        self.push_u16(return_point.wrapping_sub(1));
 
-        self.jmp(mode);
+        let hi = self.mem_read(self.program_counter.wrapping_add(1));
+        self.program_counter = u16::from_le_bytes([lo, hi]);
     }
 
     fn lda(&mut self, mode: &AddressingMode) {
@@ -659,17 +901,31 @@ impl CPU {
         let addr = self.get_operand_address(mode);
         let data = self.mem_read(addr);
 
-        self.register_a = self.register_a | data;
+        self.register_a |= data;
         self.update_neg_and_zero_status(self.register_a);
     }
 
     fn pla(&mut self) {
+        self.dummy_read(0x100 + self.stack_pointer as u16);
         let a = self.pop();
         self.register_a = a;
         self.update_neg_and_zero_status(self.register_a);
     }
 
+    fn plx(&mut self) {
+        self.dummy_read(0x100 + self.stack_pointer as u16);
+        self.register_x = self.pop();
+        self.update_neg_and_zero_status(self.register_x);
+    }
+
+    fn ply(&mut self) {
+        self.dummy_read(0x100 + self.stack_pointer as u16);
+        self.register_y = self.pop();
+        self.update_neg_and_zero_status(self.register_y);
+    }
+
     fn plp(&mut self) {
+        self.dummy_read(0x100 + self.stack_pointer as u16);
         self.flags = self.pop();
         self.remove_flag(BREAK);
         self.set_flag(BREAK_2);
@@ -704,20 +960,108 @@ impl CPU {
     }
 
     fn rti(&mut self) {
+        self.dummy_read(0x100 + self.stack_pointer as u16);
         self.flags = self.pop();
+        self.remove_flag(BREAK);
         self.set_flag(BREAK_2);
         self.program_counter = self.pop_u16();
     }
 
     fn rts(&mut self) {
-        let pc = self.pop_u16() + 1;
-        self.program_counter = pc;
+        self.dummy_read(0x100 + self.stack_pointer as u16);
+        let pc = self.pop_u16();
+        self.dummy_read(pc);
+        self.program_counter = pc.wrapping_add(1);
     }
 
     fn subtract_with_carry(&mut self, a: u8, b: u8) -> u8 {
+        if self.decimal_enabled() {
+            return self.decimal_sub(a, b);
+        }
+
         self.add_with_carry(a, (b as i8).wrapping_neg().wrapping_sub(1) as u8)
     }
 
+    fn decimal_enabled(&self) -> bool {
+        self.variant != Variant::Ricoh2A03 && self.check_flag(DECIMAL_MODE)
+    }
+
+    // BCD addition. NMOS chips take Z from the binary sum and N/V from the
+    // high digit before it is adjusted; the 65C02 sets N/Z from the result.
+    fn decimal_add(&mut self, a: u8, b: u8) -> u8 {
+        let carry = self.get_carry();
+        let binary = a.wrapping_add(b).wrapping_add(carry);
+
+        let mut lo = (a & 0x0F) + (b & 0x0F) + carry;
+        if lo > 0x09 {
+            lo += 0x06;
+        }
+        let mut hi = (a >> 4) + (b >> 4) + (lo > 0x0F) as u8;
+        let unadjusted = hi << 4;
+
+        self.update_neg_and_zero_status(binary);
+        if unadjusted & NEGATIVE_FLAG != 0 {
+            self.set_flag(NEGATIVE_FLAG);
+        } else {
+            self.remove_flag(NEGATIVE_FLAG);
+        }
+
+        if !(a ^ b) & (a ^ unadjusted) & 0x80 != 0 {
+            self.set_flag(OVERFLOW_FLAG);
+        } else {
+            self.remove_flag(OVERFLOW_FLAG);
+        }
+
+        if hi > 0x09 {
+            hi += 0x06;
+        }
+        if hi > 0x0F {
+            self.set_flag(CARRY_FLAG);
+        } else {
+            self.remove_flag(CARRY_FLAG);
+        }
+
+        let res = (hi << 4) | (lo & 0x0F);
+        if self.variant == Variant::Cmos65C02 {
+            self.update_neg_and_zero_status(res);
+        }
+        res
+    }
+
+    // BCD subtraction. The NMOS flags are all the binary ones; the 65C02
+    // sets N/Z from the result and adjusts invalid digits differently.
+    fn decimal_sub(&mut self, a: u8, b: u8) -> u8 {
+        let borrow = 1 - self.get_carry() as i16;
+        let binary = self.binary_add(a, !b);
+
+        if self.variant == Variant::Cmos65C02 {
+            let lo = (a & 0x0F) as i16 - (b & 0x0F) as i16 - borrow;
+            let mut res = binary as i16;
+            if !self.check_flag(CARRY_FLAG) {
+                res -= 0x60;
+            }
+            if lo < 0 {
+                res -= 0x06;
+            }
+
+            let res = res as u8;
+            self.update_neg_and_zero_status(res);
+            return res;
+        }
+
+        let mut lo = (a & 0x0F) as i16 - (b & 0x0F) as i16 - borrow;
+        let mut hi = (a >> 4) as i16 - (b >> 4) as i16;
+        if lo < 0 {
+            lo -= 0x06;
+            hi -= 1;
+        }
+        if hi < 0 {
+            hi -= 0x06;
+        }
+
+        ((hi << 4) | (lo & 0x0F)) as u8
+    }
+
     fn sbc(&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
         let data = self.mem_read(addr);
@@ -725,6 +1069,36 @@ impl CPU {
         self.register_a = self.subtract_with_carry(self.register_a, data);
     }
 
+    fn stz(&mut self, mode: &AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        self.mem_write(addr, 0);
+    }
+
+    // TRB and TSB set Z from A & M, then clear or set A's bits in M
+    fn trb(&mut self, mode: &AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let data = self.mem_read(addr);
+
+        self.update_zero_status(self.register_a & data);
+        self.mem_write(addr, data & !self.register_a);
+    }
+
+    fn tsb(&mut self, mode: &AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let data = self.mem_read(addr);
+
+        self.update_zero_status(self.register_a & data);
+        self.mem_write(addr, data | self.register_a);
+    }
+
+    fn update_zero_status(&mut self, value: u8) {
+        if value == 0 {
+            self.set_flag(ZERO_FLAG);
+        } else {
+            self.remove_flag(ZERO_FLAG);
+        }
+    }
+
     fn stx(&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
         self.mem_write(addr, self.register_x);
@@ -777,32 +1151,35 @@ impl CPU {
             AddressingMode::Absolute => self.mem_read_u16(self.program_counter),
             AddressingMode::ZeroPage_X => {
                 let pos = self.mem_read(self.program_counter);
-                let addr = pos.wrapping_add(self.register_x) as u16;
-                addr
+                self.dummy_read(pos as u16);
+                pos.wrapping_add(self.register_x) as u16
             }
             AddressingMode::ZeroPage_Y => {
                 let pos = self.mem_read(self.program_counter);
-                let addr = pos.wrapping_add(self.register_y) as u16;
-                addr
+                self.dummy_read(pos as u16);
+                pos.wrapping_add(self.register_y) as u16
             }
             AddressingMode::Absolute_X => {
                 let base = self.mem_read_u16(self.program_counter);
                 let addr = base.wrapping_add(self.register_x as _);
+                self.dummy_read_indexed(base, addr);
                 addr
             }
             AddressingMode::Absolute_Y => {
                 let base = self.mem_read_u16(self.program_counter);
                 let addr = base.wrapping_add(self.register_y as _);
+                self.dummy_read_indexed(base, addr);
                 addr
             }
             AddressingMode::Indirect => {
                 let ptr = self.mem_read_u16(self.program_counter);
 
                 let lo = self.mem_read(ptr);
-                let hi = if ptr & 0xFF == 0xFF {
+                // The NMOS chips wrap within the page
+                let hi = if ptr & 0xFF == 0xFF && self.variant != Variant::Cmos65C02 {
                     self.mem_read(ptr & 0xFF00)
                 } else {
-                    self.mem_read(ptr + 1)
+                    self.mem_read(ptr.wrapping_add(1))
                 };
 
                 u16::from_le_bytes([lo, hi])
@@ -810,6 +1187,8 @@ impl CPU {
             AddressingMode::Indirect_X => {
                 let base = self.mem_read(self.program_counter);
 
+                self.dummy_read(base as u16);
+
                 let ptr: u8 = base.wrapping_add(self.register_x);
                 let lo = self.mem_read(ptr as u16);
                 let hi = self.mem_read(ptr.wrapping_add(1) as u16);
@@ -821,23 +1200,39 @@ impl CPU {
                 let lo = self.mem_read(base as u16);
                 let hi = self.mem_read(base.wrapping_add(1) as u16);
                 let ptr = u16::from_le_bytes([lo, hi]);
-                let real_addr = ptr.wrapping_add(self.register_y as u16);
-                real_addr
+                let addr = ptr.wrapping_add(self.register_y as u16);
+                self.dummy_read_indexed(ptr, addr);
+                addr
+            }
+            AddressingMode::ZeroPage_Indirect => {
+                let base = self.mem_read(self.program_counter);
+
+                let lo = self.mem_read(base as u16);
+                let hi = self.mem_read(base.wrapping_add(1) as u16);
+                u16::from_le_bytes([lo, hi])
+            }
+            AddressingMode::Absolute_Indirect_X => {
+                let base = self.mem_read_u16(self.program_counter);
+                let ptr = base.wrapping_add(self.register_x as u16);
+
+                let lo = self.mem_read(ptr);
+                let hi = self.mem_read(ptr.wrapping_add(1));
+                u16::from_le_bytes([lo, hi])
             }
             _ => panic!("mode {mode:?} is not supported"),
         }
     }
 
     fn push(&mut self, data: u8) {
+        // The stack pointer wraps within page 1
         let stack_top = 0x100 + self.stack_pointer as u16;
-        self.stack_pointer = self.stack_pointer.checked_sub(1).expect("Stack overflow");
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
         self.mem_write(stack_top, data);
     }
 
     fn pop(&mut self) -> u8 {
-        let stack_top = 0x100 + self.stack_pointer as u16 + 1;
-        self.stack_pointer = self.stack_pointer.checked_add(1).expect("Stack underflow");
-        self.mem_read(stack_top)
+        self.stack_pointer = self.stack_pointer.wrapping_add(1);
+        self.mem_read(0x100 + self.stack_pointer as u16)
     }
 
     fn push_u16(&mut self, data: u16) {
@@ -895,7 +1290,7 @@ impl CPU {
 
     #[allow(unused)]
     pub(super) fn get_stack_top_u16(&mut self) -> u16 {
-        self.mem_read_u16(0x100 + self.stack_pointer as u16)
+        self.mem_read_u16(0x100 + self.stack_pointer as u16 + 1)
     }
 
     fn logical_shift_right(&mut self, data: u8) -> u8 {
@@ -911,14 +1306,14 @@ impl CPU {
         res
     }
 
-    pub fn _run(&mut self) {
+    pub fn run(&mut self) {
         self.run_with_callback(|_| {});
     }
 
-    pub fn _load_and_run(&mut self, program: Vec<u8>) {
-        self._load(program);
+    pub fn load_and_run(&mut self, program: Vec<u8>) {
+        self.load(program);
         self.reset();
-        self._run()
+        self.run()
     }
 
     pub fn reset(&mut self) {
@@ -927,23 +1322,39 @@ impl CPU {
         self.register_y = 0;
         self.stack_pointer = STACK_RESET;
         self.set_flag(INTERRUPT_DISABLE | BREAK_2);
+        self.jammed = None;
+        self.extra_cycles = 0;
+
+        // Reset is an interrupt with the stack writes turned into reads,
+        // 7 cycles in all
+        if self.cycle_stepped {
+            let pc = self.program_counter;
+            self.dummy_read(pc);
+            self.dummy_read(pc);
+            for offset in 0..3 {
+                self.dummy_read(0x100 + STACK_RESET.wrapping_add(3 - offset) as u16);
+            }
+        } else {
+            self.bus.tick(7);
+        }
 
         self.program_counter = self.mem_read_u16(0xFFFC);
     }
 
-    pub fn _load(&mut self, program: Vec<u8>) {
+    pub fn load(&mut self, program: Vec<u8>) {
         for (id, val) in program.iter().enumerate() {
             self.bus.mem_write(0x0600 + id as u16, *val);
         }
         self.mem_write_u16(0xFFFC, 0x0600);
     }
 
+    // The program counter wraps from $FFFF to $0000
     fn inc_prg(&mut self) {
-        self.program_counter += 1;
+        self.program_counter = self.program_counter.wrapping_add(1);
     }
 
     fn inc_prg_by(&mut self, amount: u8) {
-        self.program_counter += (amount - 1) as u16;
+        self.program_counter = self.program_counter.wrapping_add((amount - 1) as u16);
     }
 
     pub(super) fn check_flag(&self, flag: u8) -> bool {
@@ -975,21 +1386,30 @@ impl CPU {
     }
 }
 
-impl Mem for CPU {
+impl<B: Mem> Mem for CPU<B> {
     fn mem_read(&mut self, addr: u16) -> u8 {
-        self.bus.mem_read(addr)
-    }
+        if !self.cycle_stepped {
+            return self.bus.mem_read(addr);
+        }
 
-    fn mem_write(&mut self, addr: u16, data: u8) {
-        self.bus.mem_write(addr, data)
+        self.bus.tick(1);
+        self.last_read = self.bus.mem_read(addr);
+        self.last_read
     }
 
-    fn mem_read_u16(&mut self, pos: u16) -> u16 {
-        self.bus.mem_read_u16(pos)
-    }
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        if self.cycle_stepped {
+            // Read-modify-write instructions write the unmodified value back
+            // before the result
+            if self.access == Access::ReadModifyWrite {
+                self.access = Access::Read;
+                self.bus.tick(1);
+                self.bus.mem_write(addr, self.last_read);
+            }
+            self.bus.tick(1);
+        }
 
-    fn mem_write_u16(&mut self, pos: u16, data: u16) {
-        self.bus.mem_write_u16(pos, data)
+        self.bus.mem_write(addr, data)
     }
 }
 
@@ -1004,39 +1424,36 @@ pub mod constants {
     pub const NEGATIVE_FLAG: u8 = 0b1000_0000;
 }
 
-pub fn trace(cpu: &mut CPU) -> String {
+pub fn trace<B: Mem>(cpu: &mut CPU<B>) -> String {
     let pc = format!("{:04X}", cpu.program_counter);
 
-    let opscode = cpu.mem_read(cpu.program_counter);
-    let opcode = OPCODES
-        .get(&opscode)
-        .expect(&format!("That is really fucked up opcode: {opscode:02X}"));
+    let opscode = cpu.bus.peek(cpu.program_counter);
+    let opcode = &cpu.opcodes()[opscode as usize];
 
     let mut real_addr = String::new();
 
     let bytes = match opcode.bytes {
         1 => {
-            match opcode.mode {
-                AddressingMode::Accumulator => real_addr = format!("A"),
-                _ => ()
-            };
+            if let AddressingMode::Accumulator = opcode.mode {
+                real_addr = "A".to_string();
+            }
             format!("{:02X}", opcode.code)
         },
         2 => {
-            let second_arg = cpu.mem_read(cpu.program_counter + 1);
+            let second_arg = cpu.bus.peek(cpu.program_counter.wrapping_add(1));
             real_addr = match opcode.mode {
                 AddressingMode::Immediate => format!("#${:02X}", second_arg),
                 AddressingMode::ZeroPage => {
-                    let val = cpu.mem_read(second_arg as _);
+                    let val = cpu.bus.peek(second_arg as _);
                     format!("${second_arg:02X} = {:02X}", val)
                 },
                 AddressingMode::ZeroPage_X => {
                     let addr = second_arg.wrapping_add(cpu.register_x);
-                    format!("${second_arg:02X},X @ {addr:02X} = {:02X}", cpu.mem_read(addr as u16))
+                    format!("${second_arg:02X},X @ {addr:02X} = {:02X}", cpu.bus.peek(addr as u16))
                 }
                 AddressingMode::ZeroPage_Y => {
                     let addr = second_arg.wrapping_add(cpu.register_y);
-                    format!("${second_arg:02X},Y @ {addr:02X} = {:02X}", cpu.mem_read(addr as u16))
+                    format!("${second_arg:02X},Y @ {addr:02X} = {:02X}", cpu.bus.peek(addr as u16))
                 }
                 AddressingMode::Relative => {
                     let offset = second_arg as u16;
@@ -1047,37 +1464,47 @@ pub fn trace(cpu: &mut CPU) -> String {
                     let base = second_arg;
 
                     let ptr = base.wrapping_add(cpu.register_x);
-                    let lo = cpu.mem_read(ptr as u16);
-                    let hi = cpu.mem_read(ptr.wrapping_add(1) as u16);
+                    let lo = cpu.bus.peek(ptr as u16);
+                    let hi = cpu.bus.peek(ptr.wrapping_add(1) as u16);
                     let real_addr = u16::from_le_bytes([lo, hi]);
-                    let val = cpu.mem_read(real_addr);
+                    let val = cpu.bus.peek(real_addr);
 
                     format!("(${base:02X},X) @ {ptr:02X} = {real_addr:04X} = {val:02X}")
                 }
                 AddressingMode::Indirect_Y => {
                     let base = second_arg;
 
-                    let lo = cpu.mem_read(base as u16);
-                    let hi = cpu.mem_read(base.wrapping_add(1) as u16);
+                    let lo = cpu.bus.peek(base as u16);
+                    let hi = cpu.bus.peek(base.wrapping_add(1) as u16);
                     let ptr = u16::from_le_bytes([lo, hi]);
                     let real_addr = ptr.wrapping_add(cpu.register_y as u16);
-                    let contents = cpu.mem_read(real_addr);
+                    let contents = cpu.bus.peek(real_addr);
 
                     format!("(${base:02X}),Y = {ptr:04X} @ {real_addr:04X} = {contents:02X}")
                 }
+                AddressingMode::ZeroPage_Indirect => {
+                    let base = second_arg;
+
+                    let lo = cpu.bus.peek(base as u16);
+                    let hi = cpu.bus.peek(base.wrapping_add(1) as u16);
+                    let real_addr = u16::from_le_bytes([lo, hi]);
+                    let contents = cpu.bus.peek(real_addr);
+
+                    format!("(${base:02X}) = {real_addr:04X} = {contents:02X}")
+                }
                 _ => unreachable!("{:?}", opcode.mode),
             };
 
             format!("{:02X} {:02X}", opcode.code, second_arg)
         }
         3 => {
-            let second_arg = cpu.mem_read(cpu.program_counter + 1);
-            let third_arg = cpu.mem_read(cpu.program_counter + 2);
+            let second_arg = cpu.bus.peek(cpu.program_counter.wrapping_add(1));
+            let third_arg = cpu.bus.peek(cpu.program_counter.wrapping_add(2));
 
             real_addr = match opcode.mode {
                 AddressingMode::Absolute => {
                     if opcode.mnemonic != "JMP" && opcode.mnemonic != "JSR" {
-                        let val = cpu.mem_read(u16::from_le_bytes([second_arg, third_arg]));
+                        let val = cpu.bus.peek(u16::from_le_bytes([second_arg, third_arg]));
                         format!("${third_arg:02X}{second_arg:02X} = {val:02X}")
                     } else {
                         format!("${third_arg:02X}{second_arg:02X}")
@@ -1086,29 +1513,38 @@ pub fn trace(cpu: &mut CPU) -> String {
                 AddressingMode::Absolute_X => {
                     let base = u16::from_le_bytes([second_arg, third_arg]);
                     let real_addr = base.wrapping_add(cpu.register_x as u16);
-                    let contents = cpu.mem_read(real_addr);
+                    let contents = cpu.bus.peek(real_addr);
 
                     format!("${base:04X},X @ {real_addr:04X} = {contents:02X}")
                 }
                 AddressingMode::Absolute_Y => {
                     let base = u16::from_le_bytes([second_arg, third_arg]);
                     let real_addr = base.wrapping_add(cpu.register_y as u16);
-                    let contents = cpu.mem_read(real_addr);
+                    let contents = cpu.bus.peek(real_addr);
 
                     format!("${base:04X},Y @ {real_addr:04X} = {contents:02X}")
                 }
                 AddressingMode::Indirect => {
                     let base = u16::from_le_bytes([second_arg, third_arg]);
-                    let lo = cpu.mem_read(base);
-                    let hi = if base & 0xFF == 0xFF {
-                        cpu.mem_read(base & 0xFF00)
+                    let lo = cpu.bus.peek(base);
+                    let hi = if base & 0xFF == 0xFF && cpu.variant != Variant::Cmos65C02 {
+                        cpu.bus.peek(base & 0xFF00)
                     } else {
-                        cpu.mem_read(base + 1)
+                        cpu.bus.peek(base.wrapping_add(1))
                     };
                     let real_addr = u16::from_le_bytes([lo, hi]);
 
                     format!("(${base:04X}) = {real_addr:04X}")
                 }
+                AddressingMode::Absolute_Indirect_X => {
+                    let base = u16::from_le_bytes([second_arg, third_arg]);
+                    let ptr = base.wrapping_add(cpu.register_x as u16);
+                    let lo = cpu.bus.peek(ptr);
+                    let hi = cpu.bus.peek(ptr.wrapping_add(1));
+                    let real_addr = u16::from_le_bytes([lo, hi]);
+
+                    format!("(${base:04X},X) = {real_addr:04X}")
+                }
                 _ => unreachable!("{:?}", opcode.mode),
             };
 
@@ -1133,3 +1569,13 @@ pub fn trace(cpu: &mut CPU) -> String {
         sp = cpu.stack_pointer,
     )
 }
+
+// `trace` followed by the PPU position and CPU cycle count, in the layout of
+// Nintendulator's nestest log
+pub fn trace_with_timing(cpu: &mut CPU) -> String {
+    let ppu = cpu.bus.ppu();
+    let (scanline, dot) = (ppu.scanline(), ppu.dot());
+    let cycles = cpu.bus.cycles();
+
+    format!("{} PPU:{scanline:3},{dot:3} CYC:{cycles}", trace(cpu))
+}
@@ -0,0 +1,152 @@
+use crate::bus::ram::RamBus;
+
+use super::{
+    constants::{
+        BREAK, BREAK_2, CARRY_FLAG, DECIMAL_MODE, INTERRUPT_DISABLE, NEGATIVE_FLAG, OVERFLOW_FLAG,
+        ZERO_FLAG,
+    },
+    cpu::{Mem, STACK_RESET},
+    CPU,
+};
+
+// Where a halted program is parked: a BRK written just before jumping there
+const HALT_TRAP: u16 = 0xFFF0;
+// A program that runs this long is assumed to be stuck
+const STEP_LIMIT: usize = 100_000;
+
+// A CPU on flat RAM for unit tests. Programs run until a BRK, a jam or a
+// halt condition, and the helpers below make the assertions readable.
+pub(super) struct Harness {
+    pub cpu: CPU<RamBus>,
+    // Instructions and bus cycles the last run took, not counting the BRK
+    // or halt it stopped at
+    pub steps: usize,
+    pub cycles: usize,
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        let mut cpu = CPU::new(RamBus::new());
+        cpu.stack_pointer = STACK_RESET;
+        cpu.flags = INTERRUPT_DISABLE | BREAK_2;
+
+        Harness {
+            cpu,
+            steps: 0,
+            cycles: 0,
+        }
+    }
+
+    // Copies `program` to `addr` and points PC at it
+    pub fn load(&mut self, addr: u16, program: &[u8]) -> &mut Self {
+        self.cpu.bus.load(addr, program);
+        self.cpu.program_counter = addr;
+        self
+    }
+
+    pub fn write(&mut self, addr: u16, data: &[u8]) -> &mut Self {
+        self.cpu.bus.load(addr, data);
+        self
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        self.cpu.bus.memory[addr as usize]
+    }
+
+    // Runs until a BRK (PC is left past it) or a KIL
+    pub fn run(&mut self) -> &mut Self {
+        self.run_until(|_| false)
+    }
+
+    // Runs a single instruction
+    pub fn step(&mut self) -> &mut Self {
+        let mut first = true;
+        self.run_until(move |_| !std::mem::replace(&mut first, false))
+    }
+
+    // Runs until `halt` holds before an instruction, leaving PC on it
+    pub fn run_until<F>(&mut self, mut halt: F) -> &mut Self
+    where
+        F: FnMut(&CPU<RamBus>) -> bool,
+    {
+        let mut steps = 0;
+        let mut start = None;
+        let mut end = 0;
+        let mut halted_at = None;
+        let mut trap = 0;
+
+        self.cpu.run_with_callback(|cpu| {
+            end = cpu.bus.cycles;
+            start.get_or_insert(end);
+
+            if halt(cpu) {
+                halted_at = Some(cpu.program_counter);
+                trap = cpu.bus.mem_read(HALT_TRAP);
+                cpu.bus.mem_write(HALT_TRAP, 0x00);
+                cpu.program_counter = HALT_TRAP;
+                return;
+            }
+
+            steps += 1;
+            assert!(
+                steps <= STEP_LIMIT,
+                "program ran away, PC at {:04X}",
+                cpu.program_counter
+            );
+        });
+
+        if let Some(addr) = halted_at {
+            self.cpu.bus.mem_write(HALT_TRAP, trap);
+            self.cpu.program_counter = addr;
+        }
+
+        // The final callback ran before the BRK or trap it stopped at
+        self.steps = steps.saturating_sub(halted_at.is_none() as usize);
+        self.cycles = end - start.unwrap_or(end);
+        self
+    }
+
+    pub fn assert_registers(&self, a: u8, x: u8, y: u8) {
+        let cpu = &self.cpu;
+        assert_eq!(
+            (cpu.register_a, cpu.register_x, cpu.register_y),
+            (a, x, y),
+            "(A, X, Y)"
+        );
+    }
+
+    // Checks that every flag in `set` is on and every flag in `clear` off
+    pub fn assert_flags(&self, set: u8, clear: u8) {
+        let flags = self.cpu.flags;
+        assert!(
+            flags & set == set && flags & clear == 0,
+            "flags are {}, expected {} set and {} clear",
+            flag_names(flags),
+            flag_names(set),
+            flag_names(clear),
+        );
+    }
+}
+
+// NV-BDIZC, upper case when set
+pub(super) fn flag_names(flags: u8) -> String {
+    [
+        (NEGATIVE_FLAG, 'N'),
+        (OVERFLOW_FLAG, 'V'),
+        (BREAK_2, '-'),
+        (BREAK, 'B'),
+        (DECIMAL_MODE, 'D'),
+        (INTERRUPT_DISABLE, 'I'),
+        (ZERO_FLAG, 'Z'),
+        (CARRY_FLAG, 'C'),
+    ]
+    .iter()
+    .map(|&(flag, name)| {
+        if flags & flag != 0 {
+            name
+        } else {
+            name.to_ascii_lowercase()
+        }
+    })
+    .collect()
+}
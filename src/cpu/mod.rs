@@ -1,17 +1,22 @@
+#[allow(clippy::module_inception)]
 pub mod cpu;
+#[cfg(test)]
+mod harness;
 mod opcodes;
-pub use cpu::{constants, CPU};
+pub use cpu::{constants, Variant, CPU};
 
 #[cfg(test)]
-#[cfg(feature = "")]
 mod test {
-    use crate::cpu::constants::{CARRY_FLAG, NEGATIVE_FLAG, ZERO_FLAG};
+    use crate::{
+        bus::ram::RamBus,
+        cpu::constants::{CARRY_FLAG, NEGATIVE_FLAG, ZERO_FLAG},
+    };
 
     use super::cpu::*;
 
     #[test]
     fn test_0xa9_immediate_load_data() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0x05, 0x00]);
         assert_eq!(cpu.register_a, 0x05);
         assert!(cpu.flags & constants::ZERO_FLAG == 0b00);
@@ -20,14 +25,14 @@ mod test {
 
     #[test]
     fn test_0xa9_lda_zero_flag() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0x00, 0x00]);
         assert!(cpu.flags & constants::ZERO_FLAG == 0b10)
     }
 
     #[test]
     fn test_0xaa_tax_move_a_to_x() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load(vec![0xAA, 0x0]);
         cpu.reset();
         cpu.register_a = 10;
@@ -38,7 +43,7 @@ mod test {
 
     #[test]
     fn test_5_ops_working_together() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
 
         assert_eq!(cpu.register_x, 0xc1)
@@ -46,7 +51,7 @@ mod test {
 
     #[test]
     fn test_inx_overflow() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load(vec![0xE8, 0xE8, 0x00]);
         cpu.reset();
         cpu.register_x = 0xff;
@@ -57,7 +62,7 @@ mod test {
 
     #[test]
     fn test_lda_from_memory() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.mem_write(0x10, 0x55);
 
         cpu.load_and_run(vec![0xa5, 0x10, 0x00]);
@@ -67,7 +72,7 @@ mod test {
 
     #[test]
     fn test_adc_immediate() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
 
         cpu.load_and_run(vec![0x69, 0x10, 0x00]);
 
@@ -76,7 +81,7 @@ mod test {
 
     #[test]
     fn test_adc_from_memory() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.mem_write(0x10, 0x55);
 
         cpu.load_and_run(vec![0x65, 0x10, 0x00]);
@@ -100,7 +105,7 @@ mod test {
         for entry in table {
             let (a, b, ans, overflow, carry) = entry;
 
-            let mut cpu = CPU::new();
+            let mut cpu = CPU::new(RamBus::new());
             cpu.load(vec![0x69, b, 0x00]);
             cpu.reset();
             cpu.register_a = a;
@@ -114,7 +119,7 @@ mod test {
 
     #[test]
     fn test_and_immediate() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
 
         cpu.load_and_run(vec![0x29, 0b1111_1111, 0x00]);
 
@@ -129,7 +134,7 @@ mod test {
 
     #[test]
     fn test_and_from_memory() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
 
         cpu.mem_write(0x10, 0b1111_1111);
         cpu.load(vec![0x25, 0x10, 0x00]);
@@ -141,7 +146,7 @@ mod test {
 
     #[test]
     fn test_asl_immediate() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load(vec![0x0A, 0x00]);
         cpu.reset();
         cpu.run();
@@ -152,19 +157,19 @@ mod test {
         cpu.register_a = 0b0010_1000;
         cpu.run();
         assert_eq!(cpu.register_a, 0b0101_0000);
-        assert_eq!(cpu.check_flag(constants::CARRY_FLAG), false);
+        assert!(!cpu.check_flag(constants::CARRY_FLAG));
 
         cpu.load(vec![0x0A, 0x00]);
         cpu.reset();
         cpu.register_a = 0b1000_0001;
         cpu.run();
         assert_eq!(cpu.register_a, 0b0000_0010);
-        assert_eq!(cpu.check_flag(constants::CARRY_FLAG), true);
+        assert!(cpu.check_flag(constants::CARRY_FLAG));
     }
 
     #[test]
     fn test_asl_from_memory() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.mem_write(0x10, 0b0010_1000);
         cpu.load_and_run(vec![0x06, 0x10, 0x00]);
         assert_eq!(cpu.mem_read(0x10), 0b0101_0000);
@@ -172,14 +177,14 @@ mod test {
 
     #[test]
     fn test_bcc() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xE8, 0x90, 0x02, 0x85, 0x22, 0x00]);
         assert_ne!(cpu.register_a, 0x22);
     }
 
     #[test]
     fn test_bit() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.mem_write(0x10, 0b0000_1010);
         cpu.mem_write(0x11, 0b1000_1111);
         cpu.mem_write(0x12, 0b0100_0101);
@@ -196,7 +201,7 @@ mod test {
 
     #[test]
     fn test_cmp() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0x22, 0xC9, 0x22, 0x00]);
         assert!(cpu.check_flag(ZERO_FLAG));
 
@@ -211,7 +216,7 @@ mod test {
 
     #[test]
     fn test_dec() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.mem_write(0x10, 0x11);
         cpu.load_and_run(vec![0xC6, 0x10, 0xC6, 0x10, 0x00]);
         assert_eq!(cpu.mem_read(0x10), 0x0F);
@@ -223,14 +228,14 @@ mod test {
 
     #[test]
     fn test_eor() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0xFF, 0x49, 0xAA, 0x00]);
         assert_eq!(cpu.register_a, 0xFF ^ 0xAA);
     }
 
     #[test]
     fn test_inc() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.mem_write(0x10, 0xFF);
         cpu.load_and_run(vec![0xE6, 0x10, 0xE6, 0x10, 0x00]);
         assert_eq!(cpu.mem_read(0x10), 0x01);
@@ -251,7 +256,7 @@ mod test {
             BRK
         */
 
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![
             0xA9, 0x08, 0x85, 0x11, 0x24, 0x11, 0xD0, 0x07, 0xE6, 0x10, 0xA5, 0x10, 0x4C, 0x04,
             0x06, 0x00,
@@ -262,7 +267,7 @@ mod test {
 
     #[test]
     fn test_jmp_indirect() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
 
         cpu.load_and_run(vec![
             0xA9, 0x01, 0x85, 0xF0, 0xA9, 0xCC, 0x85, 0xF1, 0x6C, 0xF0, 0x00, 0x00,
@@ -272,15 +277,15 @@ mod test {
 
     #[test]
     fn test_jsr() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0x20, 0x03, 0x80, 0x00]);
-        assert_eq!(cpu.get_stack_top_u16(), 0x8003);
-        1539;
+        // Return address minus one, as JSR pushes it
+        assert_eq!(cpu.get_stack_top_u16(), 0x0602);
     }
 
     #[test]
     fn test_lsr() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0xFF, 0x4A, 0x00]);
         assert_eq!(cpu.register_a, 0x7F);
         assert!(cpu.check_flag(CARRY_FLAG));
@@ -288,28 +293,28 @@ mod test {
 
     #[test]
     fn test_ora() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0x0F, 0x09, 0x0A, 0x00]);
         assert_eq!(cpu.register_a, 0x0F);
     }
 
     #[test]
     fn test_pha() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0x55, 0x48, 0x00]);
         assert_eq!(cpu.get_stack_top(), 0x55);
     }
 
     #[test]
     fn test_pla() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0x55, 0x48, 0xA9, 0x00, 0x68, 0x00]);
         assert_eq!(cpu.register_a, 0x55);
     }
 
     #[test]
     fn test_plp() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.mem_write(0x10, 0xFF);
         cpu.load_and_run(vec![0xE6, 0x10, 0x08, 0xE6, 0x10, 0x28, 0x00]);
         assert!(cpu.check_flag(ZERO_FLAG));
@@ -317,7 +322,7 @@ mod test {
 
     #[test]
     fn test_rol() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0xFE, 0x2A, 0x00]);
         assert_eq!(cpu.register_a, 0xFC);
         assert!(cpu.check_flag(CARRY_FLAG));
@@ -325,7 +330,7 @@ mod test {
 
     #[test]
     fn test_ror() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0x7F, 0x6A, 0x00]);
         assert_eq!(cpu.register_a, 0x3F);
         assert!(cpu.check_flag(CARRY_FLAG));
@@ -333,7 +338,7 @@ mod test {
 
     #[test]
     fn test_rti() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0x33, 0x48, 0x48, 0xA9, 0xFF, 0x08, 0x40, 0x00]);
         assert_eq!(cpu.program_counter, 0x3334);
         assert!(cpu.check_flag(NEGATIVE_FLAG));
@@ -341,7 +346,7 @@ mod test {
 
     #[test]
     fn test_jsr_rts() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![
             0x20, 0x09, 0x80, 0x20, 0x0c, 0x80, 0x20, 0x12, 0x80, 0xa2, 0x00, 0x60, 0xe8, 0xe0,
             0x05, 0xd0, 0xfb, 0x60, 0x00,
@@ -350,8 +355,1124 @@ mod test {
 
     #[test]
     fn test_sbc() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(RamBus::new());
         cpu.load_and_run(vec![0xA9, 0x22, 0xE9, 0x12, 0x00]);
         assert_eq!(cpu.register_a, 0x0F);
     }
+
+    #[test]
+    fn test_pc_wraps_at_top_of_memory() {
+        // LDA $1234 with its operand split across $FFFF and $0000, then INX
+        let mut bus = RamBus::new();
+        bus.load(0xFFFE, &[0xAD, 0x34, 0x12, 0xE8, 0x00]);
+        bus.memory[0x1234] = 0x42;
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0xFFFE;
+        assert!(trace(&mut cpu).starts_with("FFFE  AD 34 12  LDA $1234 = 42"));
+        cpu.run_with_callback(|_| {});
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.register_x, 0x01);
+
+        // JSR $0010 at $FFFF returns to $0002, past its last byte at $0001
+        let mut bus = RamBus::new();
+        bus.load(0xFFFF, &[0x20, 0x10, 0x00, 0xE8, 0x00]);
+        bus.memory[0x0010] = 0x60; // RTS
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0xFFFF;
+        assert!(trace(&mut cpu).starts_with("FFFF  20 10 00  JSR $0010"));
+        cpu.run_with_callback(|_| {});
+        assert_eq!(cpu.register_x, 0x01);
+        assert_eq!(cpu.program_counter, 0x0004);
+    }
+}
+
+// NROM-128 with RAM-resident test code; the reset vector is unused.
+#[cfg(test)]
+fn nrom_cpu() -> CPU {
+    CPU::new(crate::bus::bus::Bus::new(crate::cartridge::test_rom(&[], &[])).unwrap())
+}
+
+#[cfg(test)]
+mod cycle_test {
+    use super::{
+        constants::INTERRUPT_DISABLE,
+        cpu::{trace, Mem},
+        nrom_cpu,
+        opcodes::{Instruction, OPCODES},
+        CPU,
+    };
+
+    fn cycle_stepped_cpu() -> CPU {
+        let mut cpu = nrom_cpu();
+        cpu.cycle_stepped = true;
+        cpu
+    }
+
+    // Runs the instruction at $0300 and returns the CPU cycles it took.
+    fn run_one(cpu: &mut CPU, code: &[u8]) -> usize {
+        for (idx, &byte) in code.iter().enumerate() {
+            cpu.bus.mem_write(0x0300 + idx as u16, byte);
+        }
+        // BRK to stop at
+        cpu.bus.mem_write(0x0400, 0x00);
+
+        cpu.program_counter = 0x0300;
+        let mut start = None;
+        let mut end = 0;
+        cpu.run_with_callback(|cpu| match start {
+            None => start = Some(cpu.bus.cycles()),
+            Some(_) => {
+                end = cpu.bus.cycles();
+                cpu.program_counter = 0x0400;
+            }
+        });
+
+        end - start.unwrap()
+    }
+
+    #[test]
+    fn test_cycle_stepped_timing_matches_table() {
+        for (code, opcode) in OPCODES.iter().enumerate() {
+            if matches!(opcode.instruction, Instruction::Brk | Instruction::Kil) {
+                continue;
+            }
+
+            let mut cpu = cycle_stepped_cpu();
+            // No page crossings, and every branch falls through
+            cpu.register_x = 0;
+            cpu.register_y = 0;
+            cpu.stack_pointer = 0xF0;
+            cpu.flags = match opcode.instruction {
+                Instruction::Bne | Instruction::Bcc | Instruction::Bpl | Instruction::Bvc => 0xFF,
+                _ => 0x00,
+            };
+
+            let cycles = run_one(&mut cpu, &[code as u8, 0x10, 0x02]);
+            assert_eq!(
+                cycles, opcode.cycles as usize,
+                "{} ({code:02X})",
+                opcode.mnemonic
+            );
+        }
+    }
+
+    #[test]
+    fn test_cycle_stepped_extra_cycles() {
+        // LDA $02F0,X crossing into $0300
+        let mut cpu = cycle_stepped_cpu();
+        cpu.register_x = 0x20;
+        assert_eq!(run_one(&mut cpu, &[0xBD, 0xF0, 0x02]), 5);
+
+        // Taken BNE within the page, then across it
+        let mut cpu = cycle_stepped_cpu();
+        assert_eq!(run_one(&mut cpu, &[0xD0, 0x10]), 3);
+        let mut cpu = cycle_stepped_cpu();
+        assert_eq!(run_one(&mut cpu, &[0xD0, 0x80]), 4);
+
+        // INC $10 reads, writes the old value back, then the result
+        let mut cpu = cycle_stepped_cpu();
+        cpu.bus.mem_write(0x10, 0x41);
+        assert_eq!(run_one(&mut cpu, &[0xE6, 0x10]), 5);
+        assert_eq!(cpu.mem_read(0x10), 0x42);
+    }
+
+    #[test]
+    fn test_indexed_write_dummy_reads_ppudata() {
+        // STA $2000,X with X = 7 reads $2007 before writing it, so the VRAM
+        // address moves twice; instruction-stepped it only sees the write
+        for (cycle_stepped, vram_addr) in [(true, 0x2102), (false, 0x2101)] {
+            let mut cpu = nrom_cpu();
+            cpu.cycle_stepped = cycle_stepped;
+            cpu.bus.mem_write(0x2006, 0x21);
+            cpu.bus.mem_write(0x2006, 0x00);
+            cpu.register_x = 0x07;
+
+            run_one(&mut cpu, &[0x9D, 0x00, 0x20]);
+            assert_eq!(cpu.bus.ppu().vram_addr(), vram_addr);
+        }
+    }
+
+    #[test]
+    fn test_page_cross_dummy_read_acknowledges_frame_irq() {
+        // LDA $40F0,X with X = $25 reads $4015 before $4115, which clears
+        // the frame IRQ flag
+        for (cycle_stepped, irq_left) in [(true, false), (false, true)] {
+            let mut cpu = nrom_cpu();
+            cpu.cycle_stepped = cycle_stepped;
+            cpu.bus.mem_write(0x4017, 0x00);
+            while !cpu.bus.poll_irq() {
+                cpu.bus.tick(1);
+            }
+            cpu.register_x = 0x25;
+            cpu.flags = INTERRUPT_DISABLE;
+
+            run_one(&mut cpu, &[0xBD, 0xF0, 0x40]);
+            assert_eq!(cpu.bus.poll_irq(), irq_left);
+        }
+    }
+
+    #[test]
+    fn test_write_only_registers_read_open_bus() {
+        // LDA $2000 sees the last byte on the bus, the operand's high byte
+        let mut cpu = cycle_stepped_cpu();
+        run_one(&mut cpu, &[0xAD, 0x00, 0x20]);
+        assert_eq!(cpu.register_a, 0x20);
+
+        // LDA ($10),Y crossing from $20F0 to $2100, also write-only
+        let mut cpu = cycle_stepped_cpu();
+        cpu.bus.mem_write(0x10, 0xF0);
+        cpu.bus.mem_write(0x11, 0x20);
+        cpu.register_y = 0x10;
+        run_one(&mut cpu, &[0xB1, 0x10]);
+        assert_eq!(cpu.register_a, 0x20);
+
+        // APU registers, as APU init loops dummy read them
+        let mut cpu = cycle_stepped_cpu();
+        run_one(&mut cpu, &[0xAD, 0x03, 0x40]);
+        assert_eq!(cpu.register_a, 0x40);
+    }
+
+    #[test]
+    fn test_peek_leaves_cartridge_registers_alone() {
+        use crate::{bus::bus::Bus, cartridge::Rom};
+
+        let rom = Rom::from_fds(b"\x01*NINTENDO-HVC*", &[0; 0x2000]).unwrap();
+        let mut cpu = CPU::new(Bus::new(rom).unwrap());
+
+        // Timer IRQ after 4 cycles, then LDA $4030 at $6000
+        cpu.bus.mem_write(0x4023, 0x01);
+        cpu.bus.mem_write(0x4020, 3);
+        cpu.bus.mem_write(0x4021, 0);
+        cpu.bus.mem_write(0x4022, 0x02);
+        cpu.bus.tick(4);
+        assert!(cpu.bus.poll_irq());
+        for (idx, byte) in [0xAD, 0x30, 0x40].into_iter().enumerate() {
+            cpu.bus.mem_write(0x6000 + idx as u16, byte);
+        }
+        cpu.program_counter = 0x6000;
+
+        assert_eq!(cpu.bus.peek(0x4030), 0xFF);
+        assert!(trace(&mut cpu).contains("LDA $4030 = FF"));
+        assert!(cpu.bus.poll_irq());
+
+        // Reading it for real acknowledges the IRQ
+        cpu.bus.mem_read(0x4030);
+        assert!(!cpu.bus.poll_irq());
+    }
+
+    #[test]
+    fn test_ppudata_mirrors() {
+        let mut cpu = cycle_stepped_cpu();
+        let set_addr = |cpu: &mut CPU, addr: u16| {
+            cpu.bus.mem_read(0x2002);
+            cpu.bus.mem_write(0x2006, (addr >> 8) as u8);
+            cpu.bus.mem_write(0x2006, addr as u8);
+        };
+        // PPUSTATUS is read-only; writes are dropped
+        cpu.bus.mem_write(0x2002, 0xFF);
+
+        set_addr(&mut cpu, 0x2005);
+        cpu.bus.mem_write(0x2007, 0x42);
+        set_addr(&mut cpu, 0x3F10);
+        cpu.bus.mem_write(0x2007, 0x0F);
+        set_addr(&mut cpu, 0x3F25);
+        cpu.bus.mem_write(0x2007, 0x16);
+
+        // $3000-$3EFF mirror the nametables, buffered like them
+        set_addr(&mut cpu, 0x3005);
+        cpu.bus.mem_read(0x2007);
+        assert_eq!(cpu.bus.mem_read(0x2007), 0x42);
+
+        // $3F10 mirrors $3F00, and $3F20-$3FFF mirror $3F00-$3F1F
+        set_addr(&mut cpu, 0x3F00);
+        assert_eq!(cpu.bus.mem_read(0x2007), 0x0F);
+        set_addr(&mut cpu, 0x3FE5);
+        assert_eq!(cpu.bus.mem_read(0x2007), 0x16);
+    }
+
+    #[test]
+    fn test_stack_pointer_wraps() {
+        // PHA with S = $00 stores at $0100, then PLA reads it back from there
+        let mut cpu = cycle_stepped_cpu();
+        cpu.stack_pointer = 0x00;
+        cpu.register_a = 0x5A;
+        run_one(&mut cpu, &[0x48]);
+        assert_eq!(cpu.stack_pointer, 0xFF);
+        assert_eq!(cpu.mem_read(0x0100), 0x5A);
+
+        cpu.register_a = 0;
+        run_one(&mut cpu, &[0x68]);
+        assert_eq!(cpu.stack_pointer, 0x00);
+        assert_eq!(cpu.register_a, 0x5A);
+    }
+
+    #[test]
+    fn test_kil_jams_until_reset() {
+        let mut cpu = cycle_stepped_cpu();
+        cpu.bus.mem_write(0x0300, 0xE8); // INX
+        cpu.bus.mem_write(0x0301, 0x02); // KIL
+        cpu.bus.mem_write(0x0302, 0xE8);
+        cpu.program_counter = 0x0300;
+        cpu.run_with_callback(|_| {});
+
+        assert_eq!(cpu.jammed, Some(0x0301));
+        assert_eq!(cpu.program_counter, 0x0301);
+        assert_eq!(cpu.register_x, 1);
+
+        // Stays halted until reset
+        cpu.run_with_callback(|_| panic!("jammed CPU ran"));
+        cpu.reset();
+        assert_eq!(cpu.jammed, None);
+    }
+
+    #[test]
+    fn test_unstable_magic() {
+        // XAA #$F0: A = (A | magic) & X & imm
+        let mut cpu = cycle_stepped_cpu();
+        cpu.register_a = 0x01;
+        cpu.register_x = 0x3C;
+        run_one(&mut cpu, &[0x8B, 0xF0]);
+        assert_eq!(cpu.register_a, 0x20);
+
+        // LXA #$F0: A = X = (A | magic) & imm
+        let mut cpu = cycle_stepped_cpu();
+        cpu.unstable_magic = 0xFF;
+        cpu.register_a = 0x01;
+        run_one(&mut cpu, &[0xAB, 0xF0]);
+        assert_eq!(cpu.register_a, 0xF0);
+        assert_eq!(cpu.register_x, 0xF0);
+    }
+}
+
+#[cfg(test)]
+mod decimal_test {
+    use super::{
+        constants::{
+            CARRY_FLAG, DECIMAL_MODE, INTERRUPT_DISABLE, NEGATIVE_FLAG, OVERFLOW_FLAG, ZERO_FLAG,
+        },
+        cpu::Mem,
+        nrom_cpu, Variant, CPU,
+    };
+
+    const ARITHMETIC_FLAGS: u8 = NEGATIVE_FLAG | OVERFLOW_FLAG | ZERO_FLAG | CARRY_FLAG;
+
+    fn decimal_cpu(variant: Variant) -> CPU {
+        let mut cpu = nrom_cpu();
+        cpu.variant = variant;
+        cpu.bus.mem_write(0x0302, 0x00);
+        cpu
+    }
+
+    // Runs `opcode #operand` and returns A and the N, V, Z and C flags
+    fn run(cpu: &mut CPU, opcode: u8, a: u8, operand: u8, carry: bool) -> (u8, u8) {
+        cpu.bus.mem_write(0x0300, opcode);
+        cpu.bus.mem_write(0x0301, operand);
+        cpu.register_a = a;
+        // Keep the APU frame IRQ out of the way
+        cpu.flags = INTERRUPT_DISABLE | DECIMAL_MODE | if carry { CARRY_FLAG } else { 0 };
+        cpu.program_counter = 0x0300;
+
+        let mut first = true;
+        cpu.run_with_callback(|cpu| {
+            if !first {
+                cpu.program_counter = 0x0302;
+            }
+            first = false;
+        });
+
+        (cpu.register_a, cpu.flags & ARITHMETIC_FLAGS)
+    }
+
+    fn flags(n: bool, v: bool, z: bool, c: bool) -> u8 {
+        [
+            (n, NEGATIVE_FLAG),
+            (v, OVERFLOW_FLAG),
+            (z, ZERO_FLAG),
+            (c, CARRY_FLAG),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |acc, (_, flag)| acc | flag)
+    }
+
+    // Bruce Clark's description of decimal ADC and SBC, the model the Klaus
+    // Dormann decimal test checks against
+    fn reference_adc(variant: Variant, a: u8, b: u8, carry: bool) -> (u8, u8) {
+        let (a, b, c) = (a as i32, b as i32, carry as i32);
+
+        let mut al = (a & 0x0F) + (b & 0x0F) + c;
+        if al >= 0x0A {
+            al = ((al + 0x06) & 0x0F) + 0x10;
+        }
+
+        let signed = ((a & 0xF0) as u8 as i8) as i32 + ((b & 0xF0) as u8 as i8) as i32 + al;
+        let n = signed & 0x80 != 0;
+        let v = !(-128..=127).contains(&signed);
+
+        let mut sum = (a & 0xF0) + (b & 0xF0) + al;
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        let z = (a + b + c) & 0xFF == 0;
+
+        let res = sum as u8;
+        match variant {
+            Variant::Cmos65C02 => (res, flags(res & 0x80 != 0, v, res == 0, sum >= 0x100)),
+            _ => (res, flags(n, v, z, sum >= 0x100)),
+        }
+    }
+
+    fn reference_sbc(variant: Variant, a: u8, b: u8, carry: bool) -> (u8, u8) {
+        let (a, b, c) = (a as i32, b as i32, carry as i32);
+
+        let binary = a - b + c - 1;
+        let result = binary as u8;
+        let n = result & 0x80 != 0;
+        let v = (a ^ b) & (a ^ result as i32) & 0x80 != 0;
+
+        let mut al = (a & 0x0F) - (b & 0x0F) + c - 1;
+        if variant == Variant::Cmos65C02 {
+            let mut diff = binary;
+            if diff < 0 {
+                diff -= 0x60;
+            }
+            if al < 0 {
+                diff -= 0x06;
+            }
+
+            let res = diff as u8;
+            return (res, flags(res & 0x80 != 0, v, res == 0, binary >= 0));
+        }
+
+        if al < 0 {
+            al = ((al - 0x06) & 0x0F) - 0x10;
+        }
+        let mut diff = (a & 0xF0) - (b & 0xF0) + al;
+        if diff < 0 {
+            diff -= 0x60;
+        }
+
+        (diff as u8, flags(n, v, result == 0, binary >= 0))
+    }
+
+    #[test]
+    fn test_decimal_matches_reference() {
+        for variant in [Variant::Nmos6502, Variant::Cmos65C02] {
+            let mut cpu = decimal_cpu(variant);
+
+            for a in 0..=0xFF {
+                for b in 0..=0xFF {
+                    for carry in [false, true] {
+                        let adc = run(&mut cpu, 0x69, a, b, carry);
+                        assert_eq!(
+                            adc,
+                            reference_adc(variant, a, b, carry),
+                            "{variant:?} ADC {a:02X} {b:02X} {carry}"
+                        );
+
+                        let sbc = run(&mut cpu, 0xE9, a, b, carry);
+                        assert_eq!(
+                            sbc,
+                            reference_sbc(variant, a, b, carry),
+                            "{variant:?} SBC {a:02X} {b:02X} {carry}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decimal_mode() {
+        let mut cpu = decimal_cpu(Variant::Nmos6502);
+        assert_eq!(run(&mut cpu, 0x69, 0x19, 0x28, true).0, 0x48);
+        // N follows the high digit before the decimal adjust
+        assert_eq!(
+            run(&mut cpu, 0x69, 0x99, 0x01, false),
+            (0x00, NEGATIVE_FLAG | CARRY_FLAG)
+        );
+        assert_eq!(run(&mut cpu, 0xE9, 0x40, 0x01, true).0, 0x39);
+
+        // The 2A03 ignores the decimal flag
+        let mut cpu = decimal_cpu(Variant::Ricoh2A03);
+        assert_eq!(run(&mut cpu, 0x69, 0x19, 0x28, true).0, 0x42);
+        assert_eq!(run(&mut cpu, 0xE9, 0x40, 0x01, true).0, 0x3F);
+    }
+}
+
+#[cfg(test)]
+mod cmos_test {
+    use super::{
+        constants::INTERRUPT_DISABLE,
+        cpu::{trace, Mem},
+        nrom_cpu, Variant, CPU,
+    };
+
+    // Runs the program at $0300 up to its BRK
+    fn run_program(variant: Variant, program: &[u8]) -> CPU {
+        let mut cpu = nrom_cpu();
+        cpu.variant = variant;
+        for (idx, &byte) in program.iter().enumerate() {
+            cpu.bus.mem_write(0x0300 + idx as u16, byte);
+        }
+        // ($20) points at $0400, and so does the JMP ($04FF) page-wrap bug
+        cpu.bus.mem_write_u16(0x20, 0x0400);
+        cpu.bus.mem_write(0x0400, 0x55);
+        cpu.bus.mem_write(0x04FF, 0x20);
+        cpu.bus.mem_write(0x0500, 0x03);
+        cpu.bus.mem_write(0x10, 0xFF);
+        cpu.bus.mem_write(0x11, 0xF0);
+        cpu.bus.mem_write(0x12, 0xFF);
+
+        cpu.flags = INTERRUPT_DISABLE;
+        cpu.program_counter = 0x0300;
+        cpu.run_with_callback(|_| {});
+        cpu
+    }
+
+    #[test]
+    fn test_65c02_instructions() {
+        let mut cpu = run_program(
+            Variant::Cmos65C02,
+            &[
+                0xA2, 0x12, // LDX #$12
+                0xDA, // PHX
+                0x7A, // PLY
+                0x64, 0x10, // STZ $10
+                0xA9, 0x0F, // LDA #$0F
+                0x04, 0x11, // TSB $11
+                0x14, 0x12, // TRB $12
+                0xB2, 0x20, // LDA ($20)
+                0x1A, // INC A
+                0x03, // undefined, 1 byte
+                0x02, 0xEA, // undefined, 2 bytes
+                0x80, 0x02, // BRA +2
+                0xA9, 0x00, // LDA #$00, skipped
+                0x6C, 0xFF, 0x04, // JMP ($04FF)
+            ],
+        );
+
+        assert_eq!(cpu.register_y, 0x12);
+        assert_eq!(cpu.mem_read(0x10), 0x00);
+        assert_eq!(cpu.mem_read(0x11), 0xFF);
+        assert_eq!(cpu.mem_read(0x12), 0xF0);
+        assert_eq!(cpu.register_a, 0x56);
+        // Stopped at the BRK at $0320, past the page boundary
+        assert_eq!(cpu.program_counter, 0x0321);
+    }
+
+    #[test]
+    fn test_nmos_jmp_indirect_wraps() {
+        let cpu = run_program(Variant::Nmos6502, &[0x6C, 0xFF, 0x04]);
+        // Pointer high byte came from $0400, landing on $5520
+        assert_eq!(cpu.program_counter, 0x5521);
+    }
+
+    #[test]
+    fn test_65c02_jmp_indirect_at_top_of_memory() {
+        let mut cpu = run_program(
+            Variant::Cmos65C02,
+            &[
+                0xA9, 0x05, // LDA #$05
+                0x85, 0x00, // STA $00
+                0x6C, 0xFF, 0xFF, // JMP ($FFFF)
+            ],
+        );
+        // Low byte from $FFFF, high from $0000: $0500, an undefined 1-byte
+        // opcode and then a BRK
+        assert_eq!(cpu.program_counter, 0x0502);
+
+        // The trace reads the pointer the same way
+        cpu.program_counter = 0x0304;
+        assert!(trace(&mut cpu).contains("JMP ($FFFF) = 0500"));
+    }
+}
+
+// Every opcode in the NMOS table, run through each of its addressing modes
+// on flat RAM. Opcodes are grouped into families sharing one expectation.
+#[cfg(test)]
+mod opcode_test {
+    use super::{
+        constants::{
+            BREAK, BREAK_2, CARRY_FLAG, DECIMAL_MODE, INTERRUPT_DISABLE, NEGATIVE_FLAG,
+            OVERFLOW_FLAG, ZERO_FLAG,
+        },
+        cpu::AddressingMode,
+        harness::{flag_names, Harness},
+        opcodes::{Access, Instruction, Opcode, OPCODES},
+    };
+    use Instruction::*;
+
+    const PROGRAM: u16 = 0x0600;
+    // Index registers the indexed addressing modes run with
+    const INDEX_X: u8 = 0x05;
+    const INDEX_Y: u8 = 0x03;
+
+    const LOADS: &[Instruction] = &[Lda, Ldx, Ldy, Lax];
+    const STORES: &[Instruction] = &[Sta, Stx, Sty, Sax];
+    const OPERATIONS: &[Instruction] = &[
+        Ora, And, Eor, Adc, Sbc, Cmp, Cpx, Cpy, Bit, Asl, Lsr, Rol, Ror, Inc, Dec, Slo, Sre, Rla,
+        Rra, Isb, Dcp, Aac, Asr, Arr, Atx,
+    ];
+    const UNSTABLE: &[Instruction] = &[Axs, Xaa, Axa, Sxa, Sya, Xas, Lar];
+    const NOPS: &[Instruction] = &[Nop, Dop, Top];
+    const BRANCHES: &[(Instruction, u8, bool)] = &[
+        (Bcc, CARRY_FLAG, false),
+        (Bcs, CARRY_FLAG, true),
+        (Bne, ZERO_FLAG, false),
+        (Beq, ZERO_FLAG, true),
+        (Bvc, OVERFLOW_FLAG, false),
+        (Bvs, OVERFLOW_FLAG, true),
+        (Bpl, NEGATIVE_FLAG, false),
+        (Bmi, NEGATIVE_FLAG, true),
+    ];
+    const FLAG_OPS: &[(Instruction, u8, bool)] = &[
+        (Clc, CARRY_FLAG, false),
+        (Sec, CARRY_FLAG, true),
+        (Cld, DECIMAL_MODE, false),
+        (Sed, DECIMAL_MODE, true),
+        (Cli, INTERRUPT_DISABLE, false),
+        (Sei, INTERRUPT_DISABLE, true),
+        (Clv, OVERFLOW_FLAG, false),
+    ];
+    const TRANSFERS: &[Instruction] = &[Tax, Tay, Txa, Tya, Tsx, Txs, Inx, Iny, Dex, Dey];
+    const STACK: &[Instruction] = &[Pha, Php, Pla, Plp];
+    const CONTROL: &[Instruction] = &[Jmp, Jsr, Rts, Rti, Brk, Kil];
+
+    fn opcodes_for(instruction: Instruction) -> impl Iterator<Item = &'static Opcode> {
+        let table: &'static [Opcode; 256] = &OPCODES;
+        table.iter().filter(move |op| op.instruction == instruction)
+    }
+
+    fn opcode_named(code: u8) -> String {
+        let op = &OPCODES[code as usize];
+        format!("{} {:?} ({code:02X})", op.mnemonic, op.mode)
+    }
+
+    // Loads `opcode` at PROGRAM with an operand leading it to `value`, the
+    // indexed modes optionally across a page (or around the zero page), and
+    // returns the effective address.
+    fn place_operand(h: &mut Harness, opcode: &Opcode, value: u8, cross_page: bool) -> u16 {
+        let pointer_to = |h: &mut Harness, ptr: u8, target: u16| {
+            let [lo, hi] = target.to_le_bytes();
+            h.write(ptr as u16, &[lo]);
+            h.write(ptr.wrapping_add(1) as u16, &[hi]);
+        };
+        let indexed_base = if cross_page { 0x12FE } else { 0x1230 };
+
+        let (operand, addr) = match opcode.mode {
+            AddressingMode::Implied => (vec![], 0),
+            AddressingMode::Accumulator => {
+                h.cpu.register_a = value;
+                (vec![], 0)
+            }
+            AddressingMode::Immediate => (vec![value], PROGRAM + 1),
+            AddressingMode::ZeroPage => (vec![0x40], 0x0040),
+            // Both zero page indexed modes wrap to the start of the page
+            AddressingMode::ZeroPage_X => {
+                h.cpu.register_x = INDEX_X;
+                (vec![0xFE], 0x0003)
+            }
+            AddressingMode::ZeroPage_Y => {
+                h.cpu.register_y = INDEX_Y;
+                (vec![0xFE], 0x0001)
+            }
+            AddressingMode::Absolute => (vec![0x34, 0x12], 0x1234),
+            AddressingMode::Absolute_X => {
+                h.cpu.register_x = INDEX_X;
+                let base: u16 = indexed_base;
+                (base.to_le_bytes().to_vec(), base + INDEX_X as u16)
+            }
+            AddressingMode::Absolute_Y => {
+                h.cpu.register_y = INDEX_Y;
+                let base: u16 = indexed_base;
+                (base.to_le_bytes().to_vec(), base + INDEX_Y as u16)
+            }
+            // Crossing here means a pointer at $FF, wrapping to $00
+            AddressingMode::Indirect_X => {
+                h.cpu.register_x = INDEX_X;
+                let operand = if cross_page { 0xFA } else { 0x20 };
+                pointer_to(h, operand + INDEX_X, 0x1234);
+                (vec![operand], 0x1234)
+            }
+            AddressingMode::Indirect_Y => {
+                h.cpu.register_y = INDEX_Y;
+                pointer_to(h, 0x50, indexed_base);
+                (vec![0x50], indexed_base + INDEX_Y as u16)
+            }
+            ref mode => unreachable!("{mode:?}"),
+        };
+
+        let mut program = vec![opcode.code];
+        program.extend(operand);
+        h.load(PROGRAM, &program);
+        if addr != 0 && !matches!(opcode.mode, AddressingMode::Immediate) {
+            h.write(addr, &[value]);
+        }
+
+        addr
+    }
+
+    // Runs `opcode` once, cycle-stepped, after `setup` and the operand
+    // placement, and checks it took the cycles the table says it does.
+    fn run_case<F>(opcode: &Opcode, value: u8, cross_page: bool, setup: F) -> (Harness, u16)
+    where
+        F: FnOnce(&mut Harness),
+    {
+        let mut h = Harness::new();
+        h.cpu.cycle_stepped = true;
+        setup(&mut h);
+        let addr = place_operand(&mut h, opcode, value, cross_page);
+        h.step();
+
+        let penalty = cross_page
+            && opcode.instruction.access() == Access::Read
+            && matches!(
+                opcode.mode,
+                AddressingMode::Absolute_X
+                    | AddressingMode::Absolute_Y
+                    | AddressingMode::Indirect_Y
+            );
+        assert_eq!(
+            h.cycles,
+            opcode.cycles as usize + penalty as usize,
+            "cycles of {} crossing {cross_page}",
+            opcode_named(opcode.code)
+        );
+
+        (h, addr)
+    }
+
+    #[test]
+    fn test_loads() {
+        for &instruction in LOADS {
+            for opcode in opcodes_for(instruction) {
+                for (value, set, clear) in [
+                    (0x00, ZERO_FLAG, NEGATIVE_FLAG),
+                    (0x80, NEGATIVE_FLAG, ZERO_FLAG),
+                    (0x42, 0, NEGATIVE_FLAG | ZERO_FLAG),
+                ] {
+                    for cross_page in [false, true] {
+                        let (h, _) = run_case(opcode, value, cross_page, |_| {});
+                        let loaded = match instruction {
+                            Ldx => h.cpu.register_x,
+                            Ldy => h.cpu.register_y,
+                            _ => h.cpu.register_a,
+                        };
+                        assert_eq!(loaded, value, "{}", opcode_named(opcode.code));
+                        if instruction == Lax {
+                            assert_eq!(h.cpu.register_x, value);
+                        }
+                        h.assert_flags(set, clear);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_stores() {
+        for &instruction in STORES {
+            for opcode in opcodes_for(instruction) {
+                for cross_page in [false, true] {
+                    let (h, addr) = run_case(opcode, 0x00, cross_page, |h| {
+                        h.cpu.register_a = 0xC3;
+                        h.cpu.register_x = 0x5A;
+                        h.cpu.register_y = 0x3C;
+                    });
+                    let cpu = &h.cpu;
+                    let (a, x, y) = (cpu.register_a, cpu.register_x, cpu.register_y);
+                    let expected = match instruction {
+                        Sta => a,
+                        Stx => x,
+                        Sty => y,
+                        _ => a & x,
+                    };
+                    assert_eq!(h.read(addr), expected, "{}", opcode_named(opcode.code));
+                    assert_eq!(
+                        cpu.flags,
+                        Harness::new().cpu.flags,
+                        "stores leave flags alone"
+                    );
+                }
+            }
+        }
+    }
+
+    // Instruction, A (X for CPX, Y for CPY), operand, carry in, register
+    // after, operand after (memory, or A in accumulator mode), flags set,
+    // flags clear
+    type OperationCase = (Instruction, u8, u8, bool, u8, u8, u8, u8);
+
+    const N: u8 = NEGATIVE_FLAG;
+    const V: u8 = OVERFLOW_FLAG;
+    const Z: u8 = ZERO_FLAG;
+    const C: u8 = CARRY_FLAG;
+
+    #[rustfmt::skip]
+    const OPERATION_CASES: &[OperationCase] = &[
+        (Ora, 0x0F, 0x80, false, 0x8F, 0x80, N, Z),
+        (Ora, 0x00, 0x00, false, 0x00, 0x00, Z, N),
+        (And, 0xF0, 0x8F, false, 0x80, 0x8F, N, Z),
+        (And, 0xF0, 0x0F, false, 0x00, 0x0F, Z, N),
+        (Eor, 0xFF, 0x7F, false, 0x80, 0x7F, N, Z),
+        (Eor, 0x55, 0x55, false, 0x00, 0x55, Z, N),
+        (Adc, 0x10, 0x20, true, 0x31, 0x20, 0, N | V | Z | C),
+        (Adc, 0x7F, 0x01, false, 0x80, 0x01, N | V, Z | C),
+        (Adc, 0xFF, 0x01, false, 0x00, 0x01, Z | C, N | V),
+        (Adc, 0x80, 0x80, false, 0x00, 0x80, Z | C | V, N),
+        (Sbc, 0x42, 0x42, true, 0x00, 0x42, Z | C, N | V),
+        (Sbc, 0x80, 0x01, true, 0x7F, 0x01, V | C, N | Z),
+        (Sbc, 0x00, 0x01, true, 0xFF, 0x01, N, V | Z | C),
+        (Sbc, 0x50, 0x50, false, 0xFF, 0x50, N, V | Z | C),
+        (Cmp, 0x42, 0x42, false, 0x42, 0x42, Z | C, N),
+        (Cmp, 0x41, 0x42, true, 0x41, 0x42, N, Z | C),
+        (Cmp, 0x43, 0x42, false, 0x43, 0x42, C, N | Z),
+        (Cpx, 0x42, 0x42, false, 0x42, 0x42, Z | C, N),
+        (Cpx, 0x00, 0x01, true, 0x00, 0x01, N, Z | C),
+        (Cpy, 0x42, 0x42, false, 0x42, 0x42, Z | C, N),
+        (Cpy, 0x80, 0x01, false, 0x80, 0x01, C, N | Z),
+        (Bit, 0x01, 0xC0, false, 0x01, 0xC0, Z | N | V, 0),
+        (Bit, 0x01, 0x01, false, 0x01, 0x01, 0, Z | N | V),
+        (Asl, 0x00, 0x81, false, 0x00, 0x02, C, N | Z),
+        (Asl, 0x00, 0x80, false, 0x00, 0x00, C | Z, N),
+        (Lsr, 0x00, 0x81, false, 0x00, 0x40, C, N | Z),
+        (Lsr, 0x00, 0x01, false, 0x00, 0x00, C | Z, N),
+        (Rol, 0x00, 0x81, true, 0x00, 0x03, C, N | Z),
+        (Rol, 0x00, 0x40, false, 0x00, 0x80, N, C | Z),
+        (Ror, 0x00, 0x81, true, 0x00, 0xC0, C | N, Z),
+        (Ror, 0x00, 0x01, false, 0x00, 0x00, C | Z, N),
+        (Inc, 0x00, 0x7F, true, 0x00, 0x80, N | C, Z),
+        (Inc, 0x00, 0xFF, false, 0x00, 0x00, Z, N | C),
+        (Dec, 0x00, 0x01, true, 0x00, 0x00, Z | C, N),
+        (Dec, 0x00, 0x00, false, 0x00, 0xFF, N, Z | C),
+        (Slo, 0x10, 0x81, false, 0x12, 0x02, C, N | Z),
+        (Sre, 0xFF, 0x81, false, 0xBF, 0x40, C | N, Z),
+        (Rla, 0x0F, 0x81, true, 0x03, 0x03, C, N | Z),
+        (Rra, 0x10, 0x02, true, 0x91, 0x81, N, C | Z | V),
+        (Isb, 0x20, 0x0F, true, 0x10, 0x10, C, N | Z | V),
+        (Dcp, 0x42, 0x43, false, 0x42, 0x42, Z | C, N),
+        (Dcp, 0x00, 0x02, false, 0x00, 0x01, N, Z | C),
+        (Aac, 0xF0, 0x80, false, 0x80, 0x80, N | C, Z),
+        (Aac, 0x0F, 0xF0, true, 0x00, 0xF0, Z, N | C),
+        (Asr, 0xFF, 0x03, false, 0x01, 0x03, C, N | Z),
+        (Asr, 0x01, 0x01, false, 0x00, 0x01, Z | C, N),
+        (Arr, 0xFF, 0xFF, true, 0xFF, 0xFF, N | C, V | Z),
+        (Arr, 0xFF, 0x80, false, 0x40, 0x80, C | V, N | Z),
+        (Arr, 0xFF, 0x01, false, 0x00, 0x01, Z, N | C | V),
+        (Arr, 0xFF, 0x20, false, 0x10, 0x20, 0, N | Z | C | V),
+        // (A | $EE) & M
+        (Atx, 0x00, 0xFF, false, 0xEE, 0xFF, N, Z),
+        (Atx, 0x01, 0x10, false, 0x00, 0x10, Z, N),
+    ];
+
+    #[test]
+    fn test_operations() {
+        for &instruction in OPERATIONS {
+            assert!(
+                OPERATION_CASES.iter().any(|case| case.0 == instruction),
+                "no case for {instruction:?}"
+            );
+        }
+
+        for &(instruction, reg, value, carry, reg_after, value_after, set, clear) in OPERATION_CASES
+        {
+            for opcode in opcodes_for(instruction) {
+                for cross_page in [false, true] {
+                    let (h, addr) = run_case(opcode, value, cross_page, |h| {
+                        match instruction {
+                            Cpx => h.cpu.register_x = reg,
+                            Cpy => h.cpu.register_y = reg,
+                            _ => h.cpu.register_a = reg,
+                        }
+                        if carry {
+                            h.cpu.flags |= CARRY_FLAG;
+                        }
+                    });
+
+                    let name = opcode_named(opcode.code);
+                    if let AddressingMode::Accumulator = opcode.mode {
+                        assert_eq!(h.cpu.register_a, value_after, "{name}");
+                    } else {
+                        let reg = match instruction {
+                            Cpx => h.cpu.register_x,
+                            Cpy => h.cpu.register_y,
+                            _ => h.cpu.register_a,
+                        };
+                        assert_eq!(reg, reg_after, "{name}");
+                        assert_eq!(h.read(addr), value_after, "{name} operand");
+                    }
+                    h.assert_flags(set, clear);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unstable_operations() {
+        let run = |code: u8, setup: &dyn Fn(&mut Harness)| {
+            run_case(&OPCODES[code as usize], 0xF0, false, setup)
+        };
+
+        // AXS #imm: X = (A & X) - imm, carry as CMP
+        let (h, _) = run(0xCB, &|h| {
+            h.cpu.register_a = 0xF3;
+            h.cpu.register_x = 0x3F;
+        });
+        assert_eq!(h.cpu.register_x, 0x33u8.wrapping_sub(0xF0));
+        h.assert_flags(0, CARRY_FLAG | ZERO_FLAG | NEGATIVE_FLAG);
+
+        // XAA #imm: A = (A | $EE) & X & imm
+        let (h, _) = run(0x8B, &|h| h.cpu.register_x = 0xB0);
+        assert_eq!(h.cpu.register_a, 0xA0);
+        h.assert_flags(NEGATIVE_FLAG, ZERO_FLAG);
+
+        // The stores AND their value with the base address's high byte plus
+        // one, $12 + 1 here. Crossing into $13xx, that value replaces the
+        // high byte of the target too.
+        for cross_page in [false, true] {
+            let run = |code: u8, setup: &dyn Fn(&mut Harness)| {
+                run_case(&OPCODES[code as usize], 0xF0, cross_page, setup)
+            };
+            let check = |h: &Harness, addr: u16, stored: u8, name: &str| {
+                let target = match cross_page {
+                    true => u16::from_le_bytes([addr as u8, stored]),
+                    false => addr,
+                };
+                assert_eq!(h.read(target), stored, "{name} crossing {cross_page}");
+                if cross_page {
+                    assert_eq!(h.read(addr), 0xF0, "{name} crossing {cross_page}");
+                }
+            };
+
+            for code in [0x9F, 0x93] {
+                let (h, addr) = run(code, &|h| {
+                    h.cpu.register_a = 0xFF;
+                    h.cpu.register_x = 0x31;
+                });
+                check(&h, addr, 0x11, &opcode_named(code));
+            }
+
+            let (h, addr) = run(0x9E, &|h| h.cpu.register_x = 0x31);
+            check(&h, addr, 0x11, "SXA");
+
+            let (h, addr) = run(0x9C, &|h| h.cpu.register_y = 0x31);
+            check(&h, addr, 0x11, "SYA");
+
+            // XAS: SP = A & X, then stores SP & (H + 1)
+            let (h, addr) = run(0x9B, &|h| {
+                h.cpu.register_a = 0xF1;
+                h.cpu.register_x = 0x3F;
+            });
+            assert_eq!(h.cpu.stack_pointer, 0x31);
+            check(&h, addr, 0x11, "XAS");
+        }
+
+        // LAR: A = X = SP = M & SP
+        let (h, _) = run(0xBB, &|h| h.cpu.stack_pointer = 0xBD);
+        assert_eq!(h.cpu.stack_pointer, 0xB0);
+        h.assert_registers(0xB0, 0xB0, INDEX_Y);
+        h.assert_flags(NEGATIVE_FLAG, ZERO_FLAG);
+    }
+
+    #[test]
+    fn test_nops() {
+        for &instruction in NOPS {
+            for opcode in opcodes_for(instruction) {
+                for cross_page in [false, true] {
+                    let (h, _) = run_case(opcode, 0xFF, cross_page, |h| {
+                        h.cpu.register_a = 0x12;
+                    });
+                    assert_eq!(h.cpu.register_a, 0x12, "{}", opcode_named(opcode.code));
+                    assert_eq!(h.cpu.flags, Harness::new().cpu.flags);
+                    assert_eq!(h.cpu.program_counter, PROGRAM + opcode.bytes as u16);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_branches() {
+        for &(instruction, flag, taken_when_set) in BRANCHES {
+            let opcode = opcodes_for(instruction).next().unwrap();
+
+            // Start, offset, whether the flag is set, PC after, cycles
+            for (start, offset, set, pc, cycles) in [
+                (PROGRAM, 0x10, !taken_when_set, PROGRAM + 2, 2),
+                (PROGRAM, 0x10, taken_when_set, PROGRAM + 0x12, 3),
+                (0x06F0, 0x20, taken_when_set, 0x0712, 4),
+                (PROGRAM, 0xF0, taken_when_set, 0x05F2, 4),
+            ] {
+                let mut h = Harness::new();
+                h.cpu.cycle_stepped = true;
+                h.cpu.flags = if set { flag } else { 0 } | INTERRUPT_DISABLE;
+                h.load(start, &[opcode.code, offset]);
+                h.step();
+
+                let name = opcode_named(opcode.code);
+                assert_eq!(h.cpu.program_counter, pc, "{name} from {start:04X}");
+                assert_eq!(h.cycles, cycles, "cycles of {name} to {pc:04X}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_flag_instructions() {
+        for &(instruction, flag, set) in FLAG_OPS {
+            let opcode = opcodes_for(instruction).next().unwrap();
+
+            for start in [0x00, 0xFF] {
+                let mut h = Harness::new();
+                h.cpu.flags = start;
+                h.load(PROGRAM, &[opcode.code]).step();
+
+                assert_eq!(h.cpu.flags, if set { start | flag } else { start & !flag });
+            }
+        }
+    }
+
+    #[test]
+    fn test_transfers() {
+        // Instruction, (A, X, Y, SP) before, (A, X, Y, SP) after, flags set
+        // and clear
+        #[rustfmt::skip]
+        let cases = [
+            (Tax, (0x80, 0x00, 0x00, 0xFD), (0x80, 0x80, 0x00, 0xFD), N, Z),
+            (Tay, (0x00, 0x00, 0x01, 0xFD), (0x00, 0x00, 0x00, 0xFD), Z, N),
+            (Txa, (0x00, 0x42, 0x00, 0xFD), (0x42, 0x42, 0x00, 0xFD), 0, N | Z),
+            (Tya, (0x01, 0x00, 0xFF, 0xFD), (0xFF, 0x00, 0xFF, 0xFD), N, Z),
+            (Tsx, (0x00, 0x00, 0x00, 0x00), (0x00, 0x00, 0x00, 0x00), Z, N),
+            // TXS is the one transfer that leaves the flags alone
+            (Txs, (0x00, 0x00, 0x00, 0xFD), (0x00, 0x00, 0x00, 0x00), 0, N | Z),
+            (Inx, (0x00, 0xFF, 0x00, 0xFD), (0x00, 0x00, 0x00, 0xFD), Z, N),
+            (Iny, (0x00, 0x00, 0x7F, 0xFD), (0x00, 0x00, 0x80, 0xFD), N, Z),
+            (Dex, (0x00, 0x00, 0x00, 0xFD), (0x00, 0xFF, 0x00, 0xFD), N, Z),
+            (Dey, (0x00, 0x00, 0x01, 0xFD), (0x00, 0x00, 0x00, 0xFD), Z, N),
+        ];
+        for &instruction in TRANSFERS {
+            assert!(cases.iter().any(|case| case.0 == instruction));
+        }
+
+        for (instruction, (a, x, y, sp), after, set, clear) in cases {
+            let opcode = opcodes_for(instruction).next().unwrap();
+            let mut h = Harness::new();
+            h.cpu.register_a = a;
+            h.cpu.register_x = x;
+            h.cpu.register_y = y;
+            h.cpu.stack_pointer = sp;
+            h.load(PROGRAM, &[opcode.code]).step();
+
+            let cpu = &h.cpu;
+            assert_eq!(
+                (
+                    cpu.register_a,
+                    cpu.register_x,
+                    cpu.register_y,
+                    cpu.stack_pointer
+                ),
+                after,
+                "{instruction:?}"
+            );
+            h.assert_flags(set, clear);
+        }
+    }
+
+    #[test]
+    fn test_stack() {
+        for &instruction in STACK {
+            assert!(opcodes_for(instruction).next().is_some());
+        }
+
+        // PHA; LDA #$00; PLA
+        let mut h = Harness::new();
+        h.cpu.register_a = 0x80;
+        h.load(PROGRAM, &[0x48, 0xA9, 0x00, 0x68]).run();
+        assert_eq!(h.read(0x01FD), 0x80);
+        assert_eq!(h.cpu.stack_pointer, 0xFD);
+        h.assert_registers(0x80, 0x00, 0x00);
+        h.assert_flags(NEGATIVE_FLAG, ZERO_FLAG);
+
+        // PHP pushes B and bit 5 set; PLP drops B and keeps bit 5
+        let mut h = Harness::new();
+        h.cpu.flags = CARRY_FLAG | NEGATIVE_FLAG;
+        h.load(PROGRAM, &[0x08]).step();
+        assert_eq!(
+            flag_names(h.read(0x01FD)),
+            flag_names(CARRY_FLAG | NEGATIVE_FLAG | BREAK | BREAK_2)
+        );
+
+        h.write(0x01FD, &[0xFF]).load(PROGRAM, &[0x28]).step();
+        assert_eq!(flag_names(h.cpu.flags), flag_names(!BREAK));
+        assert_eq!(h.cpu.stack_pointer, 0xFD);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        for &instruction in CONTROL {
+            assert!(opcodes_for(instruction).next().is_some());
+        }
+
+        // JMP $1234
+        let mut h = Harness::new();
+        h.load(PROGRAM, &[0x4C, 0x34, 0x12]).step();
+        assert_eq!(h.cpu.program_counter, 0x1234);
+
+        // JMP ($10FF) takes the high byte from $1000, not $1100
+        let mut h = Harness::new();
+        h.write(0x10FF, &[0x34])
+            .write(0x1000, &[0x12])
+            .write(0x1100, &[0x56]);
+        h.load(PROGRAM, &[0x6C, 0xFF, 0x10]).step();
+        assert_eq!(h.cpu.program_counter, 0x1234);
+
+        // JSR $0700 pushes the return address minus one; RTS adds it back
+        let mut h = Harness::new();
+        h.write(0x0700, &[0xE8, 0x60]);
+        h.load(PROGRAM, &[0x20, 0x00, 0x07]).step();
+        assert_eq!(h.cpu.program_counter, 0x0700);
+        assert_eq!(h.cpu.get_stack_top_u16(), PROGRAM + 2);
+        h.run();
+        assert_eq!(h.cpu.program_counter, PROGRAM + 4);
+        assert_eq!(h.cpu.stack_pointer, 0xFD);
+        assert_eq!(h.steps, 2);
+        h.assert_registers(0x00, 0x01, 0x00);
+
+        // RTI pulls the flags, then the exact return address
+        let mut h = Harness::new();
+        h.cpu.stack_pointer = 0xFA;
+        h.write(0x01FB, &[CARRY_FLAG | BREAK, 0x34, 0x12]);
+        h.load(PROGRAM, &[0x40]).step();
+        assert_eq!(h.cpu.program_counter, 0x1234);
+        assert_eq!(h.cpu.flags, CARRY_FLAG | BREAK_2);
+
+        // BRK stops the run with PC past it
+        let mut h = Harness::new();
+        h.load(PROGRAM, &[0xE8, 0x00, 0xE8]).run();
+        assert_eq!(h.cpu.program_counter, PROGRAM + 2);
+        assert_eq!(h.steps, 1);
+
+        // Every KIL jams on itself
+        for opcode in opcodes_for(Kil) {
+            let mut h = Harness::new();
+            h.load(PROGRAM, &[opcode.code]).run();
+            assert_eq!(h.cpu.jammed, Some(PROGRAM), "{}", opcode_named(opcode.code));
+            assert_eq!(h.cpu.program_counter, PROGRAM);
+        }
+    }
+
+    #[test]
+    fn test_every_opcode_has_a_family() {
+        let families = [
+            LOADS, STORES, OPERATIONS, UNSTABLE, NOPS, TRANSFERS, STACK, CONTROL,
+        ];
+        let covered = |instruction: Instruction| {
+            families.iter().any(|family| family.contains(&instruction))
+                || BRANCHES.iter().any(|branch| branch.0 == instruction)
+                || FLAG_OPS.iter().any(|flag| flag.0 == instruction)
+        };
+
+        for opcode in OPCODES.iter() {
+            assert!(covered(opcode.instruction), "{}", opcode_named(opcode.code));
+        }
+    }
 }
@@ -1,13 +1,221 @@
-use std::collections::HashMap;
-
 use super::cpu::AddressingMode;
 use lazy_static::lazy_static;
 
+// Operation an opcode performs, dispatched on by `CPU::run_with_callback`.
+// Illegal opcodes share a variant with their official counterpart where
+// there is one (e.g. *SBC and *NOP).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(super) enum Instruction {
+    Aac,
+    Adc,
+    And,
+    Arr,
+    Asl,
+    Asr,
+    Atx,
+    Axa,
+    Axs,
+    Bcc,
+    Bcs,
+    Beq,
+    Bit,
+    Bmi,
+    Bne,
+    Bpl,
+    Bra,
+    Brk,
+    Bvc,
+    Bvs,
+    Clc,
+    Cld,
+    Cli,
+    Clv,
+    Cmp,
+    Cpx,
+    Cpy,
+    Dcp,
+    Dec,
+    Dex,
+    Dey,
+    Dop,
+    Eor,
+    Inc,
+    Inx,
+    Iny,
+    Isb,
+    Jmp,
+    Jsr,
+    Kil,
+    Lar,
+    Lax,
+    Lda,
+    Ldx,
+    Ldy,
+    Lsr,
+    Nop,
+    Ora,
+    Pha,
+    Php,
+    Phx,
+    Phy,
+    Pla,
+    Plp,
+    Plx,
+    Ply,
+    Rla,
+    Rol,
+    Ror,
+    Rra,
+    Rti,
+    Rts,
+    Sax,
+    Sbc,
+    Sec,
+    Sed,
+    Sei,
+    Slo,
+    Sre,
+    Sta,
+    Stx,
+    Sty,
+    Sxa,
+    Sya,
+    Stz,
+    Tax,
+    Tay,
+    Top,
+    Trb,
+    Tsb,
+    Tsx,
+    Txa,
+    Txs,
+    Tya,
+    Xaa,
+    Xas,
+}
+
+// How an instruction uses the memory operand; decides which dummy bus
+// cycles a cycle-stepped CPU issues.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(super) enum Access {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+impl Instruction {
+    pub(super) fn access(self) -> Access {
+        use Instruction::*;
+
+        match self {
+            Sta | Stx | Sty | Stz | Sax | Axa | Sxa | Sya | Xas => Access::Write,
+            Asl | Lsr | Rol | Ror | Inc | Dec | Slo | Sre | Rla | Rra | Isb | Dcp | Trb | Tsb => {
+                Access::ReadModifyWrite
+            }
+            _ => Access::Read,
+        }
+    }
+
+    fn from_mnemonic(mnemonic: &str) -> Self {
+        match mnemonic {
+            "*AAC" => Instruction::Aac,
+            "ADC" => Instruction::Adc,
+            "AND" => Instruction::And,
+            "*ARR" => Instruction::Arr,
+            "ASL" => Instruction::Asl,
+            "*ASR" => Instruction::Asr,
+            "*ATX" => Instruction::Atx,
+            "*AXA" => Instruction::Axa,
+            "*AXS" => Instruction::Axs,
+            "BCC" => Instruction::Bcc,
+            "BCS" => Instruction::Bcs,
+            "BEQ" => Instruction::Beq,
+            "BIT" => Instruction::Bit,
+            "BMI" => Instruction::Bmi,
+            "BNE" => Instruction::Bne,
+            "BPL" => Instruction::Bpl,
+            "BRA" => Instruction::Bra,
+            "BRK" => Instruction::Brk,
+            "BVC" => Instruction::Bvc,
+            "BVS" => Instruction::Bvs,
+            "CLC" => Instruction::Clc,
+            "CLD" => Instruction::Cld,
+            "CLI" => Instruction::Cli,
+            "CLV" => Instruction::Clv,
+            "CMP" => Instruction::Cmp,
+            "CPX" => Instruction::Cpx,
+            "CPY" => Instruction::Cpy,
+            "*DCP" => Instruction::Dcp,
+            "DEC" => Instruction::Dec,
+            "DEX" => Instruction::Dex,
+            "DEY" => Instruction::Dey,
+            "*DOP" => Instruction::Dop,
+            "EOR" => Instruction::Eor,
+            "INC" => Instruction::Inc,
+            "INX" => Instruction::Inx,
+            "INY" => Instruction::Iny,
+            "*ISB" => Instruction::Isb,
+            "JMP" => Instruction::Jmp,
+            "JSR" => Instruction::Jsr,
+            "*KIL" => Instruction::Kil,
+            "*LAR" => Instruction::Lar,
+            "*LAX" => Instruction::Lax,
+            "LDA" => Instruction::Lda,
+            "LDX" => Instruction::Ldx,
+            "LDY" => Instruction::Ldy,
+            "LSR" => Instruction::Lsr,
+            "*NOP" | "NOP" => Instruction::Nop,
+            "ORA" => Instruction::Ora,
+            "PHA" => Instruction::Pha,
+            "PHP" => Instruction::Php,
+            "PHX" => Instruction::Phx,
+            "PHY" => Instruction::Phy,
+            "PLA" => Instruction::Pla,
+            "PLP" => Instruction::Plp,
+            "PLX" => Instruction::Plx,
+            "PLY" => Instruction::Ply,
+            "*RLA" => Instruction::Rla,
+            "ROL" => Instruction::Rol,
+            "ROR" => Instruction::Ror,
+            "*RRA" => Instruction::Rra,
+            "RTI" => Instruction::Rti,
+            "RTS" => Instruction::Rts,
+            "*SAX" => Instruction::Sax,
+            "*SBC" | "SBC" => Instruction::Sbc,
+            "SEC" => Instruction::Sec,
+            "SED" => Instruction::Sed,
+            "SEI" => Instruction::Sei,
+            "*SLO" => Instruction::Slo,
+            "*SRE" => Instruction::Sre,
+            "STA" => Instruction::Sta,
+            "STX" => Instruction::Stx,
+            "STY" => Instruction::Sty,
+            "*SXA" => Instruction::Sxa,
+            "*SYA" => Instruction::Sya,
+            "STZ" => Instruction::Stz,
+            "TAX" => Instruction::Tax,
+            "TAY" => Instruction::Tay,
+            "*TOP" => Instruction::Top,
+            "TRB" => Instruction::Trb,
+            "TSB" => Instruction::Tsb,
+            "TSX" => Instruction::Tsx,
+            "TXA" => Instruction::Txa,
+            "TXS" => Instruction::Txs,
+            "TYA" => Instruction::Tya,
+            "*XAA" => Instruction::Xaa,
+            "*XAS" => Instruction::Xas,
+            _ => unreachable!("unknown mnemonic {mnemonic}"),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub(super) struct Opcode {
     pub code: u8,
     pub mnemonic: &'static str,
+    pub instruction: Instruction,
     pub bytes: u8,
-    pub _cycles: u8,
+    pub cycles: u8,
     pub mode: AddressingMode,
 }
 
@@ -16,14 +224,15 @@ impl Opcode {
         _code: u8,
         mnemonic: &'static str,
         bytes: u8,
-        _cycles: u8,
+        cycles: u8,
         mode: AddressingMode,
     ) -> Self {
         Self {
             code: _code,
             mnemonic,
+            instruction: Instruction::from_mnemonic(mnemonic),
             bytes,
-            _cycles,
+            cycles,
             mode,
         }
     }
@@ -40,8 +249,10 @@ fn op(
 }
 
 lazy_static! {
-    pub(super) static ref OPCODES: HashMap<u8, Opcode> = {
-        HashMap::from([
+    // Indexed by opcode byte
+    pub(super) static ref OPCODES: [Opcode; 256] = {
+        let mut table: [Option<Opcode>; 256] = std::array::from_fn(|_| None);
+        for (code, opcode) in [
             op(0x00, "BRK", 1, 7, AddressingMode::NoneAddressing),
 
             op(0x0B, "*AAC", 2, 2, AddressingMode::Immediate),
@@ -227,13 +438,13 @@ lazy_static! {
             op(0x5E, "LSR", 3, 7, AddressingMode::Absolute_X),
             op(0xEA, "NOP", 1, 2, AddressingMode::Implied),
             op(0x09, "ORA", 2, 2, AddressingMode::Immediate),
-            op(0x05, "ORA", 2, 2, AddressingMode::ZeroPage),
-            op(0x15, "ORA", 2, 2, AddressingMode::ZeroPage_X),
-            op(0x0D, "ORA", 3, 2, AddressingMode::Absolute),
-            op(0x1D, "ORA", 3, 2, AddressingMode::Absolute_X),
-            op(0x19, "ORA", 3, 2, AddressingMode::Absolute_Y),
-            op(0x01, "ORA", 2, 2, AddressingMode::Indirect_X),
-            op(0x11, "ORA", 2, 2, AddressingMode::Indirect_Y),
+            op(0x05, "ORA", 2, 3, AddressingMode::ZeroPage),
+            op(0x15, "ORA", 2, 4, AddressingMode::ZeroPage_X),
+            op(0x0D, "ORA", 3, 4, AddressingMode::Absolute),
+            op(0x1D, "ORA", 3, 4, AddressingMode::Absolute_X),
+            op(0x19, "ORA", 3, 4, AddressingMode::Absolute_Y),
+            op(0x01, "ORA", 2, 6, AddressingMode::Indirect_X),
+            op(0x11, "ORA", 2, 5, AddressingMode::Indirect_Y),
             op(0x48, "PHA", 1, 3, AddressingMode::Implied),
             op(0x08, "PHP", 1, 3, AddressingMode::Implied),
             op(0x68, "PLA", 1, 4, AddressingMode::Implied),
@@ -333,9 +544,93 @@ lazy_static! {
             op(0x9A, "TXS", 1, 2, AddressingMode::Implied),
             op(0x98, "TYA", 1, 2, AddressingMode::Implied),
 
-            op(0x8B, "*XAA", 2, 2, AddressingMode::Immediate), //TODO
+            op(0x8B, "*XAA", 2, 2, AddressingMode::Immediate),
 
             op(0x9B, "*XAS", 3, 5, AddressingMode::Absolute_Y),
-        ])
+        ] {
+            let previous = table[code as usize].replace(opcode);
+            assert!(previous.is_none(), "opcode {code:02X} is defined twice");
+        }
+
+        table.map(|opcode| opcode.expect("all 256 opcodes are defined"))
+    };
+}
+
+lazy_static! {
+    // The 65C02's table: the documented NMOS opcodes with its fixes and
+    // additions on top, and every undefined opcode a NOP.
+    pub(super) static ref CMOS_OPCODES: [Opcode; 256] = {
+        let mut table: [Option<Opcode>; 256] = std::array::from_fn(|code| {
+            let opcode = &OPCODES[code];
+            (!opcode.mnemonic.starts_with('*')).then(|| opcode.clone())
+        });
+
+        for (code, opcode) in [
+            op(0x1E, "ASL", 3, 6, AddressingMode::Absolute_X),
+            op(0x5E, "LSR", 3, 6, AddressingMode::Absolute_X),
+            op(0x3E, "ROL", 3, 6, AddressingMode::Absolute_X),
+            op(0x7E, "ROR", 3, 6, AddressingMode::Absolute_X),
+            op(0x6C, "JMP", 3, 6, AddressingMode::Indirect),
+
+            op(0x12, "ORA", 2, 5, AddressingMode::ZeroPage_Indirect),
+            op(0x32, "AND", 2, 5, AddressingMode::ZeroPage_Indirect),
+            op(0x52, "EOR", 2, 5, AddressingMode::ZeroPage_Indirect),
+            op(0x72, "ADC", 2, 5, AddressingMode::ZeroPage_Indirect),
+            op(0x92, "STA", 2, 5, AddressingMode::ZeroPage_Indirect),
+            op(0xB2, "LDA", 2, 5, AddressingMode::ZeroPage_Indirect),
+            op(0xD2, "CMP", 2, 5, AddressingMode::ZeroPage_Indirect),
+            op(0xF2, "SBC", 2, 5, AddressingMode::ZeroPage_Indirect),
+
+            op(0x89, "BIT", 2, 2, AddressingMode::Immediate),
+            op(0x34, "BIT", 2, 4, AddressingMode::ZeroPage_X),
+            op(0x3C, "BIT", 3, 4, AddressingMode::Absolute_X),
+
+            op(0x1A, "INC", 1, 2, AddressingMode::Accumulator),
+            op(0x3A, "DEC", 1, 2, AddressingMode::Accumulator),
+
+            op(0x7C, "JMP", 3, 6, AddressingMode::Absolute_Indirect_X),
+
+            op(0x80, "BRA", 2, 3, AddressingMode::Relative),
+
+            op(0xDA, "PHX", 1, 3, AddressingMode::Implied),
+            op(0x5A, "PHY", 1, 3, AddressingMode::Implied),
+            op(0xFA, "PLX", 1, 4, AddressingMode::Implied),
+            op(0x7A, "PLY", 1, 4, AddressingMode::Implied),
+
+            op(0x64, "STZ", 2, 3, AddressingMode::ZeroPage),
+            op(0x74, "STZ", 2, 4, AddressingMode::ZeroPage_X),
+            op(0x9C, "STZ", 3, 4, AddressingMode::Absolute),
+            op(0x9E, "STZ", 3, 5, AddressingMode::Absolute_X),
+
+            op(0x14, "TRB", 2, 5, AddressingMode::ZeroPage),
+            op(0x1C, "TRB", 3, 6, AddressingMode::Absolute),
+            op(0x04, "TSB", 2, 5, AddressingMode::ZeroPage),
+            op(0x0C, "TSB", 3, 6, AddressingMode::Absolute),
+
+            // Undefined opcodes that still fetch operands
+            op(0x02, "NOP", 2, 2, AddressingMode::Immediate),
+            op(0x22, "NOP", 2, 2, AddressingMode::Immediate),
+            op(0x42, "NOP", 2, 2, AddressingMode::Immediate),
+            op(0x62, "NOP", 2, 2, AddressingMode::Immediate),
+            op(0x82, "NOP", 2, 2, AddressingMode::Immediate),
+            op(0xC2, "NOP", 2, 2, AddressingMode::Immediate),
+            op(0xE2, "NOP", 2, 2, AddressingMode::Immediate),
+            op(0x44, "NOP", 2, 3, AddressingMode::ZeroPage),
+            op(0x54, "NOP", 2, 4, AddressingMode::ZeroPage_X),
+            op(0xD4, "NOP", 2, 4, AddressingMode::ZeroPage_X),
+            op(0xF4, "NOP", 2, 4, AddressingMode::ZeroPage_X),
+            op(0x5C, "NOP", 3, 8, AddressingMode::Absolute),
+            op(0xDC, "NOP", 3, 4, AddressingMode::Absolute),
+            op(0xFC, "NOP", 3, 4, AddressingMode::Absolute),
+        ] {
+            table[code as usize] = Some(opcode);
+        }
+
+        // The rest are single-cycle NOPs
+        std::array::from_fn(|code| {
+            table[code]
+                .take()
+                .unwrap_or_else(|| Opcode::new(code as u8, "NOP", 1, 1, AddressingMode::Implied))
+        })
     };
 }
@@ -0,0 +1,287 @@
+use crate::cpu::{
+    constants::{
+        CARRY_FLAG, DECIMAL_MODE, INTERRUPT_DISABLE, NEGATIVE_FLAG, OVERFLOW_FLAG, ZERO_FLAG,
+    },
+    cpu::Mem,
+    CPU,
+};
+
+// An expression over the CPU registers and memory, for breakpoint
+// conditions and the debugger's print command:
+//
+//   A X Y P SP PC       registers
+//   C Z I D V N         single flags, 0 or 1
+//   [addr]              the byte at addr, read without side effects
+//   $C000 0xC000 49152  numbers, hex or decimal
+//   * / % + - & | ^ << >>
+//   == != < <= > >=
+//   ! && ||
+//
+// with the usual C precedence, except that & | ^ bind tighter than the
+// comparisons so that `P & $80 != 0` means what it says.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Register(Register),
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    P,
+    SP,
+    PC,
+    // One bit of P
+    Flag(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    And,
+    Xor,
+    Or,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LogicalAnd,
+    LogicalOr,
+}
+
+// Operators from loosest to tightest, each level left-associative. Longer
+// symbols come first so that `<=` is not read as `<`.
+const LEVELS: &[&[(&str, BinaryOp)]] = &[
+    &[("||", BinaryOp::LogicalOr)],
+    &[("&&", BinaryOp::LogicalAnd)],
+    &[
+        ("==", BinaryOp::Eq),
+        ("!=", BinaryOp::Ne),
+        ("<=", BinaryOp::Le),
+        (">=", BinaryOp::Ge),
+        ("<", BinaryOp::Lt),
+        (">", BinaryOp::Gt),
+    ],
+    &[("|", BinaryOp::Or)],
+    &[("^", BinaryOp::Xor)],
+    &[("&", BinaryOp::And)],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+    ],
+];
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let mut parser = Parser { text, pos: 0 };
+        let expr = parser.level(0)?;
+
+        parser.skip_space();
+        match parser.rest().chars().next() {
+            None => Ok(expr),
+            Some(c) => Err(format!("unexpected {c} at column {}", parser.pos + 1)),
+        }
+    }
+
+    pub fn eval<B: Mem>(&self, cpu: &mut CPU<B>) -> i64 {
+        match self {
+            &Expr::Number(value) => value,
+            Expr::Register(register) => register.get(cpu),
+            Expr::Memory(addr) => {
+                let addr = addr.eval(cpu) as u16;
+                cpu.bus.peek(addr) as i64
+            }
+            Expr::Not(expr) => (expr.eval(cpu) == 0) as i64,
+            Expr::Negate(expr) => expr.eval(cpu).wrapping_neg(),
+            Expr::Binary(lhs, BinaryOp::LogicalAnd, rhs) => {
+                (lhs.eval(cpu) != 0 && rhs.eval(cpu) != 0) as i64
+            }
+            Expr::Binary(lhs, BinaryOp::LogicalOr, rhs) => {
+                (lhs.eval(cpu) != 0 || rhs.eval(cpu) != 0) as i64
+            }
+            Expr::Binary(lhs, op, rhs) => op.apply(lhs.eval(cpu), rhs.eval(cpu)),
+        }
+    }
+}
+
+impl Register {
+    fn from_name(name: &str) -> Option<Register> {
+        let register = match name.to_ascii_uppercase().as_str() {
+            "A" => Register::A,
+            "X" => Register::X,
+            "Y" => Register::Y,
+            "P" => Register::P,
+            "SP" | "S" => Register::SP,
+            "PC" => Register::PC,
+            "C" => Register::Flag(CARRY_FLAG),
+            "Z" => Register::Flag(ZERO_FLAG),
+            "I" => Register::Flag(INTERRUPT_DISABLE),
+            "D" => Register::Flag(DECIMAL_MODE),
+            "V" => Register::Flag(OVERFLOW_FLAG),
+            "N" => Register::Flag(NEGATIVE_FLAG),
+            _ => return None,
+        };
+
+        Some(register)
+    }
+
+    fn get<B: Mem>(self, cpu: &CPU<B>) -> i64 {
+        match self {
+            Register::A => cpu.register_a as i64,
+            Register::X => cpu.register_x as i64,
+            Register::Y => cpu.register_y as i64,
+            Register::P => cpu.flags as i64,
+            Register::SP => cpu.stack_pointer as i64,
+            Register::PC => cpu.program_counter as i64,
+            Register::Flag(flag) => (cpu.flags & flag != 0) as i64,
+        }
+    }
+}
+
+impl BinaryOp {
+    fn apply(self, lhs: i64, rhs: i64) -> i64 {
+        match self {
+            BinaryOp::Mul => lhs.wrapping_mul(rhs),
+            // Dividing by zero gives 0 rather than stopping the emulator
+            BinaryOp::Div => lhs.checked_div(rhs).unwrap_or(0),
+            BinaryOp::Rem => lhs.checked_rem(rhs).unwrap_or(0),
+            BinaryOp::Add => lhs.wrapping_add(rhs),
+            BinaryOp::Sub => lhs.wrapping_sub(rhs),
+            BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+            BinaryOp::Shr => lhs.wrapping_shr(rhs as u32),
+            BinaryOp::And => lhs & rhs,
+            BinaryOp::Xor => lhs ^ rhs,
+            BinaryOp::Or => lhs | rhs,
+            BinaryOp::Eq => (lhs == rhs) as i64,
+            BinaryOp::Ne => (lhs != rhs) as i64,
+            BinaryOp::Lt => (lhs < rhs) as i64,
+            BinaryOp::Le => (lhs <= rhs) as i64,
+            BinaryOp::Gt => (lhs > rhs) as i64,
+            BinaryOp::Ge => (lhs >= rhs) as i64,
+            BinaryOp::LogicalAnd => (lhs != 0 && rhs != 0) as i64,
+            BinaryOp::LogicalOr => (lhs != 0 || rhs != 0) as i64,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        self.skip_space();
+        if self.rest().starts_with(symbol) {
+            self.pos += symbol.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn level(&mut self, level: usize) -> Result<Expr, String> {
+        let Some(ops) = LEVELS.get(level) else {
+            return self.unary();
+        };
+
+        let mut lhs = self.level(level + 1)?;
+        'outer: loop {
+            for &(symbol, op) in ops.iter() {
+                // `&` and `|` must not take the first half of `&&` and `||`
+                let doubled =
+                    symbol.len() == 1 && self.rest().trim_start().starts_with(&symbol.repeat(2));
+                if !doubled && self.eat(symbol) {
+                    let rhs = self.level(level + 1)?;
+                    lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.level(0)?;
+            return match self.eat(")") {
+                true => Ok(expr),
+                false => Err(format!("missing ) at column {}", self.pos + 1)),
+            };
+        }
+        if self.eat("[") {
+            let addr = self.level(0)?;
+            return match self.eat("]") {
+                true => Ok(Expr::Memory(Box::new(addr))),
+                false => Err(format!("missing ] at column {}", self.pos + 1)),
+            };
+        }
+
+        self.skip_space();
+        let start = self.pos;
+        let word: String = self
+            .rest()
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '$' || *c == '_')
+            .collect();
+        if word.is_empty() {
+            return Err(match self.rest().chars().next() {
+                Some(c) => format!("unexpected {c} at column {}", start + 1),
+                None => "unexpected end of expression".to_string(),
+            });
+        }
+        self.pos += word.len();
+
+        if let Some(register) = Register::from_name(&word) {
+            return Ok(Expr::Register(register));
+        }
+        parse_number(&word)
+            .map(Expr::Number)
+            .ok_or_else(|| format!("unknown name {word} at column {}", start + 1))
+    }
+}
+
+// $FF and 0xFF are hex, anything else decimal
+pub fn parse_number(text: &str) -> Option<i64> {
+    let hex = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .or_else(|| text.strip_prefix("0X"));
+
+    match hex {
+        Some(digits) => i64::from_str_radix(digits, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
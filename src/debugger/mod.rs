@@ -0,0 +1,488 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    bus::{bus::Bus, watch::WatchHit},
+    cpu::{cpu::Mem, CPU},
+};
+
+mod expr;
+
+pub use expr::{parse_number, BinaryOp, Expr, Register};
+
+const JSR: u8 = 0x20;
+const RTI: u8 = 0x40;
+const RTS: u8 = 0x60;
+
+// Why a debugger command gave control back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    // The step finished, or the requested address, frame or scanline came up
+    Done,
+    // About to run the instruction at a breakpoint with this id
+    Breakpoint(usize),
+    // Stopped at a BRK or jammed on a KIL, at this address
+    Halted(u16),
+    // After the instruction that hit a breaking watchpoint
+    Watchpoint(WatchHit),
+}
+
+// Stops before the instruction at `addr` runs, if `condition` is missing or
+// evaluates to non-zero
+pub struct Breakpoint {
+    pub addr: u16,
+    pub condition: Option<Expr>,
+    // The condition as typed, for listing
+    pub source: String,
+    pub enabled: bool,
+    pub hits: usize,
+}
+
+// A JSR that has not returned yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Call {
+    // Address of the JSR and of the subroutine it called
+    pub from: u16,
+    pub to: u16,
+    // Stack pointer before the JSR pushed its return address
+    pub stack_pointer: u8,
+}
+
+// Drives a CPU one instruction at a time, stopping at breakpoints. Every
+// command runs at least one instruction, so continuing from a breakpoint
+// does not hit it again straight away.
+pub struct Debugger<B: Mem = Bus> {
+    pub cpu: CPU<B>,
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    next_id: usize,
+    calls: Vec<Call>,
+}
+
+impl<B: Mem> Debugger<B> {
+    pub fn new(cpu: CPU<B>) -> Self {
+        Debugger {
+            cpu,
+            breakpoints: BTreeMap::new(),
+            next_id: 1,
+            calls: vec![],
+        }
+    }
+
+    // Adds a breakpoint, conditional when `condition` is given, and returns
+    // its id
+    pub fn add_breakpoint(&mut self, addr: u16, condition: Option<&str>) -> Result<usize, String> {
+        let breakpoint = Breakpoint {
+            addr,
+            condition: condition.map(Expr::parse).transpose()?,
+            source: condition.unwrap_or_default().to_string(),
+            enabled: true,
+            hits: 0,
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.insert(id, breakpoint);
+        Ok(id)
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        self.breakpoints.remove(&id).is_some()
+    }
+
+    pub fn breakpoint_mut(&mut self, id: usize) -> Option<&mut Breakpoint> {
+        self.breakpoints.get_mut(&id)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (usize, &Breakpoint)> {
+        self.breakpoints
+            .iter()
+            .map(|(&id, breakpoint)| (id, breakpoint))
+    }
+
+    // Subroutines entered through JSR since the debugger started, innermost
+    // last
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.calls.clear();
+    }
+
+    // Runs one instruction, or the interrupt handler's first one if an
+    // interrupt is taken
+    pub fn step_into(&mut self) -> Stop {
+        self.run_until(|_, _| true)
+    }
+
+    // Like `step_into`, but runs a JSR's whole subroutine as one step
+    pub fn step_over(&mut self) -> Stop {
+        let pc = self.cpu.program_counter;
+        if self.cpu.bus.peek(pc) != JSR {
+            return self.step_into();
+        }
+
+        let (ret, sp) = (pc.wrapping_add(3), self.cpu.stack_pointer);
+        // A recursive call passes the same address deeper in the stack
+        self.run_until(|cpu, _| cpu.program_counter == ret && cpu.stack_pointer >= sp)
+    }
+
+    // Runs until the current subroutine or interrupt handler returns
+    pub fn step_out(&mut self) -> Stop {
+        let sp = self.cpu.stack_pointer;
+        self.run_until(|cpu, opcode| matches!(opcode, RTS | RTI) && cpu.stack_pointer > sp)
+    }
+
+    // Runs until the instruction at `addr` is next
+    pub fn run_to(&mut self, addr: u16) -> Stop {
+        self.run_until(|cpu, _| cpu.program_counter == addr)
+    }
+
+    // Runs until a breakpoint or a halt
+    pub fn resume(&mut self) -> Stop {
+        self.run_until(|_, _| false)
+    }
+
+    // Runs instructions until `done` says so after one of them, given the
+    // CPU and the opcode it ran, or a breakpoint comes up before one, or
+    // a watchpoint asks to stop after one
+    pub fn run_until<F>(&mut self, mut done: F) -> Stop
+    where
+        F: FnMut(&CPU<B>, u8) -> bool,
+    {
+        // Accesses made between commands, like a reset's, do not count
+        self.cpu.bus.take_break();
+
+        let mut first = true;
+        loop {
+            if !first {
+                if let Some(id) = self.breakpoint_hit() {
+                    return Stop::Breakpoint(id);
+                }
+            }
+            first = false;
+
+            let (pc, sp) = (self.cpu.program_counter, self.cpu.stack_pointer);
+            let opcode = self.cpu.bus.peek(pc);
+            if !self.cpu.step() {
+                return Stop::Halted(
+                    self.cpu
+                        .jammed
+                        .unwrap_or(self.cpu.program_counter.wrapping_sub(1)),
+                );
+            }
+            self.track_calls(pc, sp, opcode);
+
+            if let Some(hit) = self.cpu.bus.take_break() {
+                return Stop::Watchpoint(hit);
+            }
+            if done(&self.cpu, opcode) {
+                return Stop::Done;
+            }
+        }
+    }
+
+    fn breakpoint_hit(&mut self) -> Option<usize> {
+        let pc = self.cpu.program_counter;
+
+        for (&id, breakpoint) in self.breakpoints.iter_mut() {
+            if !breakpoint.enabled || breakpoint.addr != pc {
+                continue;
+            }
+            if let Some(condition) = &breakpoint.condition {
+                if condition.eval(&mut self.cpu) == 0 {
+                    continue;
+                }
+            }
+
+            breakpoint.hits += 1;
+            return Some(id);
+        }
+
+        None
+    }
+
+    fn track_calls(&mut self, pc: u16, sp: u8, opcode: u8) {
+        let cpu = &self.cpu;
+
+        match opcode {
+            // An interrupt taken instead pushes three bytes, not two
+            JSR if cpu.stack_pointer == sp.wrapping_sub(2) => self.calls.push(Call {
+                from: pc,
+                to: cpu.program_counter,
+                stack_pointer: sp,
+            }),
+            // Drop everything the return unwound, which also copes with
+            // code that pops its return address instead of using RTS
+            RTS | RTI => {
+                let sp = cpu.stack_pointer;
+                while self
+                    .calls
+                    .last()
+                    .is_some_and(|call| call.stack_pointer <= sp)
+                {
+                    self.calls.pop();
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Debugger {
+    // Runs until `frames` more frames have been drawn
+    pub fn run_frames(&mut self, frames: usize) -> Stop {
+        let target = self.cpu.bus.ppu().frame_count() + frames;
+        self.run_until(|cpu, _| cpu.bus.ppu().frame_count() >= target)
+    }
+
+    // Runs until the PPU starts `scanline`, 0-261 with 261 the pre-render
+    // line
+    pub fn run_to_scanline(&mut self, scanline: u16) -> Stop {
+        let mut last = self.cpu.bus.ppu().scanline();
+        self.run_until(|cpu, _| {
+            let now = cpu.bus.ppu().scanline();
+            let reached = now == scanline && last != scanline;
+            last = now;
+            reached
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bus::{
+            ram::RamBus,
+            watch::{Space, Watchpoint},
+        },
+        cartridge::test_rom,
+    };
+
+    // 0600  JSR $0609
+    // 0603  JSR $0609
+    // 0606  INX
+    // 0607  BRK
+    // 0609  INY        ; outer
+    // 060A  JSR $060E
+    // 060D  RTS
+    // 060E  LDA #$05   ; inner
+    // 0610  RTS
+    fn debugger() -> Debugger<RamBus> {
+        let program = vec![
+            0x20, 0x09, 0x06, 0x20, 0x09, 0x06, 0xE8, 0x00, 0xEA, 0xC8, 0x20, 0x0E, 0x06, 0x60,
+            0xA9, 0x05, 0x60,
+        ];
+        let mut cpu = CPU::new(RamBus::new());
+        cpu.load(program);
+        cpu.reset();
+
+        Debugger::new(cpu)
+    }
+
+    // An NROM image running `program` from $8000
+    fn nrom_debugger(program: &[u8]) -> Debugger {
+        let mut cpu = CPU::new(Bus::new(test_rom(program, &[])).unwrap());
+        cpu.reset();
+        Debugger::new(cpu)
+    }
+
+    fn eval(text: &str, debugger: &mut Debugger<RamBus>) -> i64 {
+        Expr::parse(text).unwrap().eval(&mut debugger.cpu)
+    }
+
+    #[test]
+    fn test_step_into() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.step_into(), Stop::Done);
+        assert_eq!(debugger.cpu.program_counter, 0x0609);
+        assert_eq!(
+            debugger.calls(),
+            &[Call {
+                from: 0x0600,
+                to: 0x0609,
+                stack_pointer: 0xFD
+            }]
+        );
+
+        debugger.step_into();
+        assert_eq!(debugger.cpu.register_y, 1);
+        assert_eq!(debugger.cpu.program_counter, 0x060A);
+    }
+
+    #[test]
+    fn test_step_over() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.step_over(), Stop::Done);
+        assert_eq!(debugger.cpu.program_counter, 0x0603);
+        assert_eq!(debugger.cpu.register_y, 1);
+        assert_eq!(debugger.cpu.register_a, 5);
+        assert!(debugger.calls().is_empty());
+
+        debugger.step_over();
+        debugger.step_over();
+        assert_eq!(debugger.cpu.register_x, 1);
+        assert_eq!(debugger.step_over(), Stop::Halted(0x0607));
+    }
+
+    #[test]
+    fn test_step_out() {
+        let mut debugger = debugger();
+        for _ in 0..3 {
+            debugger.step_into();
+        }
+        assert_eq!(debugger.cpu.program_counter, 0x060E);
+        assert_eq!(debugger.calls().len(), 2);
+
+        assert_eq!(debugger.step_out(), Stop::Done);
+        assert_eq!(debugger.cpu.program_counter, 0x060D);
+        assert_eq!(debugger.cpu.register_a, 5);
+        assert_eq!(debugger.calls().len(), 1);
+
+        debugger.step_out();
+        assert_eq!(debugger.cpu.program_counter, 0x0603);
+        assert!(debugger.calls().is_empty());
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = debugger();
+        let inner = debugger.add_breakpoint(0x060E, None).unwrap();
+
+        assert_eq!(debugger.resume(), Stop::Breakpoint(inner));
+        assert_eq!(debugger.cpu.program_counter, 0x060E);
+        assert_eq!(debugger.resume(), Stop::Breakpoint(inner));
+        assert_eq!(debugger.calls()[0].from, 0x0603);
+        assert_eq!(debugger.breakpoint_mut(inner).unwrap().hits, 2);
+        assert_eq!(debugger.resume(), Stop::Halted(0x0607));
+
+        debugger.reset();
+        debugger.breakpoint_mut(inner).unwrap().enabled = false;
+        assert_eq!(debugger.resume(), Stop::Halted(0x0607));
+
+        assert!(debugger.remove_breakpoint(inner));
+        assert!(!debugger.remove_breakpoint(inner));
+        assert_eq!(debugger.breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        let mut debugger = debugger();
+        let id = debugger.add_breakpoint(0x0609, Some("Y == 1")).unwrap();
+
+        assert_eq!(debugger.resume(), Stop::Breakpoint(id));
+        assert_eq!(debugger.calls()[0].from, 0x0603);
+        assert_eq!(debugger.breakpoints().next().unwrap().1.source, "Y == 1");
+
+        let err = debugger.add_breakpoint(0x0609, Some("Y ==")).unwrap_err();
+        assert_eq!(err, "unexpected end of expression");
+    }
+
+    #[test]
+    fn test_run_to() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.run_to(0x0606), Stop::Done);
+        assert_eq!(debugger.cpu.register_y, 2);
+
+        // Breakpoints on the way still stop it
+        let id = debugger.add_breakpoint(0x0609, None).unwrap();
+        debugger.reset();
+        assert_eq!(debugger.run_to(0x0606), Stop::Breakpoint(id));
+    }
+
+    #[test]
+    fn test_run_frames_and_scanlines() {
+        // JMP $8000
+        let mut debugger = nrom_debugger(&[0x4C, 0x00, 0x80]);
+
+        assert_eq!(debugger.run_frames(2), Stop::Done);
+        assert_eq!(debugger.cpu.bus.ppu().frame_count(), 2);
+
+        assert_eq!(debugger.run_to_scanline(100), Stop::Done);
+        assert_eq!(debugger.cpu.bus.ppu().scanline(), 100);
+        assert_eq!(debugger.run_to_scanline(100), Stop::Done);
+        assert_eq!(debugger.cpu.bus.ppu().frame_count(), 3);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        // 8000  LDA #$42
+        // 8002  STA $0810
+        // 8005  LDA $0010
+        // 8008  LDA #$3F
+        // 800A  STA $2006
+        // 800D  LDA #$01
+        // 800F  STA $2006
+        // 8012  STA $2007
+        // 8015  BRK
+        let mut debugger = nrom_debugger(&[
+            0xA9, 0x42, 0x8D, 0x10, 0x08, 0xAD, 0x10, 0x00, 0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9,
+            0x01, 0x8D, 0x06, 0x20, 0x8D, 0x07, 0x20, 0x00,
+        ]);
+        let mut ram = Watchpoint::new(Space::Cpu, 0x0010..=0x0010, true, true);
+        ram.break_on_hit = true;
+        let ram = debugger.cpu.bus.watchpoints().add(ram);
+        let palette = Watchpoint::new(Space::Ppu, 0x3F00..=0x3F1F, false, true);
+        let palette = debugger.cpu.bus.watchpoints().add(palette);
+
+        let Stop::Watchpoint(hit) = debugger.resume() else {
+            panic!("no watchpoint hit");
+        };
+        assert_eq!((hit.id, hit.addr, hit.write), (ram, 0x0810, true));
+        assert_eq!((hit.value, hit.pc), (0x42, 0x8002));
+        // Reset and LDA, as the CPU is not cycle-stepped
+        assert_eq!(hit.cycle, 7 + 2);
+        assert_eq!(debugger.cpu.program_counter, 0x8005);
+
+        let Stop::Watchpoint(hit) = debugger.resume() else {
+            panic!("no watchpoint hit");
+        };
+        assert_eq!((hit.addr, hit.write, hit.pc), (0x0010, false, 0x8005));
+
+        // The palette watchpoint only logs
+        assert_eq!(debugger.resume(), Stop::Halted(0x8015));
+        let hits = debugger.cpu.bus.watchpoints().take_hits();
+        assert_eq!(hits.len(), 3);
+        let hit = hits[2];
+        assert_eq!((hit.id, hit.space, hit.addr), (palette, Space::Ppu, 0x3F01));
+        assert_eq!((hit.value, hit.pc), (0x01, 0x8012));
+    }
+
+    #[test]
+    fn test_expressions() {
+        let mut debugger = debugger();
+        debugger.cpu.register_a = 0x80;
+        debugger.cpu.register_x = 3;
+        debugger.cpu.flags = 0b1000_0001;
+        debugger.cpu.bus.memory[0x10] = 0x42;
+        debugger.cpu.bus.memory[0x13] = 7;
+
+        assert_eq!(eval("A", &mut debugger), 0x80);
+        assert_eq!(eval("pc", &mut debugger), 0x0600);
+        assert_eq!(eval("SP == $FD", &mut debugger), 1);
+        assert_eq!(eval("[$10]", &mut debugger), 0x42);
+        assert_eq!(eval("[$10 + x]", &mut debugger), 7);
+        assert_eq!(eval("1 + 2 * 3", &mut debugger), 7);
+        assert_eq!(eval("(1 + 2) * 3", &mut debugger), 9);
+        assert_eq!(eval("P & $80 != 0", &mut debugger), 1);
+        assert_eq!(eval("C && N && !Z", &mut debugger), 1);
+        assert_eq!(eval("X < 3 || [0x10] == 66", &mut debugger), 1);
+        assert_eq!(eval("1 << 4 >> 2", &mut debugger), 4);
+        assert_eq!(eval("A <= 127", &mut debugger), 0);
+        assert_eq!(eval("-1 + X", &mut debugger), 2);
+        assert_eq!(eval("5 / 0", &mut debugger), 0);
+
+        for (text, message) in [
+            ("", "unexpected end of expression"),
+            ("A +", "unexpected end of expression"),
+            ("(A", "missing ) at column 3"),
+            ("[A", "missing ] at column 3"),
+            ("Q == 1", "unknown name Q at column 1"),
+            ("A = 1", "unexpected = at column 3"),
+            ("$XY", "unknown name $XY at column 1"),
+        ] {
+            assert_eq!(Expr::parse(text).unwrap_err(), message, "{text}");
+        }
+    }
+}
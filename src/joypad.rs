@@ -0,0 +1,50 @@
+use bitflags::bitflags;
+
+bitflags! {
+    // Buttons in the order the controller shifts them out
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct JoypadButton: u8 {
+        const A      = 0b00000001;
+        const B      = 0b00000010;
+        const SELECT = 0b00000100;
+        const START  = 0b00001000;
+        const UP     = 0b00010000;
+        const DOWN   = 0b00100000;
+        const LEFT   = 0b01000000;
+        const RIGHT  = 0b10000000;
+    }
+}
+
+// A standard controller: writing 1 then 0 to $4016 latches the buttons, and
+// each read returns the next one in bit 0, then 1s once all eight are out.
+#[derive(Default)]
+pub struct Joypad {
+    pub buttons: JoypadButton,
+    strobe: bool,
+    index: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+        if self.strobe {
+            self.index = 0;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        if self.index > 7 {
+            return 1;
+        }
+
+        let bit = (self.buttons.bits() >> self.index) & 1;
+        if !self.strobe {
+            self.index += 1;
+        }
+        bit
+    }
+}
@@ -1,4 +1,9 @@
+pub mod apu;
 pub mod bus;
 pub mod cartridge;
 pub mod cpu;
+pub mod debugger;
+pub mod joypad;
+pub mod nsf;
 pub mod ppu;
+pub mod testrom;
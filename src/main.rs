@@ -34,5 +34,9 @@ fn main() -> io::Result<()> {
         buf.clear();
     });
 
+    if let Some(addr) = cpu.jammed {
+        println!("CPU jammed at ${addr:04X}");
+    }
+
     Ok(())
 }
@@ -0,0 +1,187 @@
+use crate::cartridge::{
+    mapper::{
+        fds_audio::FdsAudio, namco163_audio::Namco163Audio, read_banked, sunsoft5b::Sunsoft5b,
+        Mapper,
+    },
+    Mirroring,
+};
+
+use super::{ExpansionChips, Nsf};
+
+const BANK_SIZE: usize = 0x1000;
+const PRG_RAM_SIZE: usize = 0x2000;
+// FDS tunes see RAM from $6000 all the way up to $FFFF
+const FDS_RAM_SIZE: usize = 0xA000;
+
+// Address of the synthesized driver: `JSR target; BRK`. The player patches
+// the JSR operand through $4101/$4102 and runs the CPU until it hits BRK.
+pub const DRIVER_ADDR: u16 = 0x4100;
+pub const DRIVER_RETURN: u16 = DRIVER_ADDR + 3;
+const DRIVER: [u8; 4] = [0x20, 0x00, 0x00, 0x00];
+
+// Cartridge board the NSF format describes.
+//
+//   $4040-$4092  FDS wavetable (FDS tunes)
+//   $4100-$4103  synthesized driver
+//   $4800        Namco 163 internal RAM data port
+//   $5FF6-$5FF7  4 KiB banks at $6000/$7000 (FDS tunes)
+//   $5FF8-$5FFF  4 KiB banks at $8000-$FFFF
+//   $6000-$7FFF  PRG-RAM
+//   $8000-$FFFF  program data, banked or loaded at the load address
+//   $C000/$E000  Sunsoft 5B register select/write
+//   $F800        Namco 163 internal RAM address port
+//
+// FDS tunes run out of RAM: bank switches copy data into it instead.
+pub struct NsfBoard {
+    data: Vec<u8>,
+    banks: [u8; 8],
+    ram: Vec<u8>,
+    fds: bool,
+    driver: [u8; 4],
+
+    fds_audio: Option<FdsAudio>,
+    n163: Option<Namco163Audio>,
+    sunsoft: Option<Sunsoft5b>,
+}
+
+impl NsfBoard {
+    pub fn new(nsf: &Nsf) -> Self {
+        let fds = nsf.chips.contains(ExpansionChips::FDS);
+
+        // Without bankswitching the data is mapped linearly from the load
+        // address, which is the same as banks 0-7 of data padded from $8000
+        // (or $6000 for FDS tunes).
+        let (padding, banks) = match nsf.bankswitch {
+            Some(banks) => (nsf.load_addr as usize & (BANK_SIZE - 1), banks),
+            None => {
+                let base = if fds { 0x6000 } else { 0x8000 };
+                let padding = (nsf.load_addr as usize).saturating_sub(base);
+                (padding, [0, 1, 2, 3, 4, 5, 6, 7])
+            }
+        };
+
+        let mut data = vec![0; padding];
+        data.extend_from_slice(&nsf.data);
+
+        let mut board = Self {
+            data,
+            banks,
+            ram: vec![0; if fds { FDS_RAM_SIZE } else { PRG_RAM_SIZE }],
+            fds,
+            driver: DRIVER,
+            fds_audio: fds.then(FdsAudio::new),
+            n163: nsf
+                .chips
+                .contains(ExpansionChips::NAMCO163)
+                .then(Namco163Audio::new),
+            sunsoft: nsf
+                .chips
+                .contains(ExpansionChips::SUNSOFT5B)
+                .then(Sunsoft5b::new),
+        };
+
+        if fds {
+            match nsf.bankswitch {
+                Some(banks) => {
+                    board.switch_fds_bank(0, banks[6]);
+                    board.switch_fds_bank(1, banks[7]);
+                    for (slot, &bank) in banks.iter().enumerate() {
+                        board.switch_fds_bank(slot + 2, bank);
+                    }
+                }
+                None => {
+                    let len = board.data.len().min(FDS_RAM_SIZE);
+                    board.ram[..len].copy_from_slice(&board.data[..len]);
+                }
+            }
+        }
+
+        board
+    }
+
+    // Copies a bank of data into the FDS RAM window; slot 0 is $6000
+    fn switch_fds_bank(&mut self, slot: usize, bank: u8) {
+        let window = &mut self.ram[slot * BANK_SIZE..(slot + 1) * BANK_SIZE];
+        for (idx, byte) in window.iter_mut().enumerate() {
+            *byte = self
+                .data
+                .get(bank as usize * BANK_SIZE + idx)
+                .copied()
+                .unwrap_or(0);
+        }
+    }
+}
+
+impl Mapper for NsfBoard {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x4092 if self.fds_audio.is_some() => {
+                self.fds_audio.as_mut().unwrap().read(addr)
+            }
+            DRIVER_ADDR..=DRIVER_RETURN => self.driver[(addr - DRIVER_ADDR) as usize],
+            0x4800..=0x4FFF if self.n163.is_some() => self.n163.as_mut().unwrap().read(),
+            0x6000..=0xFFFF if self.fds => self.ram[addr as usize - 0x6000],
+            0x6000..=0x7FFF => self.ram[addr as usize - 0x6000],
+            0x8000..=0xFFFF => {
+                let bank = self.banks[(addr as usize - 0x8000) / BANK_SIZE];
+                read_banked(&self.data, bank as usize, BANK_SIZE, addr)
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x408A if self.fds_audio.is_some() => {
+                self.fds_audio.as_mut().unwrap().write(addr, data)
+            }
+            0x4101..=0x4102 => self.driver[(addr - DRIVER_ADDR) as usize] = data,
+            0x4800..=0x4FFF if self.n163.is_some() => self.n163.as_mut().unwrap().write(data),
+            0x5FF6..=0x5FFF if self.fds => self.switch_fds_bank(addr as usize - 0x5FF6, data),
+            0x5FF8..=0x5FFF => self.banks[addr as usize - 0x5FF8] = data,
+            0xC000..=0xDFFF if self.sunsoft.is_some() => {
+                self.sunsoft.as_mut().unwrap().select(data)
+            }
+            0xE000..=0xFFFF if self.sunsoft.is_some() => self.sunsoft.as_mut().unwrap().write(data),
+            0xF800..=0xFFFF if self.n163.is_some() => self.n163.as_mut().unwrap().set_addr(data),
+            0x6000..=0xFFFF if self.fds => self.ram[addr as usize - 0x6000] = data,
+            0x6000..=0x7FFF => self.ram[addr as usize - 0x6000] = data,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn ppu_write(&mut self, _addr: u16, _data: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Horizontal
+    }
+
+    fn prg_ram(&mut self) -> Option<&mut [u8]> {
+        Some(self.ram.as_mut_slice())
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            if let Some(audio) = &mut self.fds_audio {
+                audio.tick();
+            }
+            if let Some(audio) = &mut self.n163 {
+                audio.tick();
+            }
+            if let Some(audio) = &mut self.sunsoft {
+                audio.tick();
+            }
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        let fds = self.fds_audio.as_ref().map_or(0.0, FdsAudio::output);
+        let n163 = self.n163.as_ref().map_or(0.0, Namco163Audio::output);
+        let sunsoft = self.sunsoft.as_ref().map_or(0.0, Sunsoft5b::output);
+        fds + n163 + sunsoft
+    }
+}
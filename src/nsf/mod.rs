@@ -0,0 +1,330 @@
+mod board;
+mod player;
+mod wav;
+
+use bitflags::bitflags;
+
+use crate::cartridge::{RomError, Timing};
+
+pub use board::NsfBoard;
+pub use player::NsfPlayer;
+pub use wav::{write_wav, MAX_WAV_SAMPLES};
+
+const NSF_TAG: &[u8] = b"NESM\x1A";
+const NSFE_TAG: &[u8] = b"NSFE";
+const NSF_HEADER_SIZE: usize = 0x80;
+const NSFE_CHUNK_HEADER_SIZE: usize = 8;
+
+// Play routine periods in microseconds used when a file does not give one
+const DEFAULT_NTSC_SPEED: u16 = 16639;
+const DEFAULT_PAL_SPEED: u16 = 19997;
+
+bitflags! {
+    // Expansion sound chips the tune writes to (NSF header byte $7B)
+    #[derive(Debug, PartialEq, Clone, Copy)]
+    pub struct ExpansionChips: u8 {
+        const VRC6       = 0b00000001;
+        const VRC7       = 0b00000010;
+        const FDS        = 0b00000100;
+        const MMC5       = 0b00001000;
+        const NAMCO163   = 0b00010000;
+        const SUNSOFT5B  = 0b00100000;
+    }
+}
+
+impl ExpansionChips {
+    // Chips with an emulated sound core; writes to the others are dropped.
+    pub fn supported() -> Self {
+        Self::FDS | Self::NAMCO163 | Self::SUNSOFT5B
+    }
+}
+
+// A parsed NSF or NSFe music rip.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Nsf {
+    pub total_songs: u8,
+    // Zero-based, unlike the one-based header field
+    pub starting_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    // Play routine periods in microseconds
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    // Initial 4 KiB bank numbers for $8000-$FFFF, if the tune bankswitches
+    pub bankswitch: Option<[u8; 8]>,
+    pub timing: Timing,
+    pub chips: ExpansionChips,
+    pub data: Vec<u8>,
+}
+
+impl Nsf {
+    pub fn new(raw: &[u8]) -> Result<Nsf, RomError> {
+        if raw.starts_with(NSF_TAG) {
+            parse_nsf(raw)
+        } else if raw.starts_with(NSFE_TAG) {
+            parse_nsfe(raw)
+        } else {
+            Err(RomError::BadMagic)
+        }
+    }
+}
+
+fn read_u16(raw: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([raw[pos], raw[pos + 1]])
+}
+
+// Header strings are NUL padded and nominally ASCII
+fn read_str(raw: &[u8]) -> String {
+    let text = raw.split(|&byte| byte == 0).next().unwrap_or_default();
+    String::from_utf8_lossy(text).trim().to_string()
+}
+
+fn timing_from_flags(flags: u8) -> Timing {
+    match flags & 0x03 {
+        0 => Timing::Ntsc,
+        1 => Timing::Pal,
+        _ => Timing::MultiRegion,
+    }
+}
+
+fn parse_nsf(raw: &[u8]) -> Result<Nsf, RomError> {
+    if raw.len() < NSF_HEADER_SIZE {
+        return Err(RomError::TruncatedHeader { len: raw.len() });
+    }
+
+    let bankswitch: [u8; 8] = raw[0x70..0x78].try_into().unwrap();
+
+    // NSF2 may give the program length so that metadata can follow it
+    let data_len = u32::from_le_bytes([raw[0x7D], raw[0x7E], raw[0x7F], 0]) as usize;
+    let available = raw.len() - NSF_HEADER_SIZE;
+    let data_len = match data_len {
+        0 => available,
+        len if len > available => {
+            return Err(RomError::TruncatedPrg {
+                expected: len,
+                actual: available,
+            })
+        }
+        len => len,
+    };
+
+    Ok(Nsf {
+        total_songs: raw[0x06],
+        starting_song: raw[0x07].saturating_sub(1),
+        load_addr: read_u16(raw, 0x08),
+        init_addr: read_u16(raw, 0x0A),
+        play_addr: read_u16(raw, 0x0C),
+        title: read_str(&raw[0x0E..0x2E]),
+        artist: read_str(&raw[0x2E..0x4E]),
+        copyright: read_str(&raw[0x4E..0x6E]),
+        ntsc_speed: read_u16(raw, 0x6E),
+        pal_speed: read_u16(raw, 0x78),
+        bankswitch: bankswitch
+            .iter()
+            .any(|&bank| bank != 0)
+            .then_some(bankswitch),
+        timing: timing_from_flags(raw[0x7A]),
+        chips: ExpansionChips::from_bits_truncate(raw[0x7B]),
+        data: raw[NSF_HEADER_SIZE..NSF_HEADER_SIZE + data_len].to_vec(),
+    })
+}
+
+// Parses an NSFe rip: the "NSFE" tag followed by chunks of little-endian
+// u32 length, 4-byte id and payload, ending with NEND.
+fn parse_nsfe(raw: &[u8]) -> Result<Nsf, RomError> {
+    let mut info = None;
+    let mut data = None;
+    let mut bankswitch = None;
+    let mut speeds = None;
+    let mut auth: Vec<String> = vec![];
+
+    let mut pos = NSFE_TAG.len();
+    while pos + NSFE_CHUNK_HEADER_SIZE <= raw.len() {
+        let len = u32::from_le_bytes(raw[pos..pos + 4].try_into().unwrap()) as usize;
+        let id = &raw[pos + 4..pos + 8];
+        pos += NSFE_CHUNK_HEADER_SIZE;
+
+        let available = raw.len() - pos;
+        if len > available {
+            if id == b"DATA" {
+                return Err(RomError::TruncatedPrg {
+                    expected: len,
+                    actual: available,
+                });
+            }
+            break;
+        }
+        let chunk = &raw[pos..pos + len];
+        pos += len;
+
+        match id {
+            b"INFO" if chunk.len() >= 8 => info = Some(chunk),
+            b"INFO" => return Err(RomError::TruncatedHeader { len: chunk.len() }),
+            b"DATA" => data = Some(chunk),
+            b"BANK" => {
+                let mut banks = [0; 8];
+                banks[..chunk.len().min(8)].copy_from_slice(&chunk[..chunk.len().min(8)]);
+                bankswitch = Some(banks);
+            }
+            b"RATE" if chunk.len() >= 2 => {
+                let pal = (chunk.len() >= 4).then(|| read_u16(chunk, 2));
+                speeds = Some((read_u16(chunk, 0), pal));
+            }
+            b"auth" => {
+                auth = chunk
+                    .split(|&byte| byte == 0)
+                    .map(|text| String::from_utf8_lossy(text).trim().to_string())
+                    .collect()
+            }
+            b"NEND" => break,
+            _ => (),
+        }
+    }
+
+    let info = info.ok_or(RomError::MissingChunk("INFO"))?;
+    let data = data.ok_or(RomError::MissingChunk("DATA"))?;
+    let (ntsc_speed, pal_speed) = speeds.unwrap_or((DEFAULT_NTSC_SPEED, None));
+    let mut auth = auth.into_iter();
+
+    Ok(Nsf {
+        total_songs: info.get(8).copied().unwrap_or(1),
+        starting_song: info.get(9).copied().unwrap_or(0),
+        load_addr: read_u16(info, 0),
+        init_addr: read_u16(info, 2),
+        play_addr: read_u16(info, 4),
+        title: auth.next().unwrap_or_default(),
+        artist: auth.next().unwrap_or_default(),
+        copyright: auth.next().unwrap_or_default(),
+        ntsc_speed,
+        pal_speed: pal_speed.unwrap_or(DEFAULT_PAL_SPEED),
+        bankswitch,
+        timing: timing_from_flags(info[6]),
+        chips: ExpansionChips::from_bits_truncate(info[7]),
+        data: data.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::cpu::Mem;
+
+    // A tune whose INIT stores the song number to $00 and whose PLAY
+    // writes a constant-volume square wave to pulse 1.
+    fn nsf_image() -> Vec<u8> {
+        let mut raw = vec![0; NSF_HEADER_SIZE];
+        raw[..5].copy_from_slice(NSF_TAG);
+        raw[0x05] = 1;
+        raw[0x06] = 3;
+        raw[0x07] = 2;
+        raw[0x08..0x0A].copy_from_slice(&0x8000u16.to_le_bytes());
+        raw[0x0A..0x0C].copy_from_slice(&0x8000u16.to_le_bytes());
+        raw[0x0C..0x0E].copy_from_slice(&0x8003u16.to_le_bytes());
+        raw[0x0E..0x13].copy_from_slice(b"Title");
+        raw[0x6E..0x70].copy_from_slice(&DEFAULT_NTSC_SPEED.to_le_bytes());
+
+        raw.extend_from_slice(&[
+            0x85, 0x00, // $8000 STA $00
+            0x60, //       $8002 RTS
+            0xA9, 0xBF, // $8003 LDA #$BF  (50% duty, constant volume 15)
+            0x8D, 0x00, 0x40, // STA $4000
+            0xA9, 0xFE, // LDA #$FE
+            0x8D, 0x02, 0x40, // STA $4002
+            0xE6, 0x01, // INC $01
+            0xA5, 0x01, // LDA $01
+            0xC9, 0x01, // CMP #$01
+            0xD0, 0x05, // BNE +5
+            0xA9, 0x08, // LDA #$08
+            0x8D, 0x03, 0x40, // STA $4003 (only on the first frame)
+            0x60, //       RTS
+        ]);
+        raw
+    }
+
+    #[test]
+    fn test_nsf_header() {
+        let nsf = Nsf::new(&nsf_image()).unwrap();
+        assert_eq!(nsf.total_songs, 3);
+        assert_eq!(nsf.starting_song, 1);
+        assert_eq!(nsf.init_addr, 0x8000);
+        assert_eq!(nsf.play_addr, 0x8003);
+        assert_eq!(nsf.title, "Title");
+        assert_eq!(nsf.bankswitch, None);
+        assert_eq!(nsf.timing, Timing::Ntsc);
+
+        assert_eq!(
+            Nsf::new(&nsf_image()[..0x40]).err(),
+            Some(RomError::TruncatedHeader { len: 0x40 })
+        );
+        assert_eq!(Nsf::new(b"NES\x1A").err(), Some(RomError::BadMagic));
+    }
+
+    #[test]
+    fn test_nsfe_chunks() {
+        let nsf = Nsf::new(&nsf_image()).unwrap();
+        let chunk = |id: &[u8], data: &[u8]| {
+            let mut chunk = (data.len() as u32).to_le_bytes().to_vec();
+            chunk.extend_from_slice(id);
+            chunk.extend_from_slice(data);
+            chunk
+        };
+
+        let mut raw = NSFE_TAG.to_vec();
+        raw.extend(chunk(
+            b"INFO",
+            &[0x00, 0x80, 0x00, 0x80, 0x03, 0x80, 0x00, 0x20, 3, 1],
+        ));
+        raw.extend(chunk(b"DATA", &nsf.data));
+        raw.extend(chunk(b"auth", b"Title\0Artist\0"));
+        raw.extend(chunk(b"NEND", &[]));
+
+        let nsfe = Nsf::new(&raw).unwrap();
+        assert_eq!(nsfe.data, nsf.data);
+        assert_eq!(nsfe.starting_song, 1);
+        assert_eq!(nsfe.artist, "Artist");
+        assert_eq!(nsfe.chips, ExpansionChips::SUNSOFT5B);
+        assert_eq!(nsfe.ntsc_speed, DEFAULT_NTSC_SPEED);
+
+        let mut missing = NSFE_TAG.to_vec();
+        missing.extend(chunk(b"DATA", &nsf.data));
+        assert_eq!(
+            Nsf::new(&missing).err(),
+            Some(RomError::MissingChunk("INFO"))
+        );
+    }
+
+    #[test]
+    fn test_player_runs_init_and_play() {
+        let nsf = Nsf::new(&nsf_image()).unwrap();
+        let mut player = NsfPlayer::new(nsf, 2, 44100);
+        assert_eq!(player.cpu.bus.mem_read(0x0000), 2);
+
+        let samples = player.render(4410);
+        assert_eq!(samples.len(), 4410);
+        assert!(player.cpu.bus.mem_read(0x0001) >= 6);
+        assert!(samples.iter().any(|&sample| sample > 0.05));
+        assert!(samples.iter().any(|&sample| sample < -0.05));
+    }
+
+    #[test]
+    fn test_player_pal() {
+        let mut raw = nsf_image();
+        raw[0x78..0x7A].copy_from_slice(&20000u16.to_le_bytes());
+        raw[0x7A] = 0x01;
+        // INIT stores X, the region, instead of the song
+        raw[NSF_HEADER_SIZE] = 0x86;
+
+        let nsf = Nsf::new(&raw).unwrap();
+        assert_eq!(nsf.timing, Timing::Pal);
+        let mut player = NsfPlayer::new(nsf, 0, 44100);
+        assert_eq!(player.cpu.bus.mem_read(0x0000), 1);
+
+        // A second of PAL, 50 PLAY calls
+        player.render(44100);
+        assert!((50..=51).contains(&player.cpu.bus.mem_read(0x0001)));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    bus::bus::{Bus, NTSC_CPU_CLOCK, PAL_CPU_CLOCK},
+    cartridge::Timing,
+    cpu::{
+        cpu::{constants::INTERRUPT_DISABLE, Mem, STACK_RESET},
+        CPU,
+    },
+};
+
+use super::{
+    board::{DRIVER_ADDR, DRIVER_RETURN},
+    Nsf, NsfBoard,
+};
+
+// A single INIT or PLAY call is cut short after a second of CPU time, so
+// tunes that never return from INIT still get their PLAY calls.
+const CALL_CYCLE_LIMIT: usize = NTSC_CPU_CLOCK;
+
+// Pole of the DC-blocking high-pass filter applied to the APU mix, which
+// otherwise only swings above zero
+const HIGH_PASS_POLE: f32 = 0.995;
+
+// Plays one song of an NSF rip: INIT is called once with the song number in
+// A, then PLAY at the rate the header asks for, and the Bus mixer output is
+// resampled to `sample_rate`.
+//
+// PAL-only tunes get the PAL play rate and CPU clock, and X = 1 in INIT and
+// PLAY; dual-region ones play as NTSC. The APU only has NTSC tables, so on
+// PAL the noise and DMC rates and the frame counter are slightly off.
+pub struct NsfPlayer {
+    pub cpu: CPU,
+    nsf: Nsf,
+    pal: bool,
+    frame_cycles: f64,
+    next_frame: f64,
+    sampler: Sampler,
+}
+
+struct Sampler {
+    cycles_per_sample: f64,
+    next_sample: f64,
+    last_input: f32,
+    last_output: f32,
+    samples: Vec<f32>,
+}
+
+impl Sampler {
+    fn catch_up(&mut self, bus: &Bus) {
+        while bus.cycles() as f64 >= self.next_sample {
+            self.next_sample += self.cycles_per_sample;
+
+            let input = bus.audio_output();
+            self.last_output = input - self.last_input + HIGH_PASS_POLE * self.last_output;
+            self.last_input = input;
+            self.samples.push(self.last_output);
+        }
+    }
+}
+
+impl NsfPlayer {
+    // Starts playing `song` (zero-based).
+    pub fn new(nsf: Nsf, song: u8, sample_rate: u32) -> Self {
+        let pal = nsf.timing == Timing::Pal;
+        let (speed, cpu_clock) = match pal {
+            true => (nsf.pal_speed, PAL_CPU_CLOCK),
+            false => (nsf.ntsc_speed, NTSC_CPU_CLOCK),
+        };
+        let speed = match (speed, pal) {
+            (0, true) => super::DEFAULT_PAL_SPEED,
+            (0, false) => super::DEFAULT_NTSC_SPEED,
+            (speed, _) => speed,
+        };
+
+        let mut player = NsfPlayer {
+            cpu: Self::power_on(&nsf),
+            nsf,
+            pal,
+            frame_cycles: speed as f64 * cpu_clock as f64 / 1_000_000.0,
+            next_frame: 0.0,
+            sampler: Sampler {
+                cycles_per_sample: cpu_clock as f64 / sample_rate as f64,
+                next_sample: 0.0,
+                last_input: 0.0,
+                last_output: 0.0,
+                samples: vec![],
+            },
+        };
+        player.init(song);
+        player
+    }
+
+    fn power_on(nsf: &Nsf) -> CPU {
+        let board: Rc<RefCell<NsfBoard>> = Rc::new(RefCell::new(NsfBoard::new(nsf)));
+        CPU::new(Bus::with_mapper(board))
+    }
+
+    pub fn nsf(&self) -> &Nsf {
+        &self.nsf
+    }
+
+    // Restarts from a freshly powered-on machine and runs INIT for `song`.
+    pub fn select_song(&mut self, song: u8) {
+        self.cpu = Self::power_on(&self.nsf);
+        self.sampler.next_sample = 0.0;
+        self.init(song);
+    }
+
+    fn init(&mut self, song: u8) {
+        for addr in 0x4000..=0x4013 {
+            self.cpu.bus.mem_write(addr, 0);
+        }
+        self.cpu.bus.mem_write(0x4015, 0x0F);
+        // Frame IRQs off: the driver never clears the interrupt flag
+        self.cpu.bus.mem_write(0x4017, 0x40);
+
+        self.call(self.nsf.init_addr, song);
+        self.next_frame = self.cpu.bus.cycles() as f64;
+    }
+
+    // Runs `addr` as a subroutine through the driver, sampling the mixer as
+    // the CPU goes.
+    fn call(&mut self, addr: u16, register_a: u8) {
+        let [lo, hi] = addr.to_le_bytes();
+        self.cpu.bus.mem_write(DRIVER_ADDR + 1, lo);
+        self.cpu.bus.mem_write(DRIVER_ADDR + 2, hi);
+
+        self.cpu.register_a = register_a;
+        // X selects NTSC (0) or PAL (1)
+        self.cpu.register_x = self.pal as u8;
+        self.cpu.stack_pointer = STACK_RESET;
+        self.cpu.flags |= INTERRUPT_DISABLE;
+        self.cpu.program_counter = DRIVER_ADDR;
+
+        let deadline = self.cpu.bus.cycles() + CALL_CYCLE_LIMIT;
+        let sampler = &mut self.sampler;
+        self.cpu.run_with_callback(|cpu| {
+            sampler.catch_up(&cpu.bus);
+            if cpu.bus.cycles() >= deadline {
+                cpu.program_counter = DRIVER_RETURN;
+            }
+        });
+    }
+
+    // Plays one frame: a PLAY call, then idle time until the next one.
+    fn frame(&mut self) {
+        self.next_frame += self.frame_cycles;
+        self.call(self.nsf.play_addr, 0);
+
+        while (self.cpu.bus.cycles() as f64) < self.next_frame {
+            self.cpu.bus.tick(1);
+            self.sampler.catch_up(&self.cpu.bus);
+        }
+    }
+
+    // Renders the next `count` mono samples, roughly in -1.0..=1.0.
+    pub fn render(&mut self, count: usize) -> Vec<f32> {
+        while self.sampler.samples.len() < count {
+            self.frame();
+        }
+
+        let rest = self.sampler.samples.split_off(count);
+        std::mem::replace(&mut self.sampler.samples, rest)
+    }
+}
@@ -0,0 +1,66 @@
+use std::io::{self, Write};
+
+const BITS_PER_SAMPLE: u16 = 16;
+const HEADER_SIZE: u32 = 36;
+
+// The most samples whose sizes still fit the RIFF header's 32-bit fields
+pub const MAX_WAV_SAMPLES: usize =
+    ((u32::MAX - HEADER_SIZE) / (BITS_PER_SAMPLE as u32 / 8)) as usize;
+
+// Writes mono samples in -1.0..=1.0 as a 16-bit PCM WAV file.
+pub fn write_wav<W: Write>(out: &mut W, sample_rate: u32, samples: &[f32]) -> io::Result<()> {
+    let block_align = BITS_PER_SAMPLE / 8;
+    if samples.len() > MAX_WAV_SAMPLES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} samples do not fit in a WAV file", samples.len()),
+        ));
+    }
+    let data_size = samples.len() as u32 * block_align as u32;
+    let byte_rate = sample_rate
+        .checked_mul(block_align as u32)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "sample rate is too high"))?;
+
+    out.write_all(b"RIFF")?;
+    out.write_all(&(HEADER_SIZE + data_size).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?; // PCM
+    out.write_all(&1u16.to_le_bytes())?; // mono
+    out.write_all(&sample_rate.to_le_bytes())?;
+    out.write_all(&byte_rate.to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+    out.write_all(b"data")?;
+    out.write_all(&data_size.to_le_bytes())?;
+    for &sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        out.write_all(&sample.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_header_sizes() {
+        let mut out = vec![];
+        write_wav(&mut out, 44100, &[0.0, 1.0, -1.0]).unwrap();
+
+        let field = |at: usize| u32::from_le_bytes(out[at..at + 4].try_into().unwrap());
+        assert_eq!(out.len(), 44 + 6);
+        assert_eq!(field(4), 36 + 6);
+        assert_eq!(field(28), 44100 * 2);
+        assert_eq!(field(40), 6);
+        assert_eq!(&out[44..], [0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80]);
+
+        let err = write_wav(&mut vec![], u32::MAX, &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -1,39 +1,46 @@
-use registers::{AddrRegister, ControlRegister, MaskRegister, StatusRegister};
+use frame::Frame;
+use registers::{AddrRegister, ControlRegister, MaskRegister, ScrollRegister, StatusRegister};
 
-use crate::cartridge::Mirroring;
+use crate::cartridge::{mapper::MapperRef, Mirroring};
 
+pub mod frame;
+pub mod palette;
 pub mod registers;
+mod render;
 
 pub struct PPU {
     pub ctrl: ControlRegister,
     pub mask: MaskRegister,
     pub status: StatusRegister,
-    // TODO OAMADDR
-    // TODO OAMDATA
-    // TODO PPUSCROLL
+    pub oam_addr: u8,
+    pub scroll: ScrollRegister,
     addr: AddrRegister,
     // TODO PPUDATA
-    // TODO OAMDMA    
 
-    pub chr_rom: Vec<u8>,
+    pub mapper: MapperRef,
     pub palette_table: [u8; 32],
     pub vram: [u8; 2048],
     pub oam_data: [u8; 256],
-    pub mirroring: Mirroring,
 
     internal_data_buf: u8,
     scanline: u16,
     cycles: usize,
+    // Set when the NMI line goes low, taken by the Bus for the CPU
+    pub nmi_interrupt: bool,
+    // The last picture, drawn at the start of each vblank
+    pub frame: Frame,
+    frame_count: usize,
 }
 
 impl PPU {
-    pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+    pub fn new(mapper: MapperRef) -> Self {
         PPU {
-            chr_rom,
+            mapper,
             palette_table: [0; 32], //TODO
             vram: [0; 2048],
             oam_data: [0; 256],
-            mirroring,
+            oam_addr: 0,
+            scroll: ScrollRegister::new(),
             internal_data_buf: 0,
             ctrl: ControlRegister::new(),
             addr: AddrRegister::new(),
@@ -41,32 +48,69 @@ impl PPU {
             status: StatusRegister::new(),
             scanline: 0,
             cycles: 0,
+            nmi_interrupt: false,
+            frame: Frame::new(),
+            frame_count: 0,
         }
     }
 
+    // Advances by `cycles` dots; returns true when a frame is finished
     pub fn tick(&mut self, cycles: u8) -> bool {
         self.cycles += cycles as usize;
-        if self.cycles >= 341 {
-            self.cycles = self.cycles - 341;
-            self.scanline += 1;
-
-            if self.scanline == 241 {
-                if self.ctrl.generate_vblank_nmi() {
-                    self.status.set_vblank_status(true);
-                    todo!("nmi interrupt")
-                }
-
-                if self.scanline >= 262 {
-                    self.scanline = 0;
-                    self.status.set_vblank_status(false);
-                    return true;
-                }
+        if self.cycles < 341 {
+            return false;
+        }
+
+        self.cycles -= 341;
+        self.scanline += 1;
+
+        // Approximated as sprite 0's top line, whatever its pixels
+        let sprite_0_y = self.oam_data[0] as u16 + 1;
+        if self.scanline == sprite_0_y && self.mask.contains(MaskRegister::SPRITES_SHOW) {
+            self.status.insert(StatusRegister::SPRITE_0_HIT);
+        }
+
+        if self.scanline == 241 {
+            let mut frame = std::mem::take(&mut self.frame);
+            render::render(self, &mut frame);
+            self.frame = frame;
+            self.frame_count += 1;
+
+            self.status.set_vblank_status(true);
+            if self.ctrl.generate_vblank_nmi() {
+                self.nmi_interrupt = true;
             }
         }
 
+        if self.scanline >= 262 {
+            self.scanline = 0;
+            self.status.set_vblank_status(false);
+            self.status.remove(StatusRegister::SPRITE_0_HIT);
+            return true;
+        }
+
         false
     }
 
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    // Dot within the current scanline
+    pub fn dot(&self) -> usize {
+        self.cycles
+    }
+
+    // Frames drawn since power-on
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    // Where the next PPUDATA ($2007) access goes
+    pub fn vram_addr(&self) -> u16 {
+        self.addr.get()
+    }
+
     pub fn write_to_ppu_addr(&mut self, data: u8) {
         self.addr.update(data);
     }
@@ -76,7 +120,13 @@ impl PPU {
     }
 
     pub fn write_to_ctrl(&mut self, data: u8) {
+        let nmi_was_enabled = self.ctrl.generate_vblank_nmi();
         self.ctrl.update(data);
+
+        // Enabling NMI during vblank fires one straight away
+        if !nmi_was_enabled && self.ctrl.generate_vblank_nmi() && self.status.is_in_v_blank() {
+            self.nmi_interrupt = true;
+        }
     }
 
     pub fn write_to_mask(&mut self, data: u8) {
@@ -84,9 +134,35 @@ impl PPU {
     }
 
     pub fn read_from_status(&mut self) -> u8 {
+        self.addr.reset_latch();
+        self.scroll.reset_latch();
         self.status.get()
     }
 
+    pub fn write_to_scroll(&mut self, data: u8) {
+        self.scroll.update(data);
+    }
+
+    pub fn write_to_oam_addr(&mut self, data: u8) {
+        self.oam_addr = data;
+    }
+
+    pub fn write_to_oam_data(&mut self, data: u8) {
+        self.oam_data[self.oam_addr as usize] = data;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    pub fn read_oam_data(&self) -> u8 {
+        self.oam_data[self.oam_addr as usize]
+    }
+
+    // OAMDMA: a page of CPU memory copied in from OAMADDR on
+    pub fn write_oam_dma(&mut self, page: &[u8; 256]) {
+        for &data in page {
+            self.write_to_oam_data(data);
+        }
+    }
+
     pub fn read_to_data(&mut self) -> u8 {
         let addr = self.addr.get();
         self.increment_vram_addr();
@@ -94,17 +170,20 @@ impl PPU {
         match addr {
             0..=0x1fff => {
                 let result = self.internal_data_buf;
-                self.internal_data_buf = self.chr_rom[addr as usize];
+                self.internal_data_buf = self.mapper.borrow_mut().ppu_read(addr);
                 result
             }
-            0x2000..=0x2fff => {
+            0x2000..=0x3eff => {
                 let result = self.internal_data_buf;
                 self.internal_data_buf = self.vram[self.mirror_vram_addr(addr) as usize];
                 result
             }
-            0x3000..=0x3eff => panic!("addr space 0x3000..0x3eff is not expected to be used, requested = {} ", addr),
-            0x3f00..=0x3fff => self.palette_table[(addr - 0x3f00) as usize],
-            _ => panic!("unexpected access to mirrored space {}", addr),
+            // Palette reads are not buffered; the buffer gets the nametable
+            // byte underneath instead
+            _ => {
+                self.internal_data_buf = self.vram[self.mirror_vram_addr(addr) as usize];
+                self.palette_table[Self::palette_index(addr)]
+            }
         }
     }
 
@@ -112,33 +191,37 @@ impl PPU {
         let addr = self.addr.get();
 
         match addr {
-            0..=0x1fff => panic!("attempt writing to chr rom space"),
-            0x2000..=0x2fff => {
+            0..=0x1fff => self.mapper.borrow_mut().ppu_write(addr, data),
+            0x2000..=0x3eff => {
                 self.vram[self.mirror_vram_addr(addr) as usize] = data;
             }
-            0x3000..=0x3eff => panic!("addr space 0x3000..0x3eff is not expected to be used, requested = {} ", addr),
-            //Addresses $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
-            0x3f10 | 0x3f14 | 0x3f18 | 0x3f1c => {
-                let add_mirror = addr - 0x10;
-                self.palette_table[(add_mirror - 0x3f00) as usize] = data;
-            }
-            0x3f00..=0x3fff => self.palette_table[(addr - 0x3f00) as usize] = data,
-            _ => panic!("unexpected access to mirrored space {}", addr),
+            _ => self.palette_table[Self::palette_index(addr)] = data,
         }
 
         self.increment_vram_addr();
     }
 
+    // $3F20-$3FFF mirror $3F00-$3F1F, and $3F10/$3F14/$3F18/$3F1C are
+    // mirrors of $3F00/$3F04/$3F08/$3F0C
+    fn palette_index(addr: u16) -> usize {
+        match addr & 0x1f {
+            0x10 | 0x14 | 0x18 | 0x1c => (addr & 0x0f) as usize,
+            idx => idx as usize,
+        }
+    }
+
     pub fn mirror_vram_addr(&self, addr: u16) -> u16 {
         let mirrored_vram = addr & 0b10111111111111; // mirror down 0x3000-0x3eff to 0x2000 - 0x2eff
         let vram_idx = mirrored_vram - 0x2000;
         let name_table = vram_idx / 0x400;
 
-        match (&self.mirroring, name_table) {
+        match (self.mapper.borrow().mirroring(), name_table) {
             (Mirroring::Vertical, 2) | (Mirroring::Vertical, 3) => vram_idx - 0x800,
             (Mirroring::Horizontal, 2) => vram_idx - 0x400,
             (Mirroring::Horizontal, 1) => vram_idx - 0x400,
             (Mirroring::Horizontal, 3) => vram_idx - 0x800,
+            (Mirroring::SingleScreenLower, _) => vram_idx & 0x3FF,
+            (Mirroring::SingleScreenUpper, _) => 0x400 | (vram_idx & 0x3FF),
             _ => vram_idx, 
         }
     }
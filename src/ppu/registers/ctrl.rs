@@ -29,6 +29,12 @@ bitflags! {
     }
  }
 
+ impl Default for ControlRegister {
+     fn default() -> Self {
+         Self::new()
+     }
+ }
+
  impl ControlRegister {
     pub fn new() -> Self {
         ControlRegister::from_bits_truncate(0x00)
@@ -77,6 +83,6 @@ bitflags! {
     }
 
     pub fn update(&mut self, data: u8) {
-        self.set(ControlRegister::from_bits_truncate(data), true);
+        *self = ControlRegister::from_bits_truncate(data);
     }
  }
\ No newline at end of file
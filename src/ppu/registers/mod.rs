@@ -29,13 +29,19 @@ bitflags! {
     }
  }
 
+ impl Default for MaskRegister {
+     fn default() -> Self {
+         Self::new()
+     }
+ }
+
  impl MaskRegister {
     pub fn new() -> Self {
         MaskRegister::from_bits_truncate(0x00)
     }
 
     pub fn update(&mut self, data: u8) {
-        self.set(MaskRegister::from_bits_truncate(data), true);
+        *self = MaskRegister::from_bits_truncate(data);
     }
  }
 
@@ -71,6 +77,12 @@ bitflags! {
     }
  }
 
+ impl Default for StatusRegister {
+     fn default() -> Self {
+         Self::new()
+     }
+ }
+
  impl StatusRegister {
     pub fn new() -> Self {
         StatusRegister::from_bits_truncate(0x00)
@@ -100,6 +112,12 @@ pub struct AddrRegister {
     hi_ptr: bool,
 }
 
+impl Default for AddrRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AddrRegister {
     pub fn new() -> Self {
         Self {
@@ -147,4 +165,32 @@ impl AddrRegister {
             self.set(self.get() & 0b11111111111111); // mirror down
         }
     }
-}
\ No newline at end of file
+}
+
+// PPUSCROLL: X on the first write, Y on the second
+#[derive(Default)]
+pub struct ScrollRegister {
+    pub x: u8,
+    pub y: u8,
+    latch: bool,
+}
+
+impl ScrollRegister {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: u8) {
+        if self.latch {
+            self.y = data;
+        } else {
+            self.x = data;
+        }
+
+        self.latch = !self.latch;
+    }
+
+    pub fn reset_latch(&mut self) {
+        self.latch = false;
+    }
+}
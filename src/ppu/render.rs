@@ -0,0 +1,131 @@
+use super::{
+    frame::{Frame, HEIGHT, WIDTH},
+    palette::SYSTEM_PALETTE,
+    registers::MaskRegister,
+    PPU,
+};
+
+// Draws the whole picture from the PPU state at the start of vblank: the
+// background scrolled by PPUSCROLL, then the sprites. Changes made during
+// the frame, like split scrolling, are not seen.
+pub fn render(ppu: &PPU, frame: &mut Frame) {
+    let backdrop = colour(ppu, 0);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            frame.set_pixel(x, y, backdrop);
+        }
+    }
+
+    // Where the background is not transparent, for sprites behind it
+    let mut opaque = vec![false; WIDTH * HEIGHT];
+    if ppu.mask.contains(MaskRegister::BACKGROUND_SHOW) {
+        render_background(ppu, frame, &mut opaque);
+    }
+    if ppu.mask.contains(MaskRegister::SPRITES_SHOW) {
+        render_sprites(ppu, frame, &opaque);
+    }
+}
+
+fn render_background(ppu: &PPU, frame: &mut Frame, opaque: &mut [bool]) {
+    let bank = ppu.ctrl.get_bgrnd_patt_addr();
+    let nametable = ppu.ctrl.get_nametable_addr() - 0x2000;
+
+    // The four nametables make a 512x480 plane, wrapping both ways
+    let origin_x = ppu.scroll.x as usize + (nametable as usize & 0x400) / 0x400 * 256;
+    let origin_y = ppu.scroll.y as usize + (nametable as usize & 0x800) / 0x800 * 240;
+
+    for y in 0..HEIGHT {
+        let plane_y = (origin_y + y) % 480;
+        for x in 0..WIDTH {
+            if x < 8 && !ppu.mask.contains(MaskRegister::BACKGROUND_CTRL) {
+                continue;
+            }
+
+            let plane_x = (origin_x + x) % 512;
+            let table = 0x2000 + (plane_y / 240 * 0x800 + plane_x / 256 * 0x400) as u16;
+            let (tile_x, tile_y) = ((plane_x % 256) / 8, (plane_y % 240) / 8);
+
+            let tile = read_vram(ppu, table + (tile_y * 32 + tile_x) as u16) as u16;
+            let attribute = read_vram(ppu, table + 0x3C0 + (tile_y / 4 * 8 + tile_x / 4) as u16);
+            let shift = (tile_y % 4 / 2 * 2 + tile_x % 4 / 2) * 2;
+            let palette = (attribute >> shift) & 0b11;
+
+            let value = pattern_pixel(ppu, bank + tile * 16, plane_y % 8, plane_x % 8);
+            if value == 0 {
+                continue;
+            }
+
+            opaque[y * WIDTH + x] = true;
+            frame.set_pixel(x, y, colour(ppu, palette * 4 + value));
+        }
+    }
+}
+
+// Lower OAM entries win, so they are drawn last
+fn render_sprites(ppu: &PPU, frame: &mut Frame, opaque: &[bool]) {
+    let height = ppu.ctrl.get_sprite_size() as usize;
+
+    for sprite in ppu.oam_data.chunks_exact(4).rev() {
+        let (top, tile, attributes, left) =
+            (sprite[0] as usize + 1, sprite[1], sprite[2], sprite[3]);
+        let palette = 4 + (attributes & 0b11);
+        let behind_background = attributes & 0x20 != 0;
+        let flip_horizontal = attributes & 0x40 != 0;
+        let flip_vertical = attributes & 0x80 != 0;
+
+        // 8x16 sprites take their bank from bit 0 of the tile number
+        let (bank, tile) = match height {
+            16 => ((tile as u16 & 1) * 0x1000, tile as u16 & 0xFE),
+            _ => (ppu.ctrl.sprite_pattern_addr(), tile as u16),
+        };
+
+        for row in 0..height {
+            let y = top + row;
+            if y >= HEIGHT {
+                break;
+            }
+
+            let row = if flip_vertical { height - 1 - row } else { row };
+            let addr = bank + (tile + row as u16 / 8) * 16;
+
+            for column in 0..8 {
+                let x = left as usize + column;
+                if x >= WIDTH || x < 8 && !ppu.mask.contains(MaskRegister::SPRITES_CTRL) {
+                    continue;
+                }
+
+                let column = if flip_horizontal { 7 - column } else { column };
+                let value = pattern_pixel(ppu, addr, row % 8, column);
+                if value == 0 || behind_background && opaque[y * WIDTH + x] {
+                    continue;
+                }
+
+                frame.set_pixel(x, y, colour(ppu, palette * 4 + value));
+            }
+        }
+    }
+}
+
+fn read_vram(ppu: &PPU, addr: u16) -> u8 {
+    ppu.vram[ppu.mirror_vram_addr(addr) as usize]
+}
+
+// Two-bit colour of a pixel in the 8x8 tile at `addr`
+fn pattern_pixel(ppu: &PPU, addr: u16, row: usize, column: usize) -> u8 {
+    let mut mapper = ppu.mapper.borrow_mut();
+    let lo = mapper.ppu_read(addr + row as u16);
+    let hi = mapper.ppu_read(addr + row as u16 + 8);
+
+    let bit = 7 - column;
+    (hi >> bit & 1) << 1 | (lo >> bit & 1)
+}
+
+// RGB of palette RAM entry `index`
+fn colour(ppu: &PPU, index: u8) -> (u8, u8, u8) {
+    let mut entry = ppu.palette_table[index as usize] & 0x3F;
+    if ppu.mask.contains(MaskRegister::GREYSCALE) {
+        entry &= 0x30;
+    }
+
+    SYSTEM_PALETTE[entry as usize]
+}
@@ -0,0 +1,78 @@
+use std::{fs, path::Path, time::Duration};
+
+use crate::{
+    bus::bus::NTSC_CPU_CLOCK,
+    cartridge::RomError,
+    cpu::{cpu::trace_with_timing, CPU},
+};
+
+use super::{Outcome, TestReport};
+
+// Loads a ROM and a reference trace and runs them with `run_log_rom`.
+pub fn run_log_rom_file<P: AsRef<Path>, L: AsRef<Path>>(
+    path: P,
+    log: L,
+    timeout: Duration,
+) -> Result<TestReport, RomError> {
+    let log = fs::read_to_string(log)?;
+    let mut cpu = CPU::load_rom_file_without_save(path)?;
+    cpu.reset();
+
+    Ok(run_log_rom(&mut cpu, &log, timeout))
+}
+
+// Compares the CPU's trace, PPU and CYC columns included, against a log in
+// Nintendulator's format, starting from the PC on its first line. Passes
+// once every line matched; otherwise the message shows the first line that
+// differs with a marker under the first wrong column.
+pub fn run_log_rom(cpu: &mut CPU, log: &str, timeout: Duration) -> TestReport {
+    let start = cpu.bus.cycles();
+    let deadline = start + (timeout.as_secs_f64() * NTSC_CPU_CLOCK as f64) as usize;
+
+    let lines = log.lines().enumerate().filter(|(_, line)| !line.is_empty());
+    if let Some(pc) = log
+        .lines()
+        .find_map(|line| u16::from_str_radix(line.get(..4)?, 16).ok())
+    {
+        cpu.program_counter = pc;
+    }
+
+    let mut message = String::new();
+    let mut outcome = Outcome::Passed;
+    let mut running = true;
+    for (idx, expected) in lines {
+        if !running {
+            outcome = Outcome::Halted(cpu.program_counter);
+            break;
+        }
+        if cpu.bus.cycles() >= deadline {
+            outcome = Outcome::TimedOut;
+            break;
+        }
+
+        let actual = trace_with_timing(cpu);
+        if actual != expected {
+            let column = actual
+                .chars()
+                .zip(expected.chars())
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            message = format!(
+                "line {}:\nexpected: {expected}\nactual:   {actual}\n          {:>column$}^",
+                idx + 1,
+                ""
+            );
+            outcome = Outcome::Diverged(idx + 1);
+            break;
+        }
+
+        running = cpu.step();
+    }
+
+    TestReport {
+        outcome,
+        message,
+        cycles: cpu.bus.cycles() - start,
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    fmt, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::cpu::CPU;
+
+use super::{
+    frame_hash, run_frames, run_log_rom, run_status_rom, InputScript, Outcome, TestReport,
+    FRAME_RATE,
+};
+
+// The CPU modes every test is run in: instruction-stepped, then
+// cycle-stepped
+pub const MODES: [bool; 2] = [false, true];
+
+// How a test ROM's result is told apart, and what it should be
+#[derive(Debug, Clone, PartialEq)]
+pub enum Method {
+    // blargg's $6000 protocol, expecting this status code; 0 is a pass
+    Status(u8),
+    // Trace compared against a Nintendulator log at this path
+    Log(PathBuf),
+    // CRC-32 of the last frame when the time limit is up
+    ScreenHash(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    // As written in the manifest, relative to it
+    pub name: String,
+    pub rom: PathBuf,
+    pub method: Method,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "{}", self.message),
+            line => write!(f, "line {line}: {}", self.message),
+        }
+    }
+}
+
+// A list of accuracy test ROMs, one per line, whitespace separated:
+//
+//   method    status, log or hash
+//   expect    status: pass or the failure code it should report
+//             log: reference log, relative to the manifest
+//             hash: CRC-32 of the final frame in hex
+//   seconds   emulated time limit, or how long to run for hash
+//   rom       ROM path relative to the manifest, rest of the line
+//
+// Blank lines and lines starting with # are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ManifestError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|err| ManifestError {
+            line: 0,
+            message: format!("{}: {err}", path.display()),
+        })?;
+
+        Self::parse(&text, path.parent().unwrap_or(Path::new("")))
+    }
+
+    // Parses manifest text, resolving paths against `base`.
+    pub fn parse(text: &str, base: &Path) -> Result<Self, ManifestError> {
+        let mut manifest = Manifest::default();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let entry = parse_line(line, base).map_err(|message| ManifestError {
+                line: idx + 1,
+                message,
+            })?;
+            manifest.entries.push(entry);
+        }
+
+        Ok(manifest)
+    }
+
+    // Runs every entry in every one of `MODES` on `jobs` threads. Each
+    // entry gets its reports in `MODES` order.
+    pub fn run(&self, jobs: usize) -> Vec<Vec<TestReport>> {
+        let runs: Vec<_> = self
+            .entries
+            .iter()
+            .flat_map(|entry| {
+                MODES
+                    .iter()
+                    .map(move |&cycle_stepped| (entry, cycle_stepped))
+            })
+            .collect();
+
+        let next = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..jobs.max(1) {
+                let sender = sender.clone();
+                let (runs, next) = (&runs, &next);
+                scope.spawn(move || loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(&(entry, cycle_stepped)) = runs.get(idx) else {
+                        return;
+                    };
+                    let report = entry.run(cycle_stepped);
+                    sender.send((idx, report)).unwrap();
+                });
+            }
+        });
+        drop(sender);
+
+        let mut reports: Vec<Option<TestReport>> = vec![None; runs.len()];
+        for (idx, report) in receiver {
+            reports[idx] = Some(report);
+        }
+
+        let mut reports = reports.into_iter().map(Option::unwrap);
+        self.entries
+            .iter()
+            .map(|_| reports.by_ref().take(MODES.len()).collect())
+            .collect()
+    }
+}
+
+impl ManifestEntry {
+    // Runs the ROM from power-on. Load errors and emulator panics are
+    // reported rather than passed on, so one bad entry does not stop a run.
+    pub fn run(&self, cycle_stepped: bool) -> TestReport {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.run_unguarded(cycle_stepped)));
+
+        match result {
+            Ok(report) => report,
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| payload.downcast_ref::<&str>().map(|msg| msg.to_string()))
+                    .unwrap_or_default();
+
+                TestReport {
+                    outcome: Outcome::Crashed,
+                    message,
+                    cycles: 0,
+                }
+            }
+        }
+    }
+
+    fn run_unguarded(&self, cycle_stepped: bool) -> TestReport {
+        let load_failed = |message: String| TestReport {
+            outcome: Outcome::LoadFailed,
+            message,
+            cycles: 0,
+        };
+
+        let mut cpu = match CPU::load_rom_file_without_save(&self.rom) {
+            Ok(cpu) => cpu,
+            Err(err) => return load_failed(format!("{}: {err}", self.rom.display())),
+        };
+        cpu.cycle_stepped = cycle_stepped;
+        cpu.reset();
+
+        match &self.method {
+            Method::Status(_) => run_status_rom(&mut cpu, self.timeout),
+            Method::Log(log) => match fs::read_to_string(log) {
+                Ok(log) => run_log_rom(&mut cpu, &log, self.timeout),
+                Err(err) => load_failed(format!("{}: {err}", log.display())),
+            },
+            &Method::ScreenHash(expected) => {
+                let frames = (self.timeout.as_secs_f64() * FRAME_RATE).round() as usize;
+                let start = cpu.bus.cycles();
+                let hash = frame_hash(&run_frames(&mut cpu, frames, &InputScript::default()));
+
+                TestReport {
+                    outcome: match hash == expected {
+                        true => Outcome::Passed,
+                        false => Outcome::WrongHash(hash),
+                    },
+                    message: String::new(),
+                    cycles: cpu.bus.cycles() - start,
+                }
+            }
+        }
+    }
+
+    // Whether `outcome` is the result the manifest asks for
+    pub fn expects(&self, outcome: Outcome) -> bool {
+        match (&self.method, outcome) {
+            (Method::Status(0), Outcome::Passed) => true,
+            (Method::Status(code), Outcome::Failed(actual)) => *code == actual,
+            (Method::Log(_) | Method::ScreenHash(_), Outcome::Passed) => true,
+            _ => false,
+        }
+    }
+}
+
+fn parse_line(line: &str, base: &Path) -> Result<ManifestEntry, String> {
+    let mut rest = line;
+    let mut next = |what| {
+        let field = rest.trim_start();
+        let end = field.find(char::is_whitespace).unwrap_or(field.len());
+        rest = &field[end..];
+
+        match &field[..end] {
+            "" => Err(format!("missing {what}")),
+            field => Ok(field),
+        }
+    };
+
+    let method = next("method")?;
+    let expect = next("expected result")?;
+    let seconds = next("time limit")?;
+    let rom = match rest.trim() {
+        "" => return Err("missing ROM path".to_string()),
+        rom => rom,
+    };
+
+    let method = match method {
+        "status" => Method::Status(match expect {
+            "pass" => 0,
+            code => code
+                .parse()
+                .map_err(|_| format!("bad status code {code}"))?,
+        }),
+        "log" => Method::Log(base.join(expect)),
+        "hash" => Method::ScreenHash(
+            u32::from_str_radix(expect, 16).map_err(|_| format!("bad CRC-32 {expect}"))?,
+        ),
+        method => return Err(format!("unknown method {method}")),
+    };
+    let seconds: f64 = seconds
+        .parse()
+        .map_err(|_| format!("bad time limit {seconds}"))?;
+
+    Ok(ManifestEntry {
+        name: rom.to_string(),
+        rom: base.join(rom),
+        method,
+        timeout: Duration::from_secs_f64(seconds),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_manifest_parse() {
+        let text = "
+            # method  expect    seconds  rom
+            status    pass      30       instr_test/01-basics.nes
+            status    3         0.5      apu_test/4-jitter.nes
+            log       trace.log 1        nestest.nes
+            hash      89ABCDEF  2        games/Some Game.nes
+        ";
+        let manifest = Manifest::parse(text, Path::new("roms")).unwrap();
+
+        let entries = &manifest.entries;
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].name, "instr_test/01-basics.nes");
+        assert_eq!(
+            entries[0].rom,
+            PathBuf::from("roms/instr_test/01-basics.nes")
+        );
+        assert_eq!(entries[0].method, Method::Status(0));
+        assert_eq!(entries[0].timeout, Duration::from_secs(30));
+        assert_eq!(entries[1].method, Method::Status(3));
+        assert_eq!(entries[1].timeout, Duration::from_millis(500));
+        assert_eq!(
+            entries[2].method,
+            Method::Log(PathBuf::from("roms/trace.log"))
+        );
+        assert_eq!(entries[3].method, Method::ScreenHash(0x89ABCDEF));
+        assert_eq!(entries[3].rom, PathBuf::from("roms/games/Some Game.nes"));
+
+        assert!(entries[0].expects(Outcome::Passed));
+        assert!(!entries[0].expects(Outcome::Failed(3)));
+        assert!(entries[1].expects(Outcome::Failed(3)));
+        assert!(!entries[1].expects(Outcome::Passed));
+        assert!(!entries[2].expects(Outcome::Diverged(10)));
+    }
+
+    #[test]
+    fn test_manifest_errors() {
+        for (text, line, message) in [
+            ("status pass 30", 1, "missing ROM path"),
+            ("\nstatus fine 30 a.nes", 2, "bad status code fine"),
+            ("dump pass 30 a.nes", 1, "unknown method dump"),
+            ("hash xyz 30 a.nes", 1, "bad CRC-32 xyz"),
+            ("log a.log soon a.nes", 1, "bad time limit soon"),
+        ] {
+            let err = Manifest::parse(text, Path::new("")).unwrap_err();
+            assert_eq!(err.line, line, "{text}");
+            assert_eq!(err.message, message, "{text}");
+        }
+    }
+
+    #[test]
+    fn test_missing_rom_is_reported() {
+        let manifest = Manifest::parse("status pass 1 missing.nes", Path::new("")).unwrap();
+        let report = manifest.entries[0].run(false);
+        assert_eq!(report.outcome, Outcome::LoadFailed);
+        assert!(report.message.starts_with("missing.nes"));
+    }
+}
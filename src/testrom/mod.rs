@@ -0,0 +1,86 @@
+use std::fmt;
+
+mod log;
+mod manifest;
+mod png;
+mod screen;
+mod status;
+
+pub use log::{run_log_rom, run_log_rom_file};
+pub use manifest::{Manifest, ManifestEntry, ManifestError, Method, MODES};
+pub use png::{read_png, write_png};
+pub use screen::{check_golden, frame_hash, load_frame, run_frames, save_frame, InputScript};
+pub use status::{run_status_rom, run_status_rom_file};
+
+// NTSC frames per second, the same for frame counts
+const FRAME_RATE: f64 = 60.0988;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    // The ROM's result code, 1 and up
+    Failed(u8),
+    // Still running, or never started reporting, when time ran out
+    TimedOut,
+    // Stopped at a BRK or jammed on a KIL, at this address
+    Halted(u16),
+    // Trace differed from the reference log from this line on
+    Diverged(usize),
+    // The ROM or its reference could not be read
+    LoadFailed,
+    // The emulator panicked
+    Crashed,
+    // The final frame hashed to this instead
+    WrongHash(u32),
+    // This many pixels differ from the golden picture
+    ScreenDiffers(usize),
+}
+
+impl Outcome {
+    pub fn passed(self) -> bool {
+        self == Outcome::Passed
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Passed => write!(f, "passed"),
+            Outcome::Failed(code) => write!(f, "failed with code {code}"),
+            Outcome::TimedOut => write!(f, "timed out"),
+            Outcome::Halted(addr) => write!(f, "halted at ${addr:04X}"),
+            Outcome::Diverged(line) => write!(f, "diverged at line {line}"),
+            Outcome::LoadFailed => write!(f, "could not be loaded"),
+            Outcome::Crashed => write!(f, "crashed"),
+            Outcome::WrongHash(hash) => write!(f, "frame hash is {hash:08X}"),
+            Outcome::ScreenDiffers(pixels) => write!(f, "{pixels} pixels differ"),
+        }
+    }
+}
+
+// What a test ROM run came to: the outcome, the text the ROM printed and
+// how many CPU cycles it took.
+#[derive(Debug, Clone)]
+pub struct TestReport {
+    pub outcome: Outcome,
+    pub message: String,
+    pub cycles: usize,
+}
+
+// An NROM image running `program` from $8000, with `chr` at the start of
+// CHR ROM
+#[cfg(test)]
+fn test_cpu(program: &[u8], chr: &[u8]) -> crate::cpu::CPU {
+    use crate::{bus::bus::Bus, cartridge::test_rom, cpu::CPU};
+
+    let mut cpu = CPU::new(Bus::new(test_rom(program, chr)).unwrap());
+    cpu.reset();
+    cpu
+}
+
+// LDA #data; STA addr
+#[cfg(test)]
+fn store(program: &mut Vec<u8>, data: u8, addr: u16) {
+    let [lo, hi] = addr.to_le_bytes();
+    program.extend_from_slice(&[0xA9, data, 0x8D, lo, hi]);
+}
@@ -0,0 +1,196 @@
+use std::io::{self, Write};
+
+use miniz_oxide::{deflate::compress_to_vec_zlib, inflate::decompress_to_vec_zlib};
+
+use crate::cartridge::hash::crc32;
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const BIT_DEPTH: u8 = 8;
+const COLOUR_TYPE_RGB: u8 = 2;
+const COMPRESSION_LEVEL: u8 = 6;
+
+// Writes 24-bit RGB pixels, row by row, as a PNG file.
+pub fn write_png<W: Write>(out: &mut W, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Compression, filter method and interlacing are all 0
+    header.extend_from_slice(&[BIT_DEPTH, COLOUR_TYPE_RGB, 0, 0, 0]);
+
+    // Every row starts with its filter type, 0 for none
+    let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+    for row in rgb.chunks_exact(width * 3) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    out.write_all(SIGNATURE)?;
+    write_chunk(out, b"IHDR", &header)?;
+    write_chunk(out, b"IDAT", &compress_to_vec_zlib(&raw, COMPRESSION_LEVEL))?;
+    write_chunk(out, b"IEND", &[])
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    out.write_all(&crc32(&[kind, data].concat()).to_be_bytes())
+}
+
+// Reads an 8-bit RGB, non-interlaced PNG, the kind `write_png` makes,
+// returning its width, height and pixels.
+pub fn read_png(data: &[u8]) -> Result<(usize, usize, Vec<u8>), String> {
+    let mut rest = data.strip_prefix(SIGNATURE).ok_or("not a PNG file")?;
+
+    let mut header = None;
+    let mut compressed = vec![];
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let kind = &rest[4..8];
+        let body = rest.get(8..8 + len).ok_or("truncated chunk")?;
+        rest = &rest[(12 + len).min(rest.len())..];
+
+        match kind {
+            b"IHDR" if body.len() >= 13 => header = Some(body[..13].to_vec()),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => (),
+        }
+    }
+
+    let header = header.ok_or("missing IHDR chunk")?;
+    let width = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+    if header[8..] != [BIT_DEPTH, COLOUR_TYPE_RGB, 0, 0, 0] {
+        return Err("only 8-bit RGB PNGs without interlacing are supported".to_string());
+    }
+
+    let raw = decompress_to_vec_zlib(&compressed).map_err(|_| "bad image data")?;
+    let stride = width * 3;
+    if raw.len() < (stride + 1) * height {
+        return Err("image data is truncated".to_string());
+    }
+
+    let mut rgb = vec![0; stride * height];
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let line = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+
+        for x in 0..stride {
+            let left = if x >= 3 { rgb[y * stride + x - 3] } else { 0 };
+            let up = if y > 0 { rgb[(y - 1) * stride + x] } else { 0 };
+            let up_left = if x >= 3 && y > 0 {
+                rgb[(y - 1) * stride + x - 3]
+            } else {
+                0
+            };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(format!("unknown filter type {filter}")),
+            };
+            rgb[y * stride + x] = line[x].wrapping_add(predicted);
+        }
+    }
+
+    Ok((width, height, rgb))
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance = |value: u8| (estimate - value as i16).abs();
+
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const WIDTH: usize = 5;
+    const HEIGHT: usize = 5;
+
+    fn pixels() -> Vec<u8> {
+        (0..WIDTH * HEIGHT * 3)
+            .map(|idx| (idx * 37 % 251) as u8)
+            .collect()
+    }
+
+    // A PNG of `rgb` whose row y uses filter type `filters[y]`, the way
+    // other encoders write them
+    fn filtered_png(rgb: &[u8], filters: &[u8]) -> Vec<u8> {
+        let stride = WIDTH * 3;
+        let at = |x: usize, y: usize| rgb[y * stride + x];
+
+        let mut raw = vec![];
+        for (y, &filter) in filters.iter().enumerate() {
+            raw.push(filter);
+            for x in 0..stride {
+                let left = if x >= 3 { at(x - 3, y) } else { 0 };
+                let up = if y > 0 { at(x, y - 1) } else { 0 };
+                let up_left = if x >= 3 && y > 0 { at(x - 3, y - 1) } else { 0 };
+
+                let predicted = match filter {
+                    0 => 0,
+                    1 => left,
+                    2 => up,
+                    3 => ((left as u16 + up as u16) / 2) as u8,
+                    _ => paeth(left, up, up_left),
+                };
+                raw.push(at(x, y).wrapping_sub(predicted));
+            }
+        }
+
+        let mut header = vec![];
+        header.extend_from_slice(&(WIDTH as u32).to_be_bytes());
+        header.extend_from_slice(&(HEIGHT as u32).to_be_bytes());
+        header.extend_from_slice(&[BIT_DEPTH, COLOUR_TYPE_RGB, 0, 0, 0]);
+
+        let mut png = SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &header).unwrap();
+        write_chunk(
+            &mut png,
+            b"IDAT",
+            &compress_to_vec_zlib(&raw, COMPRESSION_LEVEL),
+        )
+        .unwrap();
+        write_chunk(&mut png, b"IEND", &[]).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_write_read_round_trip() {
+        let rgb = pixels();
+        let mut png = vec![];
+        write_png(&mut png, WIDTH, HEIGHT, &rgb).unwrap();
+
+        assert_eq!(read_png(&png).unwrap(), (WIDTH, HEIGHT, rgb));
+    }
+
+    #[test]
+    fn test_read_filtered_rows() {
+        let rgb = pixels();
+        // Row 0 has nothing above it, so up and Paeth lean on zeros there
+        for filters in [[1, 2, 3, 4, 0], [4, 3, 2, 1, 4], [2, 4, 1, 3, 3]] {
+            let png = filtered_png(&rgb, &filters);
+            assert_eq!(
+                read_png(&png).unwrap(),
+                (WIDTH, HEIGHT, rgb.clone()),
+                "{filters:?}"
+            );
+        }
+
+        let png = filtered_png(&rgb, &[0, 5, 0, 0, 0]);
+        assert_eq!(read_png(&png).unwrap_err(), "unknown filter type 5");
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use crate::{
+    cartridge::hash::crc32,
+    cpu::CPU,
+    joypad::JoypadButton,
+    ppu::frame::{Frame, HEIGHT, WIDTH},
+};
+
+use super::{
+    png::{read_png, write_png},
+    Outcome,
+};
+
+// Buttons held on the first controller, frame by frame. The text form has
+// one change per line:
+//
+//   frame  buttons
+//
+// where `buttons` are a, b, select, start, up, down, left and right,
+// separated by spaces, held from that frame until the next line; - holds
+// none. Frames count from 0 when the run starts. Blank lines and lines
+// starting with # are skipped.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputScript {
+    changes: Vec<(usize, JoypadButton)>,
+}
+
+impl InputScript {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut script = InputScript::default();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let frame = fields.next().unwrap_or_default();
+            let frame: usize = frame
+                .parse()
+                .map_err(|_| format!("line {}: bad frame number {frame}", idx + 1))?;
+
+            let mut buttons = JoypadButton::empty();
+            for name in fields.filter(|&name| name != "-") {
+                buttons |= JoypadButton::from_name(&name.to_ascii_uppercase())
+                    .ok_or_else(|| format!("line {}: unknown button {name}", idx + 1))?;
+            }
+
+            if script
+                .changes
+                .last()
+                .is_some_and(|&(last, _)| last >= frame)
+            {
+                return Err(format!("line {}: frames must go up", idx + 1));
+            }
+            script.changes.push((frame, buttons));
+        }
+
+        Ok(script)
+    }
+
+    pub fn buttons_at(&self, frame: usize) -> JoypadButton {
+        self.changes
+            .iter()
+            .take_while(|&&(start, _)| start <= frame)
+            .last()
+            .map_or(JoypadButton::empty(), |&(_, buttons)| buttons)
+    }
+}
+
+// Runs the CPU for `frames` frames, feeding controller 1 from `input`, and
+// returns the last picture. Stops early if the CPU halts.
+pub fn run_frames(cpu: &mut CPU, frames: usize, input: &InputScript) -> Frame {
+    let first = cpu.bus.ppu().frame_count();
+
+    loop {
+        let frame = cpu.bus.ppu().frame_count() - first;
+        if frame >= frames {
+            break;
+        }
+
+        cpu.bus.joypad(0).buttons = input.buttons_at(frame);
+        if !cpu.step() {
+            break;
+        }
+    }
+
+    cpu.bus.ppu().frame.clone()
+}
+
+pub fn frame_hash(frame: &Frame) -> u32 {
+    crc32(&frame.data)
+}
+
+pub fn save_frame<P: AsRef<Path>>(frame: &Frame, path: P) -> io::Result<()> {
+    write_png(
+        &mut BufWriter::new(File::create(path)?),
+        WIDTH,
+        HEIGHT,
+        &frame.data,
+    )
+}
+
+pub fn load_frame<P: AsRef<Path>>(path: P) -> io::Result<Frame> {
+    let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let (width, height, data) = read_png(&fs::read(path)?).map_err(invalid)?;
+    if (width, height) != (WIDTH, HEIGHT) {
+        return Err(invalid(format!(
+            "picture is {width}x{height}, not {WIDTH}x{HEIGHT}"
+        )));
+    }
+
+    Ok(Frame { data })
+}
+
+// Compares `frame` with the golden PNG at `golden`. When they differ,
+// actual.png, expected.png and diff.png are written to `out_dir`, the diff
+// showing the differing pixels in red over a faded copy of the golden.
+// With `update`, a missing or different golden is replaced by `frame`.
+pub fn check_golden(
+    frame: &Frame,
+    golden: &Path,
+    out_dir: &Path,
+    update: bool,
+) -> io::Result<Outcome> {
+    if update && !golden.exists() {
+        save_frame(frame, golden)?;
+        return Ok(Outcome::Passed);
+    }
+
+    let expected = load_frame(golden)?;
+    let mut diff = Frame::new();
+    let mut differing = 0;
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let (r, g, b) = expected.pixel(x, y);
+            if frame.pixel(x, y) == (r, g, b) {
+                let faded = ((r as u16 + g as u16 + b as u16) / 6 + 0x80) as u8;
+                diff.set_pixel(x, y, (faded, faded, faded));
+            } else {
+                differing += 1;
+                diff.set_pixel(x, y, (0xFF, 0x00, 0x00));
+            }
+        }
+    }
+
+    if differing == 0 {
+        return Ok(Outcome::Passed);
+    }
+    if update {
+        save_frame(frame, golden)?;
+        return Ok(Outcome::Passed);
+    }
+
+    fs::create_dir_all(out_dir)?;
+    save_frame(frame, out_dir.join("actual.png"))?;
+    save_frame(&expected, out_dir.join("expected.png"))?;
+    save_frame(&diff, out_dir.join("diff.png"))?;
+
+    Ok(Outcome::ScreenDiffers(differing))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testrom::{store, test_cpu};
+
+    use super::*;
+
+    // Shows a white 8x8 sprite at ($40, $40) that moves right one pixel
+    // every frame A is held on controller 1
+    fn sprite_rom() -> CPU {
+        let mut program = vec![];
+        // Black backdrop, white for colour 1 of sprite palette 0
+        for (addr, colour) in [(0x00, 0x0F), (0x11, 0x30)] {
+            store(&mut program, 0x3F, 0x2006);
+            store(&mut program, addr, 0x2006);
+            store(&mut program, colour, 0x2007);
+        }
+        // Sprite 0: tile 1 at ($40, $40), its X kept in $00 too
+        store(&mut program, 0x00, 0x2003);
+        for byte in [0x3F, 0x01, 0x00, 0x40] {
+            store(&mut program, byte, 0x2004);
+        }
+        store(&mut program, 0x40, 0x0000);
+        store(&mut program, 0x10, 0x2001);
+
+        // wait: BIT $2002; BPL wait
+        let wait = program.len();
+        program.extend_from_slice(&[0x2C, 0x02, 0x20, 0x10, 0xFB]);
+        // Latch the buttons; LDA $4016; AND #1; BEQ wait
+        store(&mut program, 0x01, 0x4016);
+        store(&mut program, 0x00, 0x4016);
+        program.extend_from_slice(&[0xAD, 0x16, 0x40, 0x29, 0x01, 0xF0]);
+        program.push((wait as isize - program.len() as isize - 1) as u8);
+        // INC $00; LDA #3; STA $2003; LDA $00; STA $2004; JMP wait
+        program.extend_from_slice(&[0xE6, 0x00]);
+        store(&mut program, 0x03, 0x2003);
+        program.extend_from_slice(&[0xA5, 0x00, 0x8D, 0x04, 0x20]);
+        let [lo, hi] = (0x8000 + wait as u16).to_le_bytes();
+        program.extend_from_slice(&[0x4C, lo, hi]);
+
+        // Tile 0 is blank, tile 1 solid colour 1
+        let mut chr = vec![0; 0x20];
+        chr[0x10..0x18].fill(0xFF);
+
+        test_cpu(&program, &chr)
+    }
+
+    #[test]
+    fn test_input_script() {
+        let script = InputScript::parse(
+            "
+            # frame buttons
+            0   -
+            10  a right
+            25  START
+            ",
+        )
+        .unwrap();
+        assert_eq!(script.buttons_at(0), JoypadButton::empty());
+        assert_eq!(script.buttons_at(9), JoypadButton::empty());
+        assert_eq!(script.buttons_at(10), JoypadButton::A | JoypadButton::RIGHT);
+        assert_eq!(script.buttons_at(24), JoypadButton::A | JoypadButton::RIGHT);
+        assert_eq!(script.buttons_at(1000), JoypadButton::START);
+
+        for (text, message) in [
+            ("x a", "line 1: bad frame number x"),
+            ("0 jump", "line 1: unknown button jump"),
+            ("5 a\n5 b", "line 2: frames must go up"),
+        ] {
+            assert_eq!(InputScript::parse(text).unwrap_err(), message);
+        }
+    }
+
+    #[test]
+    fn test_run_frames() {
+        let input = InputScript::parse("2 a\n6 -").unwrap();
+        let frame = run_frames(&mut sprite_rom(), 10, &input);
+
+        let white = (0xFF, 0xFF, 0xFF);
+        let backdrop = frame.pixel(0, 0);
+        assert_ne!(backdrop, white);
+        // Held for frames 2 to 5, so moved four pixels
+        assert_eq!(frame.pixel(0x44, 0x40), white);
+        assert_eq!(frame.pixel(0x4B, 0x47), white);
+        assert_eq!(frame.pixel(0x43, 0x40), backdrop);
+        assert_eq!(frame.pixel(0x4C, 0x40), backdrop);
+        assert_eq!(frame.pixel(0x44, 0x48), backdrop);
+
+        let again = run_frames(&mut sprite_rom(), 10, &input);
+        assert_eq!(frame_hash(&frame), frame_hash(&again));
+        let still = run_frames(&mut sprite_rom(), 10, &InputScript::default());
+        assert_ne!(frame_hash(&frame), frame_hash(&still));
+    }
+
+    #[test]
+    fn test_check_golden() {
+        let dir = std::env::temp_dir().join(format!("nes-golden-{}", std::process::id()));
+        let golden = dir.join("golden.png");
+        let out_dir = dir.join("out");
+        fs::create_dir_all(&dir).unwrap();
+
+        let still = run_frames(&mut sprite_rom(), 4, &InputScript::default());
+        let moved = run_frames(&mut sprite_rom(), 4, &InputScript::parse("0 a").unwrap());
+
+        // Update writes a missing golden, which then reads back the same
+        assert_eq!(
+            check_golden(&still, &golden, &out_dir, true).unwrap(),
+            Outcome::Passed
+        );
+        assert_eq!(load_frame(&golden).unwrap().data, still.data);
+        assert_eq!(
+            check_golden(&still, &golden, &out_dir, false).unwrap(),
+            Outcome::Passed
+        );
+        assert!(!out_dir.exists());
+
+        // The sprite moved a few pixels, so only its edges differ
+        let outcome = check_golden(&moved, &golden, &out_dir, false).unwrap();
+        assert!(matches!(outcome, Outcome::ScreenDiffers(pixels) if pixels > 0 && pixels <= 64));
+        assert_eq!(
+            load_frame(out_dir.join("actual.png")).unwrap().data,
+            moved.data
+        );
+        assert_eq!(
+            load_frame(out_dir.join("expected.png")).unwrap().data,
+            still.data
+        );
+        assert!(out_dir.join("diff.png").exists());
+
+        assert_eq!(
+            check_golden(&moved, &golden, &out_dir, true).unwrap(),
+            Outcome::Passed
+        );
+        assert_eq!(load_frame(&golden).unwrap().data, moved.data);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+use std::{path::Path, time::Duration};
+
+use crate::{
+    bus::bus::NTSC_CPU_CLOCK,
+    cartridge::RomError,
+    cpu::{cpu::Mem, CPU},
+};
+
+use super::{Outcome, TestReport};
+
+// blargg's protocol: a result byte at $6000, valid once $6001-$6003 hold the
+// signature, and a NUL-terminated message from $6004 on
+const STATUS: u16 = 0x6000;
+const SIGNATURE: u16 = 0x6001;
+const SIGNATURE_BYTES: [u8; 3] = [0xDE, 0xB0, 0x61];
+const MESSAGE: u16 = 0x6004;
+const MESSAGE_END: u16 = 0x7FFF;
+
+const RUNNING: u8 = 0x80;
+// The test wants the reset button pressed, no sooner than 100ms from now
+const NEEDS_RESET: u8 = 0x81;
+const RESET_DELAY: usize = NTSC_CPU_CLOCK / 10;
+
+// Loads a ROM, powers it on and runs it with `run_status_rom`.
+pub fn run_status_rom_file<P: AsRef<Path>>(
+    path: P,
+    timeout: Duration,
+) -> Result<TestReport, RomError> {
+    let mut cpu = CPU::load_rom_file_without_save(path)?;
+    cpu.reset();
+
+    Ok(run_status_rom(&mut cpu, timeout))
+}
+
+// Runs a test ROM reporting through $6000 until its status leaves $80, or
+// for `timeout` of emulated time. Resets asked for with $81 are done.
+pub fn run_status_rom(cpu: &mut CPU, timeout: Duration) -> TestReport {
+    let start = cpu.bus.cycles();
+    let deadline = start + (timeout.as_secs_f64() * NTSC_CPU_CLOCK as f64) as usize;
+
+    let mut started = false;
+    let mut reset_at = None;
+    let mut reset_done = false;
+
+    let outcome = loop {
+        if cpu.bus.cycles() >= deadline {
+            break Outcome::TimedOut;
+        }
+        if !cpu.step() {
+            break Outcome::Halted(cpu.program_counter);
+        }
+
+        if !has_signature(cpu) {
+            continue;
+        }
+
+        match cpu.bus.peek(STATUS) {
+            RUNNING => {
+                started = true;
+                reset_done = false;
+            }
+            NEEDS_RESET => {
+                started = true;
+                if reset_done {
+                    continue;
+                }
+
+                let at = *reset_at.get_or_insert(cpu.bus.cycles() + RESET_DELAY);
+                if cpu.bus.cycles() >= at {
+                    cpu.reset();
+                    reset_at = None;
+                    reset_done = true;
+                }
+            }
+            0 if started => break Outcome::Passed,
+            code if started => break Outcome::Failed(code),
+            _ => (),
+        }
+    };
+
+    TestReport {
+        outcome,
+        message: read_message(cpu),
+        cycles: cpu.bus.cycles() - start,
+    }
+}
+
+fn has_signature(cpu: &mut CPU) -> bool {
+    (0..3).all(|idx| cpu.bus.peek(SIGNATURE + idx) == SIGNATURE_BYTES[idx as usize])
+}
+
+// The message is printable ASCII and newlines; empty without the signature
+fn read_message(cpu: &mut CPU) -> String {
+    if !has_signature(cpu) {
+        return String::new();
+    }
+
+    let mut message = String::new();
+    for addr in MESSAGE..=MESSAGE_END {
+        match cpu.bus.peek(addr) {
+            0 => break,
+            byte => message.push(byte as char),
+        }
+    }
+
+    message.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testrom::{store, test_cpu};
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    // Marks the test as running and writes the signature
+    fn start(program: &mut Vec<u8>) {
+        store(program, RUNNING, STATUS);
+        for (idx, byte) in SIGNATURE_BYTES.into_iter().enumerate() {
+            store(program, byte, SIGNATURE + idx as u16);
+        }
+    }
+
+    // JMP to itself
+    fn spin(program: &mut Vec<u8>) {
+        let [lo, hi] = (0x8000 + program.len() as u16).to_le_bytes();
+        program.extend_from_slice(&[0x4C, lo, hi]);
+    }
+
+    fn finish(message: &[u8], status: u8) -> CPU {
+        let mut program = vec![];
+        start(&mut program);
+        for (idx, &byte) in message.iter().chain(&[0]).enumerate() {
+            store(&mut program, byte, MESSAGE + idx as u16);
+        }
+        store(&mut program, status, STATUS);
+        spin(&mut program);
+
+        test_cpu(&program, &[])
+    }
+
+    #[test]
+    fn test_passed() {
+        let report = run_status_rom(&mut finish(b"\nPassed\n", 0), TIMEOUT);
+        assert_eq!(report.outcome, Outcome::Passed);
+        assert_eq!(report.message, "\nPassed");
+    }
+
+    #[test]
+    fn test_failed() {
+        let report = run_status_rom(&mut finish(b"Failed #3", 3), TIMEOUT);
+        assert_eq!(report.outcome, Outcome::Failed(3));
+        assert_eq!(report.message, "Failed #3");
+    }
+
+    #[test]
+    fn test_timed_out() {
+        let mut program = vec![];
+        start(&mut program);
+        spin(&mut program);
+
+        let report = run_status_rom(&mut test_cpu(&program, &[]), Duration::from_millis(50));
+        assert_eq!(report.outcome, Outcome::TimedOut);
+        assert!(report.cycles >= NTSC_CPU_CLOCK / 20);
+    }
+
+    #[test]
+    fn test_status_ignored_without_signature() {
+        let mut program = vec![];
+        store(&mut program, 0x01, STATUS);
+        spin(&mut program);
+
+        let report = run_status_rom(&mut test_cpu(&program, &[]), Duration::from_millis(50));
+        assert_eq!(report.outcome, Outcome::TimedOut);
+        assert_eq!(report.message, "");
+    }
+
+    #[test]
+    fn test_reset_request() {
+        // LDA $10; BNE after_reset; INC $10
+        let mut program = vec![0xA5, 0x10, 0xD0, 0x00, 0xE6, 0x10];
+        start(&mut program);
+        store(&mut program, 0x81, STATUS);
+        spin(&mut program);
+
+        program[3] = (program.len() - 4) as u8;
+        store(&mut program, 0x00, STATUS);
+        spin(&mut program);
+
+        let report = run_status_rom(&mut test_cpu(&program, &[]), TIMEOUT);
+        assert_eq!(report.outcome, Outcome::Passed);
+        assert!(report.cycles >= NTSC_CPU_CLOCK / 10);
+    }
+
+    #[test]
+    fn test_halted() {
+        let report = run_status_rom(&mut test_cpu(&[0x02], &[]), TIMEOUT);
+        assert_eq!(report.outcome, Outcome::Halted(0x8000));
+    }
+}
@@ -0,0 +1,37 @@
+use std::{fs, time::Duration};
+
+use nes_emulator::{cpu::CPU, testrom::run_log_rom};
+
+const ROM: &str = "roms/nestest.nes";
+const LOG: &str = "roms/nestest.log";
+
+// The whole log is about 15ms of CPU time
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+// Runs nestest against Nintendulator's log, from $C000 where its automated
+// mode starts, and fails with the first line that differs.
+fn check_nestest(cycle_stepped: bool) {
+    let log = fs::read_to_string(LOG).expect("nestest.log");
+
+    let mut cpu = CPU::load_rom_file(ROM).expect("nestest.nes");
+    cpu.cycle_stepped = cycle_stepped;
+    cpu.reset();
+
+    let report = run_log_rom(&mut cpu, &log, TIMEOUT);
+    assert!(
+        report.outcome.passed(),
+        "nestest {}\n{}",
+        report.outcome,
+        report.message
+    );
+}
+
+#[test]
+fn nestest_matches_log() {
+    check_nestest(false);
+}
+
+#[test]
+fn nestest_matches_log_cycle_stepped() {
+    check_nestest(true);
+}